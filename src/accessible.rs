@@ -0,0 +1,134 @@
+//! A mode for screen readers, which prints every menu once, with numbered entries, and reads
+//! selections line by line, instead of redrawing the output in place
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use anyhow::{anyhow, Result};
+use console::Term;
+
+use crate::core;
+use crate::dynmenu;
+use crate::exec;
+use crate::i18n::tr;
+use crate::parser::{Node, SnippetTable};
+use crate::rt_conf;
+
+/// Returns the exit code of the command that ended the session, or 0
+pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> Result<i32> {
+    let arg_vals = if input.len() > 1 { &input[1..] } else { &[] };
+    let presets = &rt_conf::cli_options().var_presets;
+    let term = Term::stdout();
+    core::ignore_ctrlc()?;
+
+    // a copy, that gets the entries of the dynamic menus that are entered
+    let mut tree = root_node.clone();
+    let mut dynamic = dynmenu::Expander::default();
+    // the keys of the entries that lead to the current menu
+    let mut path: Vec<Vec<char>> = vec![];
+    if let Some(keys) = input.first() {
+        let keys: Vec<char> = keys.chars().collect();
+        dynamic.expand(&mut tree, &keys, snippet_table)?;
+        match exec::follow_path(&tree, &keys, 0) {
+            (Some(Node::Command(cmd)), _) => {
+                let keys = String::from_iter(&keys);
+                let code =
+                    core::run_command(cmd, &term, arg_vals, snippet_table, &keys, presets, None)?;
+                return Ok(code.unwrap_or(0));
+            }
+            (Some(Node::Menu(_)), pos) if pos == keys.len() => path.push(keys),
+            _ => {}
+        }
+    }
+
+    loop {
+        let menu_keys = path.concat();
+        dynamic.expand(&mut tree, &menu_keys, snippet_table)?;
+        let Some(Node::Menu(menu)) = exec::follow_path(&tree, &menu_keys, 0).0 else {
+            unreachable!("the path always leads to a menu");
+        };
+        let entries: Vec<_> = menu.entries.iter().collect();
+        let name = menu.display_name.as_ref().unwrap_or(&menu.name);
+        println!("{}", tr("menu_heading", &[("name", name)]));
+        if let Some(header) = &menu.header {
+            println!("{header}");
+        }
+        for (i, (keys, node)) in entries.iter().enumerate() {
+            println!("{}. {node} ({})", i + 1, String::from_iter(*keys));
+        }
+        if let Some(footer) = &menu.footer {
+            println!("{footer}");
+        }
+        println!("{}", tr("menu_help", &[]));
+        let Some(line) = read_line()? else {
+            return Ok(0);
+        };
+        if line.is_empty() {
+            if path.pop().is_none() {
+                return Ok(0);
+            }
+            continue;
+        }
+
+        // keys take precedence, as they could be numbers, too
+        let selected = entries
+            .iter()
+            .find(|(keys, _)| String::from_iter(*keys) == line)
+            .or_else(|| {
+                let n: usize = line.parse().ok()?;
+                entries.get(n.checked_sub(1)?)
+            });
+        match selected {
+            Some((keys, Node::Menu(_))) => path.push(keys.to_vec()),
+            Some((keys, Node::Command(cmd))) => {
+                let keys = String::from_iter(menu_keys.iter().chain(keys.iter()));
+                if let Some(code) =
+                    core::run_command(cmd, &term, arg_vals, snippet_table, &keys, presets, None)?
+                {
+                    return Ok(code);
+                }
+            }
+            None => println!("{}", tr("invalid_selection", &[("input", &line)])),
+        }
+    }
+}
+
+/// Prints the options numbered, and reads the numbers of the selected ones, which are
+/// separated by spaces or commas. Without `multi`, exactly one has to be selected
+pub fn pick(prompt: &str, options: &[impl Display], multi: bool) -> Result<Vec<usize>> {
+    println!("{prompt}");
+    for (i, option) in options.iter().enumerate() {
+        println!("{}. {option}", i + 1);
+    }
+    loop {
+        let help = if multi {
+            "pick_help_multi"
+        } else {
+            "pick_help"
+        };
+        println!("{}", tr(help, &[]));
+        let line = read_line()?.ok_or(anyhow!(tr("no_selection", &[])))?;
+        let selection: Option<Vec<usize>> = line
+            .split([' ', ','])
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                let n: usize = n.parse().ok()?;
+                (1..=options.len()).contains(&n).then_some(n - 1)
+            })
+            .collect();
+        match selection {
+            Some(selection) if multi || selection.len() == 1 => return Ok(selection),
+            _ => println!("{}", tr("invalid_selection", &[("input", &line)])),
+        }
+    }
+}
+
+/// Reads a line from stdin, without the line break. None at the end of the input
+fn read_line() -> Result<Option<String>> {
+    print!("> ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
@@ -0,0 +1,177 @@
+//! The actions a command can run instead of a shell: opening a URL, copying text to the
+//! clipboard, printing text and changing the directory. They behave the same on every
+//! platform, and don't depend on the quoting rules of a shell
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::exec::expand_home;
+use crate::i18n::tr;
+use crate::parser::Action;
+
+/// The wrapper function `dt shell-init` prints for bash and zsh
+pub const POSIX_SHELL_INIT: &str = r#"dt() {
+	local dt_cd_file dt_status
+	dt_cd_file=$(mktemp) || return
+	DT_CD_FILE="$dt_cd_file" command dt "$@"
+	dt_status=$?
+	if [ -s "$dt_cd_file" ]; then
+		cd -- "$(cat "$dt_cd_file")" || dt_status=$?
+	fi
+	rm -f -- "$dt_cd_file"
+	return $dt_status
+}
+"#;
+
+/// The wrapper function `dt shell-init` prints for fish
+pub const FISH_SHELL_INIT: &str = r#"function dt --wraps dt
+	set -l dt_cd_file (mktemp); or return
+	DT_CD_FILE=$dt_cd_file command dt $argv
+	set -l dt_status $status
+	if test -s $dt_cd_file
+		cd (cat $dt_cd_file); or set dt_status $status
+	end
+	rm -f $dt_cd_file
+	return $dt_status
+end
+"#;
+
+/// Runs the action with the resolved string of the command as argument
+pub fn run(action: Action, arg: &str) -> Result<()> {
+    match action {
+        Action::OpenUrl => open_url(arg),
+        Action::Copy => copy(arg),
+        Action::Print => {
+            println!("{arg}");
+            Ok(())
+        }
+        Action::Cd => cd(arg),
+    }
+}
+
+/// A process can't change the directory of its parent, so the directory is written to the
+/// file in $DT_CD_FILE instead, and the wrapper function of `dt shell-init` changes to it,
+/// after dt exited. Relative paths start at the directory commands are run in
+fn cd(dir: &str) -> Result<()> {
+    let Some(cd_file) = env::var_os("DT_CD_FILE") else {
+        bail!(tr("cd_needs_shell_init", &[]));
+    };
+    let dir = env::current_dir()
+        .context("getting cwd")?
+        .join(expand_home(dir));
+    ensure!(
+        dir.is_dir(),
+        tr("not_a_directory", &[("dir", &dir.display())])
+    );
+    fs::write(&cd_file, dir.to_string_lossy().as_bytes()).context("writing $DT_CD_FILE")
+}
+
+fn open_url(url: &str) -> Result<()> {
+    let (prog, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("rundll32", &["url.dll,FileProtocolHandler"])
+    } else {
+        ("xdg-open", &[])
+    };
+    let status = Command::new(prog)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .status()
+        .context(format!("running {prog}"))?;
+    if !status.success() {
+        bail!(tr("open_url_failed", &[("url", &url)]));
+    }
+    Ok(())
+}
+
+/// The programs that write their stdin to the clipboard, in the order they are tried
+fn clipboard_programs() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", &[])]
+    } else if cfg!(windows) {
+        vec![("clip.exe", &[])]
+    } else {
+        let mut progs: Vec<(_, &[_])> = vec![];
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            progs.push(("wl-copy", &[]));
+        }
+        progs.push(("xclip", &["-selection", "clipboard"]));
+        progs.push(("xsel", &["--clipboard", "--input"]));
+        progs
+    }
+}
+
+/// Copies the text with the first clipboard program that is installed. If there is none, the
+/// terminal is asked to do it with an OSC 52 sequence, which also works over ssh
+fn copy(text: &str) -> Result<()> {
+    for (prog, args) in clipboard_programs() {
+        let child = Command::new(prog)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).context(format!("running {prog}")),
+        };
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(text.as_bytes())
+            .context(format!("writing to {prog}"))?;
+        if !child.wait()?.success() {
+            bail!(tr("copy_failed", &[("prog", &prog)]));
+        }
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    if !stdout.is_terminal() {
+        bail!(tr("no_clipboard", &[]));
+    }
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"hello world"), "aGVsbG8gd29ybGQ=");
+    }
+}
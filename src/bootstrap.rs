@@ -0,0 +1,309 @@
+//! The configs that `dt init` writes: a starter config with examples, or one that is generated
+//! from the commands in the shell history, with `--from-history`
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use hashbrown::HashMap;
+
+/// Commands that are used too often to be worth a key
+const TRIVIAL: &[&str] = &[
+    "cd", "ls", "ll", "la", "l", "pwd", "clear", "exit", "history", "dt", "man", "which",
+];
+/// Commands that were run less often than this aren't proposed
+const MIN_COUNT: usize = 2;
+
+/// The config `dt init` writes without `--from-history`. It shows a snippet, submenus and a
+/// command with variables, and explains them in comments
+pub const STARTER: &str = r#"# Your dotree config. Run `dt` and type the keys of an entry to run it, see
+# https://github.com/KnorrFG/dotree for everything that can go in here.
+
+# Snippets hold code that several commands share. Use them with $name
+snippet today = "date +%Y-%m-%d"
+
+# The root menu is the one you see first
+menu root {
+	# a command: the keys, optionally a name and a -, and the code that runs in the shell
+	h: "say hello" - "echo hello from dotree"
+	d: "show the date" - "echo today is $(" + $today + ")"
+	# a submenu, defined below
+	g: git
+}
+
+menu git {
+	s: "git status"
+	l: "log" - "git log --oneline -n 20"
+	# commands with cmd can have variables, which are asked for before the command runs, and
+	# are passed as environment variables. The values you enter are kept in a history
+	c: cmd {
+		vars branch, base = "main"
+		"new branch" - "git switch -c $branch $base"
+	}
+}
+"#;
+
+/// A command from the history, and how often it was run
+#[derive(Debug, PartialEq)]
+pub struct Candidate {
+    pub command: String,
+    pub count: usize,
+}
+
+/// The history files of bash, zsh and fish, that exist
+pub fn history_files() -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(path) = std::env::var_os("HISTFILE") {
+        paths.push(PathBuf::from(path));
+    }
+    if let Some(home) = dirs::home_dir() {
+        paths.push(home.join(".bash_history"));
+        let zdotdir = std::env::var_os("ZDOTDIR").map_or(home.clone(), PathBuf::from);
+        paths.push(zdotdir.join(".zsh_history"));
+    }
+    if let Some(data_dir) = dirs::data_dir() {
+        paths.push(data_dir.join("fish").join("fish_history"));
+    }
+    let mut seen = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|p| p.is_file() && seen.insert(p.clone()))
+        .collect()
+}
+
+pub fn read_history(path: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(path).context(format!("reading {}", path.display()))?;
+    Ok(parse_history(&String::from_utf8_lossy(&bytes)))
+}
+
+/// The commands of a history file, in the order they were run. Understands the formats of
+/// bash (also with timestamps), zsh (also extended) and fish. Multi-line commands are dropped
+pub fn parse_history(src: &str) -> Vec<String> {
+    let is_fish = src.lines().any(|l| l.starts_with("- cmd: "));
+    let mut commands = vec![];
+    let mut continued: Option<String> = None;
+    for line in src.lines() {
+        let command = if is_fish {
+            let Some(cmd) = line.strip_prefix("- cmd: ") else {
+                continue;
+            };
+            unescape_fish(cmd)
+        } else if let Some(start) = continued.take() {
+            format!("{start}\n{line}")
+        } else if is_bash_timestamp(line) {
+            continue;
+        } else {
+            strip_zsh_prefix(line).to_string()
+        };
+        if !is_fish && command.ends_with('\\') {
+            continued = Some(command);
+        } else if !command.contains('\n') && !command.trim().is_empty() {
+            commands.push(command.trim().to_string());
+        }
+    }
+    commands
+}
+
+/// Fish escapes backslashes and newlines in its history
+fn unescape_fish(cmd: &str) -> String {
+    let mut res = String::new();
+    let mut chars = cmd.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                res.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                res.push('\n');
+            }
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn is_bash_timestamp(line: &str) -> bool {
+    line.strip_prefix('#')
+        .is_some_and(|ts| !ts.is_empty() && ts.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Removes the `: <timestamp>:<duration>;` of zsh's extended history format
+fn strip_zsh_prefix(line: &str) -> &str {
+    let Some((meta, command)) = line.split_once(';') else {
+        return line;
+    };
+    match meta.strip_prefix(": ") {
+        Some(meta) if meta.chars().all(|c| c.is_ascii_digit() || c == ':') => command,
+        _ => line,
+    }
+}
+
+/// The most frequent commands, that are not trivial, ordered by their count
+pub fn candidates(commands: &[String], max: usize) -> Vec<Candidate> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for command in commands {
+        *counts.entry(command.as_str()).or_default() += 1;
+    }
+    let mut candidates: Vec<_> = counts
+        .into_iter()
+        .filter(|(command, count)| *count >= MIN_COUNT && !TRIVIAL.contains(&program(command)))
+        .map(|(command, count)| Candidate {
+            command: command.to_string(),
+            count,
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.count.cmp(&a.count).then(a.command.cmp(&b.command)));
+    candidates.truncate(max);
+    candidates
+}
+
+fn program(command: &str) -> &str {
+    command.split_whitespace().next().unwrap_or("")
+}
+
+/// The source of a config with the commands. Commands of programs that occur more than once
+/// are grouped into a menu named after the program. The keys are taken from the letters of
+/// the words, and entries that don't get a key anymore are left out
+pub fn generate(commands: &[String]) -> String {
+    let mut programs: Vec<(&str, Vec<&str>)> = vec![];
+    for command in commands {
+        let prog = program(command);
+        match programs.iter_mut().find(|(p, _)| *p == prog) {
+            Some((_, cmds)) => cmds.push(command),
+            None => programs.push((prog, vec![command])),
+        }
+    }
+    let mut menu_names = HashSet::from(["root".to_string()]);
+    let mut root_keys = HashSet::new();
+    let mut root_lines = vec![];
+    let mut submenus = vec![];
+    for (prog, cmds) in programs {
+        if let [command] = cmds[..] {
+            let words: Vec<_> = command.split_whitespace().collect();
+            if let Some(key) = pick_key(&words, &mut root_keys) {
+                root_lines.push(format!("\t{key}: {}", quote(command)));
+            }
+            continue;
+        }
+        let Some(key) = pick_key(&[prog], &mut root_keys) else {
+            continue;
+        };
+        let name = menu_name(prog, &mut menu_names);
+        root_lines.push(format!("\t{key}: {name}"));
+        let mut keys = HashSet::new();
+        let lines: Vec<_> = cmds
+            .iter()
+            .filter_map(|command| {
+                let words: Vec<_> = command.split_whitespace().skip(1).collect();
+                let key = pick_key(&words, &mut keys)?;
+                Some(format!("\t{key}: {}", quote(command)))
+            })
+            .collect();
+        submenus.push((name, lines));
+    }
+    let mut src = String::from("# generated by dt init --from-history\n");
+    src += &format!("menu root {{\n{}\n}}\n", root_lines.join("\n"));
+    for (name, lines) in submenus {
+        src += &format!("\nmenu {name} {{\n{}\n}}\n", lines.join("\n"));
+    }
+    src
+}
+
+/// The first unused key out of the initials of the words, the rest of their letters, and
+/// finally any letter or digit
+fn pick_key(words: &[&str], used: &mut HashSet<char>) -> Option<char> {
+    let initials = words.iter().filter_map(|w| w.chars().next());
+    let rest = words.iter().flat_map(|w| w.chars());
+    let key = initials
+        .chain(rest)
+        .map(|c| c.to_ascii_lowercase())
+        .chain(('a'..='z').chain('0'..='9'))
+        .find(|c| c.is_ascii_alphanumeric() && !used.contains(c))?;
+    used.insert(key);
+    Some(key)
+}
+
+fn menu_name(prog: &str, used: &mut HashSet<String>) -> String {
+    let base: String = prog
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let mut name = base.clone();
+    let mut i = 2;
+    while used.contains(&name) {
+        name = format!("{base}{i}");
+        i += 1;
+    }
+    used.insert(name.clone());
+    name
+}
+
+/// Quotes the command as string literal, using a protected string if it contains quotes or
+/// backslashes
+fn quote(command: &str) -> String {
+    if !command.contains(['"', '\\']) {
+        return format!("\"{command}\"");
+    }
+    let mut marker = String::new();
+    while command.contains(&format!("\"{marker}!")) {
+        marker.push('x');
+    }
+    format!("!{marker}\"{command}\"{marker}!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn test_starter() {
+        let config = parser::parse(STARTER).unwrap();
+        assert_eq!(config.menu.entries.len(), 3);
+        assert!(config.snippet_table.contains_key("today"));
+        assert!(parser::lint(STARTER).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_history() {
+        let bash = "#1700000000\ngit status\nls\n";
+        assert_eq!(parse_history(bash), vec!["git status", "ls"]);
+        let zsh = ": 1700000000:0;git push\n: 1700000001:0;echo a \\\nb\nmake\n";
+        assert_eq!(parse_history(zsh), vec!["git push", "make"]);
+        let fish = "- cmd: cargo test\n  when: 1700000000\n- cmd: echo \\\\n\n- cmd: a\\nb\n";
+        assert_eq!(parse_history(fish), vec!["cargo test", "echo \\n"]);
+    }
+
+    #[test]
+    fn test_generate() {
+        let history: Vec<String> = [
+            "git status",
+            "git status",
+            "git push",
+            "git push",
+            "git status",
+            "ls",
+            "ls",
+            "make",
+            "echo \"hi\"",
+            "echo \"hi\"",
+            "make",
+        ]
+        .map(String::from)
+        .into();
+        let candidates = candidates(&history, 10);
+        let commands: Vec<_> = candidates.iter().map(|c| c.command.clone()).collect();
+        assert_eq!(commands, ["git status", "echo \"hi\"", "git push", "make"]);
+        let src = generate(&commands);
+        assert_eq!(
+            src,
+            "# generated by dt init --from-history\n\
+             menu root {\n\tg: git\n\te: !\"echo \"hi\"\"!\n\tm: \"make\"\n}\n\n\
+             menu git {\n\ts: \"git status\"\n\tp: \"git push\"\n}\n"
+        );
+        let config = parser::parse(&src).unwrap();
+        assert_eq!(config.menu.entries.len(), 3);
+    }
+}
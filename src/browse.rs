@@ -0,0 +1,125 @@
+//! A mode to explore the tree with the arrow keys, with menus that can be expanded and
+//! collapsed, for when you don't know the keys of a config yet
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+use console::{style, truncate_str, Key, Term};
+
+use crate::i18n::tr;
+use crate::outproxy::OutProxy;
+use crate::parser::{Menu, Node};
+use crate::screen;
+
+/// A visible line of the tree
+struct Row<'a> {
+    /// The keys that lead to the node from the root
+    keys: String,
+    depth: usize,
+    node: &'a Node,
+    /// The index of the row of the menu that contains the node
+    parent: Option<usize>,
+}
+
+/// Shows the tree until a command is selected, and returns its keys. Returns None if the user
+/// leaves the mode without selecting anything
+pub fn browse(root_node: &Node, term: &Term) -> Result<Option<String>> {
+    let Node::Menu(root) = root_node else {
+        bail!("the root node must be a menu");
+    };
+    let mut expanded = HashSet::new();
+    let mut cursor = 0;
+    let mut out_proxy = OutProxy::new();
+    loop {
+        let mut rows = vec![];
+        visible_rows(root, "", 0, None, &expanded, &mut rows);
+        cursor = cursor.min(rows.len() - 1);
+        render(&rows, cursor, term, &mut out_proxy)?;
+        let key = match screen::read_key() {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Key::Escape,
+            Err(e) => return Err(e.into()),
+        };
+        screen::clear_last_lines(out_proxy.n_lines)?;
+        out_proxy.n_lines = 0;
+        let row = &rows[cursor];
+        let is_open = expanded.contains(&row.keys);
+        match (key, row.node) {
+            (Key::ArrowUp, _) => cursor = cursor.saturating_sub(1),
+            (Key::ArrowDown, _) => cursor += 1,
+            // in an expanded menu, the cursor moves to its first entry
+            (Key::ArrowRight, Node::Menu(_)) if is_open => cursor += 1,
+            (Key::ArrowRight | Key::Enter, Node::Menu(_)) if !is_open => {
+                expanded.insert(row.keys.clone());
+            }
+            (Key::Enter | Key::ArrowLeft, _) if is_open => {
+                expanded.remove(&row.keys);
+            }
+            (Key::Enter, Node::Command(_)) => return Ok(Some(row.keys.clone())),
+            (Key::ArrowLeft, _) => cursor = row.parent.unwrap_or(cursor),
+            (Key::Escape | Key::Char('\x14'), _) => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Adds the entries of `menu`, and those of its expanded submenus, in the order of the config
+fn visible_rows<'a>(
+    menu: &'a Menu,
+    prefix: &str,
+    depth: usize,
+    parent: Option<usize>,
+    expanded: &HashSet<String>,
+    rows: &mut Vec<Row<'a>>,
+) {
+    for (keys, node) in &menu.entries {
+        let keys = format!("{prefix}{}", String::from_iter(keys));
+        rows.push(Row {
+            keys: keys.clone(),
+            depth,
+            node,
+            parent,
+        });
+        if let Node::Menu(submenu) = node {
+            if expanded.contains(&keys) {
+                let index = rows.len() - 1;
+                visible_rows(submenu, &keys, depth + 1, Some(index), expanded, rows);
+            }
+        }
+    }
+}
+
+/// Renders the rows around the cursor that fit into the terminal, and a help line
+fn render(rows: &[Row], cursor: usize, term: &Term, out_proxy: &mut OutProxy) -> Result<()> {
+    let (height, width) = term.size();
+    let height = (height as usize).saturating_sub(2).max(1);
+    let start = cursor
+        .saturating_sub(height / 2)
+        .min(rows.len().saturating_sub(height));
+    for (i, row) in rows.iter().enumerate().skip(start).take(height) {
+        let marker = match row.node {
+            Node::Menu(_) if is_expanded(rows, i) => "▾",
+            Node::Menu(_) => "▸",
+            Node::Command(_) => " ",
+        };
+        let line = format!(
+            "{}{marker} {}: {}",
+            "  ".repeat(row.depth),
+            row.keys,
+            row.node
+        );
+        let line = truncate_str(&line, width as usize, "…");
+        if i == cursor {
+            writeln!(out_proxy, "{}", style(line).reverse())?;
+        } else {
+            writeln!(out_proxy, "{line}")?;
+        }
+    }
+    writeln!(out_proxy, "{}", style(tr("browse_hint", &[])).dim())?;
+    Ok(())
+}
+
+/// A menu is expanded, if the next row is one of its children
+fn is_expanded(rows: &[Row], i: usize) -> bool {
+    rows.get(i + 1).is_some_and(|next| next.parent == Some(i))
+}
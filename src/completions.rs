@@ -0,0 +1,188 @@
+//! Completion scripts for bash, zsh and fish. The flags, subcommands and values of flags are
+//! completed by the scripts of clap_complete. A function is added to them that completes the
+//! key paths, by calling `dt __complete` with the config file that `-c` or `-l` select on the
+//! command line that is completed
+use clap::Command;
+use clap_complete::Shell;
+
+/// The command without its hidden arguments and subcommands, which clap_complete would complete
+/// as well. Its bash script also breaks on the `__` of `__complete`
+fn visible(cmd: &Command) -> Command {
+    let mut res = Command::new(cmd.get_name().to_string());
+    if let Some(about) = cmd.get_about() {
+        res = res.about(about.clone());
+    }
+    if let Some(version) = cmd.get_version() {
+        res = res.version(version.to_string());
+    }
+    res.args(cmd.get_arguments().filter(|a| !a.is_hide_set()).cloned())
+        .subcommands(
+            cmd.get_subcommands()
+                .filter(|s| !s.is_hide_set())
+                .map(visible),
+        )
+}
+
+/// The flags of the top level that are followed by a value, which isn't the key path
+fn value_flags(cmd: &Command) -> Vec<String> {
+    cmd.get_arguments()
+        .filter(|a| !a.is_positional() && a.get_action().takes_values())
+        .flat_map(|a| {
+            a.get_long()
+                .map(|l| format!("--{l}"))
+                .into_iter()
+                .chain(a.get_short().map(|s| format!("-{s}")))
+        })
+        .collect()
+}
+
+/// Registers a function that adds the key paths to the completions of the generated `_{name}`,
+/// as long as no positional argument was given
+fn bash_keys(name: &str, value_flags: &[String]) -> String {
+    format!(
+        r#"
+_{name}_keys() {{
+	_{name} "$@"
+	local cur=${{COMP_WORDS[COMP_CWORD]}} word i args=()
+	[[ $cur == -* ]] && return
+	for ((i = 1; i < COMP_CWORD; i++)); do
+		word=${{COMP_WORDS[i]}}
+		case $word in
+			-c|--conf-file) args+=(-c "${{COMP_WORDS[i+1]/#\~/$HOME}}"); ((i++)) ;;
+			-l|--local-mode) args+=(-l) ;;
+			{value_flags}) ((i++)) ;;
+			-*) ;;
+			*) return ;;
+		esac
+	done
+	# the word is the value of a flag
+	((i > COMP_CWORD)) && return
+	COMPREPLY+=($({name} "${{args[@]}}" __complete "$cur" 2>/dev/null | cut -f1))
+}}
+complete -F _{name}_keys -o nosort -o bashdefault -o default {name}
+"#,
+        value_flags = value_flags.join("|"),
+    )
+}
+
+/// Like `bash_keys`, with descriptions
+fn zsh_keys(name: &str, value_flags: &[String]) -> String {
+    format!(
+        r#"
+_{name}_keys() {{
+	local word i keys=1 prefix=$PREFIX
+	local -a args candidates
+	[[ $prefix == -* ]] && keys=0
+	for ((i = 2; i < CURRENT; i++)); do
+		word=${{words[i]}}
+		case $word in
+			-c|--conf-file) args+=(-c "${{words[i+1]/#\~/$HOME}}"); ((i++)) ;;
+			-l|--local-mode) args+=(-l) ;;
+			{value_flags}) ((i++)) ;;
+			-*) ;;
+			*) keys=0 ;;
+		esac
+	done
+	# the word is the value of a flag
+	((i > CURRENT)) && keys=0
+	# _arguments in the generated function can change the words, so they are looked at before
+	_{name} "$@"
+	((keys)) || return
+	candidates=(${{(f)"$({name} $args __complete $prefix 2>/dev/null)"}})
+	candidates=(${{candidates//:/\\:}})
+	candidates=(${{candidates//$'\t'/:}})
+	_describe -t keys key candidates
+}}
+compdef _{name}_keys {name}
+"#,
+        value_flags = value_flags.join("|"),
+    )
+}
+
+/// Adds the key paths to the completions, while no subcommand or key path was given
+fn fish_keys(name: &str) -> String {
+    format!(
+        r#"
+function __{name}_keys
+	set -l args
+	set -l tokens (commandline -opc)
+	for i in (seq 2 (count $tokens))
+		switch $tokens[$i]
+			case -c --conf-file
+				set -q tokens[(math $i + 1)]
+				and set -a args -c (string replace -r '^~' $HOME -- $tokens[(math $i + 1)])
+			case -l --local-mode
+				set -a args -l
+		end
+	end
+	{name} $args __complete (commandline -ct) 2>/dev/null
+end
+
+complete -c {name} -n '__fish_use_subcommand' -f -a '(__{name}_keys)'
+"#
+    )
+}
+
+/// The script of clap_complete for the shell, with the completion of the key paths
+pub fn script(shell: Shell, cmd: &Command) -> String {
+    let name = cmd.get_name().to_string();
+    let mut cmd = visible(cmd);
+    let mut script = vec![];
+    clap_complete::generate(shell, &mut cmd, &name, &mut script);
+    let script = String::from_utf8(script).expect("clap_complete writes UTF-8");
+    // generate built the command, so the global flags are known
+    let value_flags = value_flags(&cmd);
+    let keys = match shell {
+        Shell::Bash => bash_keys(&name, &value_flags),
+        Shell::Zsh => zsh_keys(&name, &value_flags),
+        Shell::Fish => fish_keys(&name),
+        _ => String::new(),
+    };
+    script + &keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn command() -> Command {
+        Command::new("dt")
+            .arg(
+                Arg::new("conf_file")
+                    .long("conf-file")
+                    .short('c')
+                    .global(true)
+                    .value_parser(clap::value_parser!(std::path::PathBuf)),
+            )
+            .arg(
+                Arg::new("local_mode")
+                    .long("local-mode")
+                    .short('l')
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .value_parser(["auto", "always", "never"]),
+            )
+            .arg(Arg::new("input").action(ArgAction::Append))
+            .subcommand(Command::new("list").about("Print every command"))
+            .subcommand(Command::new("__complete").hide(true))
+    }
+
+    #[test]
+    fn test_scripts() {
+        let cmd = command();
+        assert_eq!(value_flags(&cmd), ["--conf-file", "-c", "--color"]);
+        let bash = script(Shell::Bash, &cmd);
+        assert!(bash.contains("complete -F _dt -o nosort"));
+        assert!(!bash.contains("__complete)"));
+        assert!(bash.contains(r#"dt "${args[@]}" __complete "$cur""#));
+        assert!(bash.contains("--conf-file|-c|--color) ((i++)) ;;"));
+        assert!(bash.ends_with("complete -F _dt_keys -o nosort -o bashdefault -o default dt\n"));
+        assert!(script(Shell::Zsh, &cmd).ends_with("compdef _dt_keys dt\n"));
+        let fish = script(Shell::Fish, &cmd);
+        assert!(fish.contains("-a '(__dt_keys)'"));
+    }
+}
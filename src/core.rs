@@ -1,27 +1,113 @@
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use console::{pad_str, style, Alignment, Key, Term};
+use console::{pad_str, style, Alignment, Key, Style, Term};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{FuzzySelect, MultiSelect, Select};
+use hashbrown::HashMap;
 use log::debug;
-use rustyline::completion::FilenameCompleter;
+use once_cell::sync::{Lazy, OnceCell};
+use rustyline::completion::{FilenameCompleter, Pair};
 use rustyline::highlight::Highlighter;
-use rustyline::{Completer, Helper, Hinter, Validator};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::{Cmd, Completer, Helper, KeyCode, KeyEvent, Modifiers, Validator};
+use std::borrow::Cow;
 use std::env;
+use std::io;
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::Stdio;
-use std::{fs, io};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+use crate::accessible;
+use crate::actions;
+use crate::browse;
+use crate::dynmenu;
+use crate::error::{self, DotreeError};
+use crate::exec::{
+    bound_values, command_shell, effective_shell, exit_code, find_command, follow_path, is_secret,
+    resolve_stdin, runs_in_tmux, spawn_with_input, tmux_shell, wait_with_timeout, working_dir,
+    ChildEnv,
+};
+use crate::frecent;
+use crate::history;
+use crate::i18n::tr;
+use crate::logs;
 use crate::outproxy::OutProxy;
-use crate::parser::{self, CommandSetting, Menu, Node, SnippetTable};
-use crate::rt_conf;
+use crate::parser::{self, CommandSetting, Config, Menu, Node, SnippetTable, StringExpr, VarKind};
+use crate::rt_conf::{self, RuntimeContext};
+use crate::screen;
+use crate::spinner::{LinePrinter, Spinner};
+use crate::state::{self, FailedRun};
+use crate::timings;
+use crate::watch;
 
-#[derive(Debug, Clone)]
-enum Submenus<'a> {
-    Exact(&'a Node, usize),
-    Incomplete(usize),
-    None,
+/// Names and values of variables
+type Values = Vec<(String, String)>;
+
+/// The number of rows of output of repeat commands, that were printed below the menu. Wrapped
+/// lines take up several
+static PRINTED_LINES: AtomicUsize = AtomicUsize::new(0);
+
+/// The values of the variables of commands with the reuse_vars setting, from their last run
+/// in this session, by the keys of the commands
+static SESSION_VALUES: Lazy<Mutex<HashMap<String, Values>>> = Lazy::new(Default::default);
+
+/// Options for `run_config`, besides the config itself
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// The path of the config, which the state files, like the history, are scoped to
+    pub conf_path: PathBuf,
+    /// The directory of the config, if it should be run in local mode
+    pub local_conf_dir: Option<PathBuf>,
+    pub cli_options: rt_conf::CliOptions,
+    /// Keys that are applied right away, followed by values for the vars of the command
+    pub input: Vec<String>,
+}
+
+/// Shows the menu of a parsed config and runs the commands that are selected, like `dt` does,
+/// and returns the code `dt` would exit with. This is the entry point for programs that embed
+/// dotree. The runtime context only applies to the calling thread, and the variables are only
+/// passed to the processes of the commands, but the working directory of the process is changed
+/// for the commands that set one, and stays changed after it returned
+pub fn run_config(config: &Config, options: &RunOptions) -> error::Result<i32> {
+    let ctx = RuntimeContext {
+        conf_path: options.conf_path.clone(),
+        local_conf_dir: options.local_conf_dir.clone(),
+        settings: config.settings.clone(),
+        cli_options: options.cli_options.clone(),
+    };
+    let root_node = Node::Menu(config.menu.clone());
+    run(&ctx, &root_node, &options.input, &config.snippet_table)
+}
+
+/// Shows the menu, and runs the commands that are selected, with `ctx` as the current runtime
+/// context, and returns the exit code of the command that ended the session, or 0. The first
+/// element of `input` are keys that are applied right away, the others are values for the vars
+/// of the command
+pub fn run(
+    ctx: &RuntimeContext,
+    root_node: &Node,
+    input: &[String],
+    snippet_table: &SnippetTable,
+) -> error::Result<i32> {
+    let _ctx = rt_conf::enter(ctx.clone());
+    // nothing is carried over from a config that ran before in this process
+    SESSION_VALUES.lock().unwrap().clear();
+    PRINTED_LINES.store(0, Ordering::Relaxed);
+    Ok(run_tree(root_node, input, snippet_table)?)
+}
+
+/// Keeps ctrl+c from terminating dotree. It can only be set once per process
+pub(crate) fn ignore_ctrlc() -> Result<()> {
+    static HANDLER: OnceCell<()> = OnceCell::new();
+    HANDLER.get_or_try_init(|| ctrlc::set_handler(|| {}))?;
+    Ok(())
 }
 
-pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> Result<()> {
+fn run_tree(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> Result<i32> {
     let mut input_chars = if let Some(input) = input.first() {
         input.chars().collect()
     } else {
@@ -31,62 +117,360 @@ pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) ->
 
     let term = Term::stdout();
     let mut out_proxy = OutProxy::new();
-    let (found_node, input_offset) = follow_path(root_node, &input_chars, 0);
+    // a copy, that gets the entries of the dynamic menus that are entered
+    let mut tree = root_node.clone();
+    let mut dynamic = dynmenu::Expander::default();
+    dynamic.expand(&mut tree, &input_chars, snippet_table)?;
+    let (found_node, input_offset) = follow_path(&tree, &input_chars, 0);
     let mut input_pos = input_offset;
     let mut current_node = if let Some(found_node) = found_node {
         found_node
     } else {
         input_chars.clear();
-        root_node
+        &tree
     };
 
     // we need to create a handler, because, if we don't the program will terminate abnormally
     // but if we do, readline will return an io::Error with kind Interrupted, when ctrl+c
     // is pressed
-    ctrlc::set_handler(|| {})?;
+    ignore_ctrlc()?;
 
+    // the next command that is selected is pinned or unpinned, instead of run
+    let mut pin_mode = false;
+    // the next command that is selected is opened in the editor, instead of run
+    let mut edit_mode = false;
+    // the next command that is selected asks for new values, even if it has reuse_vars
+    let mut fresh_mode = false;
+    // typed in front of the keys, like in vim
+    let mut count: Option<u32> = None;
+    // the entry of the current menu that is highlighted with the arrow keys
+    let mut selected: Option<usize> = None;
     loop {
+        let favorites = favorites(&tree)?;
         match current_node {
+            Node::Command(c) if edit_mode => {
+                screen::clear_last_lines(out_proxy.n_lines)?;
+                screen::show_cursor()?;
+                let conf_path = rt_conf::conf_path();
+                let path = c.file.as_deref().unwrap_or(&conf_path);
+                open_in_editor(path, c.line)?;
+                return Ok(0);
+            }
+            Node::Command(_) if pin_mode => {
+                state::toggle_pin(&String::from_iter(&input_chars[..input_pos]))?;
+                pin_mode = false;
+                input_chars.clear();
+                input_pos = 0;
+                current_node = &tree;
+                continue;
+            }
             Node::Command(c) => {
-                if c.repeat() {
-                    input_chars.pop();
-                } else {
-                    term.clear_last_lines(out_proxy.n_lines)?;
-                    term.show_cursor()?;
+                let keys = String::from_iter(&input_chars[..input_pos]);
+                if fresh_mode {
+                    SESSION_VALUES.lock().unwrap().remove(&keys);
+                    fresh_mode = false;
                 }
-                run_command(c, &term, arg_vals, snippet_table)?;
+                // commands of a sticky menu run like repeat commands, and return to the menu
+                let sticky_cmd;
+                let c = match parent_menu(&tree, &input_chars[..input_pos]) {
+                    Some((menu, menu_keys)) if menu.sticky && !c.repeat() => {
+                        input_chars.truncate(menu_keys);
+                        let mut cmd = c.clone();
+                        cmd.settings.push(CommandSetting::Repeat);
+                        sticky_cmd = cmd;
+                        &sticky_cmd
+                    }
+                    _ if c.repeat() => {
+                        input_chars.pop();
+                        c
+                    }
+                    _ => {
+                        screen::clear_last_lines(out_proxy.n_lines)?;
+                        screen::show_cursor()?;
+                        c
+                    }
+                };
+                let presets = &rt_conf::cli_options().var_presets;
+                if let Some(code) =
+                    run_command(c, &term, arg_vals, snippet_table, &keys, presets, count)?
+                {
+                    return Ok(code);
+                }
+                // the output stays until the menu is drawn again
+                out_proxy.n_lines += PRINTED_LINES.swap(0, Ordering::Relaxed);
+                count = None;
             }
             Node::Menu(m) => {
-                term.clear_last_lines(out_proxy.n_lines)?;
+                screen::clear_last_lines(out_proxy.n_lines)?;
                 out_proxy.n_lines = 0;
-                render_menu(m, &input_chars[input_pos..], &mut out_proxy)?;
+                if input_chars.is_empty() && !favorites.is_empty() {
+                    render_favorites(&favorites, &mut out_proxy)?;
+                }
+                // in submenus, the path to them is shown, so it is clear where one is
+                if input_pos > 0 {
+                    let names = breadcrumb(&tree, &input_chars[..input_pos]);
+                    writeln!(out_proxy, "{}", style(names.join(" › ")).dim())?;
+                }
+                if let Some(count) = count {
+                    writeln!(out_proxy, "{}", style(format!("{count}×")).cyan().bold())?;
+                }
+                render_menu(
+                    m,
+                    &input_chars[..input_pos],
+                    &input_chars[input_pos..],
+                    selected,
+                    &mut out_proxy,
+                )?;
+                if pin_mode {
+                    writeln!(out_proxy, "{}", style(tr("pin_hint", &[])).yellow().dim())?;
+                } else if edit_mode {
+                    writeln!(out_proxy, "{}", style(tr("edit_hint", &[])).yellow().dim())?;
+                } else if fresh_mode {
+                    writeln!(out_proxy, "{}", style(tr("fresh_hint", &[])).yellow().dim())?;
+                } else if input_chars.is_empty() {
+                    if let Some((cmd, _)) = retry_target(&tree)? {
+                        let hint = tr("retry_hint", &[("cmd", cmd)]);
+                        writeln!(out_proxy, "{}", style(hint).red().dim())?;
+                    }
+                }
             }
         }
 
-        // returns true when the user pressed Esc or Ctrl+c, which means we should exit
-        if get_input(&mut input_chars, &term)? {
-            term.clear_last_lines(out_proxy.n_lines)?;
-            Term::stderr().show_cursor()?;
-            break Ok(());
-        };
+        match get_input(&mut input_chars)? {
+            Input::Continue => selected = None,
+            Input::Move(delta) => match current_node {
+                // a dynamic menu can be empty
+                Node::Menu(m) if !m.entries.is_empty() => {
+                    input_chars.truncate(input_pos);
+                    let n = m.entries.len() as isize;
+                    selected = Some(match selected {
+                        Some(i) => (i as isize + delta).rem_euclid(n) as usize,
+                        None if delta > 0 => 0,
+                        None => m.entries.len() - 1,
+                    });
+                }
+                _ => {}
+            },
+            Input::Select => {
+                let entry = match (current_node, selected) {
+                    (Node::Menu(m), Some(i)) => m.entries.get_index(i),
+                    _ => None,
+                };
+                if let Some((keys, node)) = entry {
+                    input_chars.truncate(input_pos);
+                    input_chars.extend(keys);
+                    // in a submenu, the highlight starts at its first entry
+                    selected = matches!(node, Node::Menu(_)).then_some(0);
+                }
+            }
+            Input::Back => {
+                if input_chars.len() > input_pos {
+                    input_chars.truncate(input_pos);
+                } else if let Some((parent, n)) = parent_menu(&tree, &input_chars) {
+                    // the highlight returns to the submenu that was left
+                    selected = parent.entries.get_index_of(&input_chars[n..]);
+                    input_chars.truncate(n);
+                }
+            }
+            input @ (Input::Exit | Input::Idle) => {
+                screen::clear_last_lines(out_proxy.n_lines)?;
+                screen::show_cursor()?;
+                if let Input::Idle = input {
+                    eprintln!("{}", style(tr("idle_exit", &[])).dim());
+                }
+                break Ok(0);
+            }
+            Input::Retry { reuse_vars } if input_chars.is_empty() => {
+                if let Some((cmd, run)) = retry_target(&tree)? {
+                    screen::clear_last_lines(out_proxy.n_lines)?;
+                    out_proxy.n_lines = 0;
+                    if !cmd.repeat() {
+                        screen::show_cursor()?;
+                    }
+                    let presets = if reuse_vars { run.vars } else { vec![] };
+                    if let Some(code) =
+                        run_command(cmd, &term, &[], snippet_table, &run.keys, &presets, None)?
+                    {
+                        return Ok(code);
+                    }
+                    // the menu was cleared before, so the output stays above it
+                    PRINTED_LINES.store(0, Ordering::Relaxed);
+                }
+            }
+            Input::Retry { .. } => {}
+            Input::TogglePinMode => {
+                pin_mode = !pin_mode;
+                edit_mode = false;
+                fresh_mode = false;
+            }
+            Input::ToggleEditMode => {
+                edit_mode = !edit_mode;
+                pin_mode = false;
+                fresh_mode = false;
+            }
+            Input::ToggleFreshMode => {
+                fresh_mode = !fresh_mode;
+                pin_mode = false;
+                edit_mode = false;
+            }
+            Input::Root => {
+                input_chars.clear();
+                count = None;
+                selected = None;
+            }
+            Input::Browse => {
+                screen::clear_last_lines(out_proxy.n_lines)?;
+                out_proxy.n_lines = 0;
+                if let Some(keys) = browse::browse(&tree, &term)? {
+                    input_chars = keys.chars().collect();
+                    // the highlight belonged to the menu that was left
+                    selected = None;
+                }
+            }
+        }
+
+        // digits in front of the keys are a count, unless they are keys themselves
+        if let [c @ '0'..='9'] = input_chars[..] {
+            let digit = c.to_digit(10).unwrap();
+            let is_key = favorites.iter().any(|fav| fav.key == c)
+                || matches!(&tree, Node::Menu(root) if root.entries.keys().any(|k| k[0] == c));
+            match count {
+                Some(n) => {
+                    count = Some(n.saturating_mul(10).saturating_add(digit));
+                    input_chars.clear();
+                }
+                None if digit != 0 && !is_key => {
+                    count = Some(digit);
+                    input_chars.clear();
+                }
+                None => {}
+            }
+        }
+
+        // the key of a favorite leads to the command it stands for
+        if let [c] = input_chars[..] {
+            if let Some(fav) = favorites.iter().find(|fav| fav.key == c) {
+                input_chars = fav.keys.chars().collect();
+            }
+        }
 
-        let (found_node, input_offset_) = follow_path(root_node, &input_chars, 0);
+        dynamic.expand(&mut tree, &input_chars, snippet_table)?;
+        let (found_node, input_offset_) = follow_path(&tree, &input_chars, 0);
         input_pos = input_offset_;
         current_node = if let Some(found_node) = found_node {
             found_node
         } else {
             input_chars.clear();
-            root_node
+            &tree
         };
     }
 }
 
-type Exit = bool;
-fn get_input(input_chars: &mut Vec<char>, term: &Term) -> Result<Exit> {
-    let key = match term.read_key() {
-        Ok(k) => k,
+/// The names of the menus the keys lead through, starting with the root menu
+pub(crate) fn breadcrumb(node: &Node, keys: &[char]) -> Vec<String> {
+    let Node::Menu(menu) = node else {
+        return vec![];
+    };
+    let mut names = vec![node.to_string()];
+    if let Some((entry_keys, child)) = menu.entries.iter().find(|(k, _)| keys.starts_with(k)) {
+        names.extend(breadcrumb(child, &keys[entry_keys.len()..]));
+    }
+    names
+}
+
+/// The menu that contains the entry the keys lead to, and the number of keys that lead to
+/// the menu
+pub(crate) fn parent_menu<'a>(node: &'a Node, keys: &[char]) -> Option<(&'a Menu, usize)> {
+    let Node::Menu(menu) = node else {
+        return None;
+    };
+    let (entry_keys, child) = menu.entries.iter().find(|(k, _)| keys.starts_with(k))?;
+    if entry_keys.len() == keys.len() {
+        return Some((menu, 0));
+    }
+    let (parent, n) = parent_menu(child, &keys[entry_keys.len()..])?;
+    Some((parent, n + entry_keys.len()))
+}
+
+/// Re-runs the last command that didn't exit successfully, with the same variable values.
+/// Values in the var presets of the command line take precedence. Returns the exit code of
+/// the command
+pub fn retry_failed(root_node: &Node, snippet_table: &SnippetTable) -> error::Result<i32> {
+    Ok(retry(root_node, snippet_table)?.unwrap_or(0))
+}
+
+fn retry(root_node: &Node, snippet_table: &SnippetTable) -> Result<Option<i32>> {
+    let (cmd, run) = retry_target(root_node)?.ok_or(anyhow!(tr("no_failed_command", &[])))?;
+    let cli_presets = &rt_conf::cli_options().var_presets;
+    let mut presets = cli_presets.clone();
+    presets.extend(
+        run.vars
+            .into_iter()
+            .filter(|(name, _)| !cli_presets.iter().any(|(n, _)| n == name)),
+    );
+    ignore_ctrlc()?;
+    run_command(
+        cmd,
+        &Term::stdout(),
+        &[],
+        snippet_table,
+        &run.keys,
+        &presets,
+        None,
+    )
+}
+
+/// The last failed run, and the command it belongs to, if it still exists. Values of
+/// variables the command doesn't have anymore are dropped
+fn retry_target(root_node: &Node) -> Result<Option<(&parser::Command, FailedRun)>> {
+    let Some(mut run) = state::load_last_failed()? else {
+        return Ok(None);
+    };
+    let Some(cmd) = find_command(root_node, &run.keys) else {
+        return Ok(None);
+    };
+    run.vars
+        .retain(|(name, _)| cmd.env_vars.iter().any(|v| &v.name == name));
+    Ok(Some((cmd, run)))
+}
+
+pub(crate) enum Input {
+    Continue,
+    /// The user pressed Esc or Ctrl+c
+    Exit,
+    /// The user pressed no key for the idle timeout
+    Idle,
+    /// The user pressed tab or shift+tab, to retry the last failed command
+    Retry {
+        reuse_vars: bool,
+    },
+    /// The user pressed ctrl+p, to pin or unpin the next command that is selected
+    TogglePinMode,
+    /// The user pressed ctrl+t, to explore the tree
+    Browse,
+    /// The user pressed ctrl+e, to open the next command that is selected in the editor
+    ToggleEditMode,
+    /// The user pressed ctrl+n, so the next command that is selected asks for new values
+    ToggleFreshMode,
+    /// The user pressed the home key, or the root key from the settings, to return to the
+    /// root menu
+    Root,
+    /// The user pressed up or down, to move the highlight by this many entries
+    Move(isize),
+    /// The user pressed enter or right, to select the highlighted entry
+    Select,
+    /// The user pressed left, or the back key from the settings, to return to the menu above
+    Back,
+}
+
+pub(crate) fn get_input(input_chars: &mut Vec<char>) -> Result<Input> {
+    let key = match screen::read_key_within(rt_conf::settings().idle_timeout) {
+        Ok(Some(k)) => k,
+        Ok(None) => {
+            return Ok(Input::Idle);
+        }
         Err(e) if e.kind() == io::ErrorKind::Interrupted => {
-            return Ok(true);
+            return Ok(Input::Exit);
         }
         Err(e) => {
             bail!("Error while waiting for key: {e:?}");
@@ -95,258 +479,1031 @@ fn get_input(input_chars: &mut Vec<char>, term: &Term) -> Result<Exit> {
 
     debug!("got char: {key:?}");
     match key {
+        Key::Char('\x10') => {
+            return Ok(Input::TogglePinMode);
+        }
+        Key::Char('\x14') => {
+            return Ok(Input::Browse);
+        }
+        Key::Char('\x05') => {
+            return Ok(Input::ToggleEditMode);
+        }
+        Key::Char('\x0e') => {
+            return Ok(Input::ToggleFreshMode);
+        }
+        Key::Home => {
+            return Ok(Input::Root);
+        }
+        Key::Char(c) if Some(c) == rt_conf::settings().root_key => {
+            return Ok(Input::Root);
+        }
+        Key::Char(c) if Some(c) == rt_conf::settings().back_key => {
+            return Ok(Input::Back);
+        }
         Key::Char(c) => {
             input_chars.push(c);
         }
         Key::Backspace => {
             input_chars.pop();
         }
+        // with escape as back key, it still closes the root menu
+        Key::Escape if rt_conf::settings().back_key == Some('\x1b') && !input_chars.is_empty() => {
+            return Ok(Input::Back);
+        }
         Key::Escape => {
-            return Ok(true);
+            return Ok(Input::Exit);
+        }
+        Key::ArrowUp => {
+            return Ok(Input::Move(-1));
+        }
+        Key::ArrowDown => {
+            return Ok(Input::Move(1));
+        }
+        Key::Enter | Key::ArrowRight => {
+            return Ok(Input::Select);
+        }
+        Key::ArrowLeft => {
+            return Ok(Input::Back);
+        }
+        Key::Tab => {
+            return Ok(Input::Retry { reuse_vars: true });
+        }
+        Key::BackTab => {
+            return Ok(Input::Retry { reuse_vars: false });
         }
         _ => {}
     }
-    Ok(false)
+    Ok(Input::Continue)
 }
 
-fn run_command(
+/// Runs the command, after querying its variables. Returns the code dotree exits with
+/// afterwards, or None for repeat commands, after which the menu is shown again
+pub(crate) fn run_command(
     cmd: &parser::Command,
     term: &Term,
     arg_vals: &[String],
     snippet_table: &SnippetTable,
-) -> Result<()> {
-    let mut history = load_hist().context("loading hist")?;
+    keys: &str,
+    presets: &[(String, String)],
+    count: Option<u32>,
+) -> Result<Option<i32>> {
+    let dry_run = rt_conf::cli_options().dry_run;
+    if !dry_run
+        && (!danger_confirmed(cmd, term, keys)?
+            || !confirmed(cmd, term)?
+            || !cooldown_passed(cmd, term, keys)?)
+    {
+        return Ok((!cmd.repeat()).then_some(0));
+    }
+    // the lock is released when it is dropped, or when the process exits. A dry run doesn't
+    // run the command, so it doesn't have to wait for other runs
+    let _lock = if cmd.settings.contains(&CommandSetting::Exclusive) && !dry_run {
+        Some(state::lock_command(keys)?)
+    } else {
+        None
+    };
+    let history = load_hist().context("loading hist")?;
+    let mut new_entries = vec![];
     debug!("Running: {cmd}");
 
+    for (name, _) in presets {
+        ensure!(
+            cmd.env_vars.iter().any(|v| &v.name == name),
+            tr("unknown_variable", &[("name", name)])
+        );
+    }
     ensure!(
-        arg_vals.len() <= cmd.env_vars.len(),
-        "Too many arguments for this command"
+        arg_vals.len() + presets.len() <= cmd.env_vars.len(),
+        tr("too_many_arguments", &[])
     );
 
-    if let Some(wd) = rt_conf::local_conf_dir() {
+    if let Some(wd) = working_dir(Some(cmd))? {
         env::set_current_dir(wd).context("Changing working directory")?;
     }
 
-    for i in 0..cmd.env_vars.len() {
-        let var = &cmd.env_vars[i];
-        let val = if let Some(val) = arg_vals.get(i) {
-            val
-        } else {
-            history = query_env_var(&var.name, &var.value, history).context("querying env var")?;
-            history.last().unwrap()
+    // preset vars are skipped, the others are taken from the positional arguments in order,
+    // then from the last run in this session, and the ones that are still missing are queried
+    // in prompt order
+    let reused = if cmd.settings.contains(&CommandSetting::ReuseVars) {
+        SESSION_VALUES.lock().unwrap().get(keys).cloned()
+    } else {
+        None
+    };
+    let mut positional_vals = arg_vals.iter();
+    let mut vals = bound_values(cmd, snippet_table)?;
+    for var in &cmd.env_vars {
+        if let Some((_, val)) = presets.iter().find(|(name, _)| name == &var.name) {
+            vals.push((var.name.clone(), val.clone()));
+        } else if let Some(val) = positional_vals.next() {
+            vals.push((var.name.clone(), val.clone()));
+        } else if let Some((_, val)) = reused.iter().flatten().find(|(name, _)| name == &var.name) {
+            vals.push((var.name.clone(), val.clone()));
+        }
+    }
+    let mut n_queried = 0;
+    for var in cmd.vars_in_prompt_order() {
+        if vals.iter().any(|(name, _)| name == &var.name) {
+            continue;
+        }
+        let val = match &var.kind {
+            VarKind::Input => {
+                let default_val = var
+                    .value
+                    .as_ref()
+                    .map(|v| v.resolve_with_vars(snippet_table, &vals))
+                    .transpose()
+                    .context(format!("resolving the default of {}", var.name))?;
+                let val = if var.secret {
+                    query_secret(&var.name, &default_val, term).context("querying env var")?
+                } else {
+                    let last_val = state::last_value(keys, &var.name)?;
+                    let var_history = history::values_for(&history, &var.name);
+                    query_env_var(&var.name, &default_val, last_val.as_deref(), &var_history)
+                        .context("querying env var")?
+                };
+                if !var.nohist {
+                    new_entries.push(history::Entry::now(&var.name, &val));
+                }
+                val
+            }
+            VarKind::MultiSelect { options, separator } => {
+                query_multi_select(&var.name, options, separator, term)
+                    .context("querying env var")?
+            }
+            VarKind::Dir => query_dir(&var.name).context("querying env var")?,
+            VarKind::Fzf { helper } => {
+                query_from_helper(&var.name, helper, true, cmd, snippet_table, &vals, term)
+                    .context("querying env var")?
+            }
+            VarKind::Pick { helper } => {
+                query_from_helper(&var.name, helper, false, cmd, snippet_table, &vals, term)
+                    .context("querying env var")?
+            }
         };
-        // uppon calling exec, the env vars are kept, so just setting them here
-        // means setting them for the callee
-        env::set_var(&var.name, val);
-    }
-    term.clear_last_lines(cmd.env_vars.len() - arg_vals.len())
-        .context("Clearing input lines")?;
-    store_hist(history).context("Storing history")?;
-
-    let shell = cmd
-        .shell
-        .clone()
-        .or_else(|| rt_conf::settings().shell_def.clone())
-        .unwrap_or_default();
+        n_queried += 1;
+        vals.push((var.name.clone(), val));
+    }
+    // the values are only passed to the processes of the command, not set for dotree, so
+    // they don't leak into the commands that run after it
+    let mut env_vals = vals.clone();
+    if let Some(count) = count {
+        env_vals.push(("DT_COUNT".into(), count.to_string()));
+    }
+    let child_env = ChildEnv::new(cmd, &env_vals, &[])?;
+    clear_last_lines(n_queried).context("Clearing input lines")?;
+    if cmd.settings.contains(&CommandSetting::ReuseVars) {
+        SESSION_VALUES
+            .lock()
+            .unwrap()
+            .insert(keys.to_string(), vals.clone());
+    }
+    // the values of nohist vars are only kept in memory
+    let stored_vals: Vec<_> = vals
+        .iter()
+        .filter(|(name, _)| !cmd.env_vars.iter().any(|v| &v.name == name && v.nohist))
+        .cloned()
+        .collect();
+    if !dry_run {
+        store_hist(&new_entries).context("Storing history")?;
+        state::record_values(keys, &stored_vals).context("Storing values")?;
+    }
+
+    if let Some(action) = cmd.action {
+        // without a shell, the variables are substituted by dotree
+        let arg = cmd
+            .exec_str
+            .resolve_with_vars(snippet_table, &vals)
+            .context(format!("resolving {}", cmd.exec_str))?;
+        if dry_run {
+            println!("{}", describe_exec(cmd, &[], &child_env, true)?.join("\n"));
+            println!("action: {action} {arg}");
+            return Ok((!cmd.repeat()).then_some(0));
+        }
+        timings::measure("command", || actions::run(action, &arg))?;
+        timings::report();
+        state::record_run(keys)?;
+        return Ok((!cmd.repeat()).then_some(0));
+    }
+    let shell = tmux_shell(cmd, command_shell(cmd)?, &child_env)?;
     debug!("shell: {shell:?}");
-    let arg = cmd
-        .exec_str
-        .resolve(snippet_table)
+    let arg = timings::measure("snippet resolution", || cmd.exec_str.resolve(snippet_table))
         .context(format!("resolving {}", cmd.exec_str))?;
-    let args = shell.args_with(arg.as_str());
+    let script = cmd.script(&arg);
+    let args = shell.args_with(&script);
+    let input = resolve_stdin(cmd, snippet_table)?;
+    let input = input.as_deref();
+    if dry_run {
+        let argv: Vec<_> = std::iter::once(shell.name.as_str()).chain(args).collect();
+        println!(
+            "{}",
+            describe_exec(cmd, &argv, &child_env, true)?.join("\n")
+        );
+        if let Some(input) = input {
+            println!("stdin: {input:?}");
+        }
+        return Ok((!cmd.repeat()).then_some(0));
+    }
+    trace_exec(cmd, &shell.name, &args, &child_env)?;
+    // the sudo of Windows asks with UAC instead
+    if cmd.sudo() && !cfg!(windows) {
+        authenticate_sudo()?;
+    }
+    let log = cmd.settings.contains(&CommandSetting::Log);
+    let name = cmd.to_string();
+    // tmux returns as soon as the command started, so its result isn't known
+    let detached = runs_in_tmux(cmd);
     if cmd.settings.contains(&CommandSetting::Repeat) {
-        run_subcommand(
-            &shell.name,
-            &args,
-            cmd.settings.contains(&CommandSetting::IgnoreResult),
-        )
+        let ignore_result = cmd.settings.contains(&CommandSetting::IgnoreResult) || detached;
+        let run_once = || -> Result<ExitStatus> {
+            let spinner = Spinner::start(term, cmd.to_string(), &PRINTED_LINES);
+            let printer = spinner.printer();
+            let status = timings::measure("command", || {
+                if log {
+                    // the output would mess up the menu, so it only goes to the log
+                    logs::run_logged(&shell.name, &args, &child_env, keys, &name, false, input)
+                } else {
+                    run_subcommand(&shell.name, &args, &child_env, input, &printer)
+                }
+            })?;
+            drop(spinner);
+            timings::report();
+            state::record_run(keys)?;
+            if !ignore_result {
+                state::record_result(keys, &stored_vals, status.success())?;
+            }
+            if !detached {
+                record_cooldown(cmd, keys, status)?;
+                run_hooks(cmd, &arg, status, snippet_table, &env_vals, false)?;
+            }
+            Ok(status)
+        };
+        let mut status = run_once()?;
+        // with a count, the command runs that many times, unless it fails
+        for _ in 1..count.unwrap_or(1) {
+            if !status.success() && !ignore_result {
+                break;
+            }
+            status = run_once()?;
+        }
+        if let Some(pattern) = cmd.watch() {
+            // while watching, failures are only shown in the status line
+            watch::watch(pattern, term, run_once)?;
+            return Ok(None);
+        }
+        check_status(status, ignore_result)?;
+        Ok(None)
     } else {
         if rt_conf::settings().echo_by_default != cmd.toggle_echo_setting {
             eprintln!("{arg}");
         }
-        exec_cmd(&shell.name, args)
+        let status = timings::measure("command", || {
+            if log {
+                logs::run_logged(&shell.name, &args, &child_env, keys, &name, true, input)
+            } else {
+                let mut child = std::process::Command::new(&shell.name);
+                child.args(args);
+                child_env.apply(&mut child);
+                let mut child = spawn_with_input(&mut child, input)?;
+                wait_with_timeout(&mut child).context(format!("running {}", shell.name))
+            }
+        })?;
+        timings::report();
+        state::record_run(keys)?;
+        if detached {
+            return Ok(Some(exit_code(status)));
+        }
+        if !cmd.settings.contains(&CommandSetting::IgnoreResult) {
+            state::record_result(keys, &stored_vals, status.success())?;
+        }
+        record_cooldown(cmd, keys, status)?;
+        run_hooks(cmd, &arg, status, snippet_table, &env_vals, true)?;
+        Ok(Some(exit_code(status)))
     }
 }
 
-#[cfg(not(windows))]
-fn exec_cmd<'a>(shell_name: &'a str, mut args: Vec<&'a str>) -> Result<()> {
-    args.insert(0, shell_name);
-    Err(anyhow!(
-        "error executing command: \n{:?}",
-        exec::execvp(shell_name, &args)
-    ))
+/// Asks for the password of sudo before a command runs, so the prompt isn't mixed up with the
+/// spinner, or the output of repeat commands. Nothing is asked, if sudo still has the
+/// credentials, or doesn't need them
+fn authenticate_sudo() -> Result<()> {
+    let cached = process::Command::new("sudo")
+        .args(["-n", "-v"])
+        .stderr(Stdio::null())
+        .status()
+        .context("running sudo")?;
+    if cached.success() {
+        return Ok(());
+    }
+    let status = process::Command::new("sudo")
+        .arg("-v")
+        .status()
+        .context("running sudo")?;
+    // the line of the password prompt
+    PRINTED_LINES.fetch_add(1, Ordering::Relaxed);
+    ensure!(status.success(), tr("sudo_failed", &[]));
+    Ok(())
 }
 
-#[cfg(windows)]
-fn exec_cmd(shell_name: &str, args: Vec<&str>) -> Result<()> {
-    // windows doesn't have an exec, let's do this instead
-    let status = std::process::Command::new(shell_name).args(args).status()?;
-    if !status.success() {
-        Err(anyhow!("Process didn't exit successfully: {status:?}"))
-    } else {
-        std::process::exit(0);
+/// Prints the shell command line and the working directory with -v, and also the environment
+/// variables dotree set with -vv or `set trace_env`, to see what exactly is run
+fn trace_exec(cmd: &parser::Command, shell: &str, args: &[&str], env: &ChildEnv) -> Result<()> {
+    let verbose = rt_conf::cli_options().verbose;
+    let trace_env = verbose >= 2 || cmd.settings.contains(&CommandSetting::TraceEnv);
+    if verbose == 0 && !trace_env {
+        return Ok(());
+    }
+    let argv: Vec<_> = std::iter::once(shell).chain(args.iter().copied()).collect();
+    for line in describe_exec(cmd, &argv, env, trace_env)? {
+        eprintln!("{}", style(line).dim());
     }
+    Ok(())
 }
 
-fn run_subcommand(prog: &str, args: &[&str], ignore_result: bool) -> Result<()> {
-    let status = std::process::Command::new(prog)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .args(args)
-        .status()?;
+/// The working directory and `argv`, unless it's empty, and with `with_env`, the
+/// environment variables dotree sets for `cmd`, with the values of secrets masked
+fn describe_exec(
+    cmd: &parser::Command,
+    argv: &[&str],
+    env: &ChildEnv,
+    with_env: bool,
+) -> Result<Vec<String>> {
+    let cwd = env::current_dir().context("getting cwd")?;
+    let mut res = vec![format!("cwd: {}", cwd.display())];
+    if !argv.is_empty() {
+        res.push(format!("argv: {argv:?}"));
+    }
+    if !with_env {
+        return Ok(res);
+    }
+    for (name, val) in &env.set {
+        let val = if is_secret(cmd, name) {
+            "****".into()
+        } else {
+            val.to_string_lossy()
+        };
+        res.push(format!("env: {name}={val}"));
+    }
+    Ok(res)
+}
+
+/// Runs the on_error hook of the command, or the global one, if the command failed, and then
+/// its finally hook. The hooks get the exit code in DT_EXIT_CODE, and the name of the command,
+/// or `arg`, the command line it ran, if it has none, in DT_COMMAND
+fn run_hooks(
+    cmd: &parser::Command,
+    arg: &str,
+    status: ExitStatus,
+    snippet_table: &SnippetTable,
+    vals: &[(String, String)],
+    show_output: bool,
+) -> Result<()> {
+    let settings = rt_conf::settings();
+    let on_error = cmd.on_error.as_ref().or(settings.on_error.as_ref());
+    let mut vals = vals.to_vec();
+    vals.push((
+        "DT_EXIT_CODE".into(),
+        status.code().map(|c| c.to_string()).unwrap_or_default(),
+    ));
+    vals.push((
+        "DT_COMMAND".into(),
+        cmd.name.as_deref().unwrap_or(arg).into(),
+    ));
+    let env = ChildEnv::new(cmd, &vals, &["DT_EXIT_CODE", "DT_COMMAND"])?;
+    if let (false, Some(hook)) = (status.success(), on_error) {
+        run_hook(hook, cmd, snippet_table, &env, show_output)?;
+    }
+    if let Some(hook) = &cmd.finally {
+        run_hook(hook, cmd, snippet_table, &env, show_output)?;
+    }
+    Ok(())
+}
+
+fn run_hook(
+    hook: &parser::StringExpr,
+    cmd: &parser::Command,
+    snippet_table: &SnippetTable,
+    env: &ChildEnv,
+    show_output: bool,
+) -> Result<()> {
+    let hook = hook
+        .resolve(snippet_table)
+        .context(format!("resolving {hook}"))?;
+    let shell = effective_shell(cmd);
+    let mut child = std::process::Command::new(&shell.name);
+    child.args(shell.args_with(&cmd.script(&hook)));
+    env.apply(&mut child);
+    if !show_output {
+        child
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+    }
+    let hook_status = child.status().context(format!("running hook {hook}"))?;
+    debug!("hook {hook}: {hook_status}");
+    Ok(())
+}
+
+/// Runs a repeat command, whose output is printed above the spinner, below the menu
+fn run_subcommand(
+    prog: &str,
+    args: &[&str],
+    env: &ChildEnv,
+    input: Option<&str>,
+    printer: &LinePrinter,
+) -> Result<ExitStatus> {
+    let mut child = std::process::Command::new(prog);
+    child
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(args);
+    env.apply(&mut child);
+    let mut child = spawn_with_input(&mut child, input)?;
+    let (done_tx, done_rx) = mpsc::channel();
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| Box::new(out) as Box<dyn Read + Send>);
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| Box::new(err) as Box<dyn Read + Send>);
+    for reader in stdout.into_iter().chain(stderr) {
+        let printer = printer.clone();
+        let done_tx = done_tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).split(b'\n').map_while(Result::ok) {
+                let line = String::from_utf8_lossy(&line);
+                printer.println(line.trim_end_matches('\r').to_string());
+            }
+            let _ = done_tx.send(());
+        });
+    }
+    let status = wait_with_timeout(&mut child);
+    // processes the command started in the background can keep the pipes open, so the rest
+    // of the output is only waited for briefly
+    for _ in 0..2 {
+        if done_rx.recv_timeout(Duration::from_millis(100)).is_err() {
+            break;
+        }
+    }
+    status
+}
+
+fn check_status(status: ExitStatus, ignore_result: bool) -> Result<()> {
     if !ignore_result && !status.success() {
-        Err(anyhow!("Process didn't exit successfully: {status:?}"))
+        Err(DotreeError::ExecFailed { status }.into())
     } else {
         Ok(())
     }
 }
 
-fn get_hist_path() -> Result<PathBuf> {
-    let dir = if let Some(sd) = dirs::state_dir() {
-        sd
+/// Opens `path` at `line` with $VISUAL or $EDITOR, or vi if neither is set
+fn open_in_editor(path: &Path, line: usize) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor = editor.split_whitespace();
+    let prog = editor.next().ok_or(anyhow!("EDITOR is empty"))?;
+    let mut cmd = std::process::Command::new(prog);
+    cmd.args(editor);
+    // most editors take the line as +N, VS Code and its forks as path:line
+    if matches!(prog.rsplit('/').next(), Some("code" | "codium" | "cursor")) {
+        cmd.arg("-g").arg(format!("{}:{line}", path.display()));
     } else {
-        dirs::data_local_dir().ok_or(anyhow!("couldn't get local dir"))?
+        cmd.arg(format!("+{line}")).arg(path);
+    }
+    cmd.status().context(format!("running editor {prog}"))?;
+    Ok(())
+}
+
+/// Returns false if the command has a cooldown, that didn't pass since its last successful run,
+/// and the user doesn't want to run it anyway. Outside of a terminal, it isn't run in that case,
+/// unless --yes was passed
+fn cooldown_passed(cmd: &parser::Command, term: &Term, keys: &str) -> Result<bool> {
+    let Some(cooldown) = cmd.cooldown() else {
+        return Ok(true);
     };
-    Ok(dir.join("dthist"))
+    let Some(last_success) = state::last_success(keys)? else {
+        return Ok(true);
+    };
+    let elapsed = last_success.elapsed().unwrap_or_default();
+    if elapsed >= cooldown || rt_conf::cli_options().yes {
+        return Ok(true);
+    }
+    let msg = tr(
+        "cooldown",
+        &[
+            ("cmd", cmd),
+            (
+                "elapsed",
+                &humantime::format_duration(Duration::from_secs(elapsed.as_secs())),
+            ),
+            ("cooldown", &humantime::format_duration(cooldown)),
+        ],
+    );
+    if !term.is_term() {
+        eprintln!("{msg} {}", tr("cooldown_skip", &[]));
+        return Ok(false);
+    }
+    term.write_line(&format!("{msg} {}", tr("cooldown_confirm", &[])))?;
+    let answer = term.read_char();
+    clear_last_lines(1)?;
+    let yes = tr("yes_key", &[]);
+    Ok(answer.is_ok_and(|c| yes.starts_with(c.to_ascii_lowercase())))
 }
 
-fn load_hist() -> Result<Vec<String>> {
-    let hist_path = get_hist_path()?;
-    Ok(if hist_path.exists() {
-        fs::read_to_string(hist_path)
-            .context("reading file")?
-            .lines()
-            .map(|x| x.to_string())
-            .collect()
-    } else {
-        vec![]
-    })
-}
-
-fn store_hist(hist: Vec<String>) -> Result<()> {
-    #[cfg(windows)]
-    let line_ending = "\r\n";
-    #[cfg(not(windows))]
-    let line_ending = "\n";
-    let hist_path = get_hist_path()?;
-    fs::create_dir_all(hist_path.parent().context("Getting history file dir")?)
-        .context("creating history file dir")?;
-    fs::write(hist_path, hist.join(line_ending))?;
+/// Commands with the confirm setting only run if the user answers their question with yes,
+/// or passed --yes. Outside of a terminal, they aren't run otherwise
+fn confirmed(cmd: &parser::Command, term: &Term) -> Result<bool> {
+    let Some(question) = cmd.confirm() else {
+        return Ok(true);
+    };
+    if rt_conf::cli_options().yes {
+        return Ok(true);
+    }
+    let name = cmd.to_string();
+    if !term.is_term() {
+        eprintln!("{}", tr("confirm_skip", &[("cmd", &name)]));
+        return Ok(false);
+    }
+    let question = question.map_or_else(|| tr("confirm", &[("cmd", &name)]), String::from);
+    term.write_line(&format!(
+        "{} {}",
+        style(question).yellow(),
+        tr("confirm_suffix", &[])
+    ))?;
+    let answer = term.read_char();
+    clear_last_lines(1)?;
+    let yes = tr("yes_key", &[]);
+    Ok(answer.is_ok_and(|c| yes.starts_with(c.to_ascii_lowercase())))
+}
+
+/// Commands with the danger setting only run if the user types their name, or passed --yes,
+/// which is noted in the audit log. Outside of a terminal, they aren't run otherwise
+fn danger_confirmed(cmd: &parser::Command, term: &Term, keys: &str) -> Result<bool> {
+    if !cmd.danger() {
+        return Ok(true);
+    }
+    let name = cmd.to_string();
+    if rt_conf::cli_options().yes {
+        state::audit(keys, &format!("ran {name}, confirmed by --yes"))?;
+        return Ok(true);
+    }
+    if !term.is_term() {
+        eprintln!("{}", tr("danger_skip", &[("cmd", &name)]));
+        return Ok(false);
+    }
+    term.write_str(&format!(
+        "{} ",
+        style(tr("danger_confirm", &[("cmd", &name)])).red()
+    ))?;
+    let answer = term.read_line()?;
+    clear_last_lines(1)?;
+    Ok(answer.trim() == name)
+}
+
+fn record_cooldown(cmd: &parser::Command, keys: &str, status: ExitStatus) -> Result<()> {
+    if cmd.cooldown().is_some() && status.success() {
+        state::record_success(keys)?;
+    }
     Ok(())
 }
 
-#[derive(Helper, Completer, Hinter, Validator)]
+/// Clears the last lines of the output, unless in accessible mode, where output is never
+/// changed after it was written
+pub(crate) fn clear_last_lines(n: usize) -> io::Result<()> {
+    if rt_conf::cli_options().accessible {
+        return Ok(());
+    }
+    screen::clear_last_lines(n)
+}
+
+pub fn get_hist_path() -> error::Result<PathBuf> {
+    if let Some(path) = &rt_conf::cli_options().history_file {
+        return Ok(path.clone());
+    }
+    Ok(state::scoped_path("dthist")?)
+}
+
+fn load_hist() -> Result<Vec<history::Entry>> {
+    if rt_conf::cli_options().no_history {
+        return Ok(vec![]);
+    }
+    let entries = history::load(&get_hist_path()?)?;
+    // merged copies can contain duplicates and more entries than allowed
+    Ok(history::compact(entries, rt_conf::settings().history_max))
+}
+
+fn store_hist(entries: &[history::Entry]) -> Result<()> {
+    if rt_conf::cli_options().no_history || entries.is_empty() {
+        return Ok(());
+    }
+    let path = get_hist_path()?;
+    history::append(&path, entries)?;
+    history::prune(&path, rt_conf::settings().history_max)
+}
+
+/// Reads the value of a secret variable, without echoing it. An empty input is replaced by the
+/// default value, which isn't shown either
+fn query_secret(name: &str, default_val: &Option<String>, term: &Term) -> Result<String> {
+    term.write_str(&format!("{}: ", tr("value_for", &[("name", &name)])))?;
+    let line = term.read_secure_line()?;
+    match default_val {
+        Some(default_val) if line.is_empty() => Ok(default_val.clone()),
+        _ => Ok(line),
+    }
+}
+
+#[derive(Helper, Completer, Validator)]
 struct RlHelper {
     #[rustyline(Completer)]
     completer: FilenameCompleter,
 }
-impl Highlighter for RlHelper {}
 
+impl Highlighter for RlHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(style(hint).dim().to_string())
+    }
+}
+
+/// A hint that is only displayed, but can't be completed into the line
+struct UsageHint(String);
+
+impl Hint for UsageHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for RlHelper {
+    type Hint = UsageHint;
+
+    fn hint(&self, line: &str, _pos: usize, ctx: &rustyline::Context<'_>) -> Option<UsageHint> {
+        // screen readers would read hints as if they were input
+        let accessible = rt_conf::cli_options().accessible;
+        (!accessible && line.is_empty() && !ctx.history().is_empty())
+            .then(|| UsageHint(format!("  {}", tr("search_history_hint", &[]))))
+    }
+}
+
+/// Completes directories from the frecency ranking, and hints at the best match, which is
+/// used when the input isn't a directory itself
+#[derive(Helper, Validator)]
+struct DirHelper {
+    dirs: Vec<String>,
+    files: FilenameCompleter,
+}
+
+impl Highlighter for DirHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(style(hint).dim().to_string())
+    }
+}
+
+impl Hinter for DirHelper {
+    type Hint = UsageHint;
+
+    fn hint(&self, line: &str, _pos: usize, _ctx: &rustyline::Context<'_>) -> Option<UsageHint> {
+        let accessible = rt_conf::cli_options().accessible;
+        if accessible || line.trim().is_empty() || Path::new(line).is_dir() {
+            return None;
+        }
+        frecent::best_match(&self.dirs, line).map(|dir| UsageHint(format!("  -> {dir}")))
+    }
+}
+
+impl rustyline::completion::Completer for DirHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let candidates: Vec<_> = self
+            .dirs
+            .iter()
+            .filter(|dir| frecent::matches(dir, line))
+            .take(20)
+            .map(|dir| Pair {
+                display: dir.clone(),
+                replacement: dir.clone(),
+            })
+            .collect();
+        if candidates.is_empty() {
+            self.files.complete(line, pos, ctx)
+        } else {
+            Ok((0, candidates))
+        }
+    }
+}
+
+fn query_dir(name: &str) -> Result<String> {
+    let mut rl = rustyline::Editor::new()?;
+    rl.set_helper(Some(DirHelper {
+        dirs: frecent::ranked_dirs().context("ranking directories")?,
+        files: FilenameCompleter::new(),
+    }));
+    let line = rl.readline(&format!("{}: ", tr("directory_for", &[("name", &name)])))?;
+    let line = line.trim();
+    let dir = if line.is_empty() || Path::new(line).is_dir() {
+        line
+    } else {
+        let dirs = &rl.helper().unwrap().dirs;
+        frecent::best_match(dirs, line).unwrap_or(line)
+    };
+    if !dir.is_empty() && Path::new(dir).is_dir() && !rt_conf::cli_options().dry_run {
+        frecent::record_dir(Path::new(dir)).context("recording directory")?;
+    }
+    Ok(dir.to_string())
+}
+
+/// Reads the value of the variable `name`, with `hist` as history. The input starts with
+/// `prefill`, and an empty one is replaced by the default value
 fn query_env_var(
     name: &str,
     default_val: &Option<String>,
-    mut hist: Vec<String>,
-) -> Result<Vec<String>> {
+    prefill: Option<&str>,
+    hist: &[String],
+) -> Result<String> {
     let mut rl = rustyline::Editor::new()?;
     rl.set_helper(Some(RlHelper {
         completer: FilenameCompleter::new(),
     }));
-    for h in &hist {
-        rl.add_history_entry(h)?;
+    for h in hist {
+        rl.add_history_entry(h.as_str())?;
     }
-    let line = rl.readline(&format!(
-        "Value for {name}{default}: ",
-        default = if let Some(default_val) = default_val {
-            format!(" ({default_val})")
-        } else {
-            String::new()
-        }
-    ))?;
+    // up and down only cycle through entries that start with what was typed so far
+    rl.bind_sequence(
+        KeyEvent(KeyCode::Up, Modifiers::NONE),
+        Cmd::HistorySearchBackward,
+    );
+    rl.bind_sequence(
+        KeyEvent(KeyCode::Down, Modifiers::NONE),
+        Cmd::HistorySearchForward,
+    );
+    let prompt = if let Some(default_val) = default_val {
+        tr(
+            "value_for_default",
+            &[("name", &name), ("default", default_val)],
+        )
+    } else {
+        tr("value_for", &[("name", &name)])
+    };
+    let line =
+        rl.readline_with_initial(&format!("{prompt}: "), (prefill.unwrap_or_default(), ""))?;
 
     if line.is_empty() {
         if let Some(default_val) = default_val {
-            hist.push(default_val.to_string());
-            return Ok(hist);
+            return Ok(default_val.to_string());
+        }
+    }
+    Ok(line)
+}
+
+fn query_multi_select(
+    name: &str,
+    options: &[String],
+    separator: &str,
+    term: &Term,
+) -> Result<String> {
+    let prompt = tr("values_for", &[("name", &name)]);
+    let selection = if rt_conf::cli_options().accessible {
+        accessible::pick(&prompt, options, true)?
+    } else {
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(options)
+            .interact_on(term)?
+    };
+    Ok(selection
+        .into_iter()
+        .map(|i| options[i].as_str())
+        .collect::<Vec<_>>()
+        .join(separator))
+}
+
+/// Runs the helper command of a `from` variable, and lets the user pick one line of its
+/// output, with a fuzzy finder for `from fzf`
+fn query_from_helper(
+    name: &str,
+    helper: &StringExpr,
+    fuzzy: bool,
+    cmd: &parser::Command,
+    snippet_table: &SnippetTable,
+    vals: &[(String, String)],
+    term: &Term,
+) -> Result<String> {
+    let helper = helper
+        .resolve_with_vars(snippet_table, vals)
+        .context(format!("resolving {helper}"))?;
+    let shell = effective_shell(cmd);
+    let output = std::process::Command::new(&shell.name)
+        .args(shell.args_with(&cmd.script(&helper)))
+        .envs(vals.iter().map(|(name, val)| (name, val)))
+        .stdin(Stdio::null())
+        .output()
+        .context(format!("running {helper}"))?;
+    ensure!(
+        output.status.success(),
+        "{helper} failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let options: Vec<_> = stdout.lines().filter(|l| !l.trim().is_empty()).collect();
+    ensure!(
+        !options.is_empty(),
+        "{helper} didn't output anything to choose from"
+    );
+    let prompt = tr("value_for", &[("name", &name)]);
+    let selection = if rt_conf::cli_options().accessible {
+        accessible::pick(&prompt, &options, false)?[0]
+    } else if fuzzy {
+        FuzzySelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(&options)
+            .interact_on(term)?
+    } else {
+        Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(&options)
+            .default(0)
+            .interact_on(term)?
+    };
+    Ok(options[selection].to_string())
+}
+
+/// A pinned command, which is shown at the top of the root menu
+struct Favorite<'a> {
+    /// The key that selects it in the root menu
+    key: char,
+    /// The keys that lead to it
+    keys: String,
+    cmd: &'a parser::Command,
+}
+
+/// The pinned commands that still exist. They get the digits as keys, that no entry of the
+/// root menu starts with. If there are more, the others aren't shown
+fn favorites(root_node: &Node) -> Result<Vec<Favorite<'_>>> {
+    let Node::Menu(root) = root_node else {
+        return Ok(vec![]);
+    };
+    let free_keys = "1234567890"
+        .chars()
+        .filter(|c| !root.entries.keys().any(|keys| keys[0] == *c));
+    let pinned = state::pinned()?.into_iter().filter_map(|keys| {
+        let cmd = find_command(root_node, &keys)?;
+        Some((keys, cmd))
+    });
+    Ok(free_keys
+        .zip(pinned)
+        .map(|(key, (keys, cmd))| Favorite { key, keys, cmd })
+        .collect())
+}
+
+fn render_favorites(favorites: &[Favorite], out_proxy: &mut OutProxy) -> Result<()> {
+    writeln!(out_proxy, "{}", style(tr("favorites", &[])).dim())?;
+    for fav in favorites {
+        writeln!(
+            out_proxy,
+            "{}: {}{}",
+            fav.key,
+            command_label(fav.cmd),
+            vars_hint(fav.cmd) + &last_run_hint(fav.cmd, &fav.keys)?
+        )?;
+    }
+    writeln!(out_proxy)?;
+    Ok(())
+}
+
+/// How long ago the command was run, if it has the show_last_run setting
+fn last_run_hint(cmd: &parser::Command, keys: &str) -> Result<String> {
+    if !cmd.settings.contains(&CommandSetting::ShowLastRun) {
+        return Ok(String::new());
+    }
+    let text = match state::last_run(keys)? {
+        Some(time) => {
+            let age = round_age(time.elapsed().unwrap_or_default());
+            tr("last_run", &[("age", &humantime::format_duration(age))])
         }
+        None => tr("never_run", &[]),
+    };
+    Ok(format!(" {}", style(text).dim()))
+}
+
+/// Rounds down to the largest unit, so 1h 30min become 1h
+fn round_age(age: Duration) -> Duration {
+    let secs = age.as_secs();
+    let unit = [86400, 3600, 60, 1]
+        .into_iter()
+        .find(|unit| secs >= *unit)
+        .unwrap_or(1);
+    Duration::from_secs(secs - secs % unit)
+}
+
+/// The name of a command, in red if it is dangerous
+fn command_label(cmd: &parser::Command) -> String {
+    if cmd.danger() {
+        style(cmd).red().to_string()
+    } else {
+        themed(&rt_conf::settings().theme.command, "")
+            .apply_to(cmd)
+            .to_string()
+    }
+}
+
+/// The style of a part of the menu from the theme, or the default one
+fn themed(style: &Option<String>, default: &str) -> Style {
+    Style::from_dotted_str(style.as_deref().unwrap_or(default))
+}
+
+/// The variables a command will ask for, in the order they can be passed as arguments
+fn vars_hint(cmd: &parser::Command) -> String {
+    if cmd.env_vars.is_empty() {
+        return String::new();
     }
-    hist.push(line);
-    Ok(hist)
+    let names: Vec<_> = cmd.env_vars.iter().map(|v| v.name.as_str()).collect();
+    format!(" {}", style(format!("⟨{}⟩", names.join(", "))).dim())
 }
 
 fn render_menu(
     current_menu: &Menu,
+    menu_keys: &[char],
     remaining_path: &[char],
+    selected: Option<usize>,
     out_proxy: &mut OutProxy,
 ) -> Result<()> {
+    let menu_keys = String::from_iter(menu_keys);
     let remaining_path = String::from_iter(remaining_path);
     let keysection_len = current_menu
         .entries
         .keys()
         .map(|keys| keys.len())
         .max()
-        .expect("empty menu")
+        .unwrap_or(0)
         + 1;
-    for (keys, node) in &current_menu.entries {
+    let theme = &rt_conf::settings().theme;
+    if let Some(header) = &current_menu.header {
+        writeln!(
+            out_proxy,
+            "{}",
+            themed(&theme.title, "bold").apply_to(header)
+        )?;
+    }
+    for (i, (keys, node)) in current_menu.entries.iter().enumerate() {
         let keys = String::from_iter(keys);
-        let keys = if let Some(rest) = keys.strip_prefix(&remaining_path) {
-            format!(
-                "{}{}:",
-                style(&remaining_path).green().bright().bold(),
-                rest
-            )
-        } else {
-            format!("{keys}:")
+        let full_keys = format!("{menu_keys}{keys}");
+        let Some(rest) = keys.strip_prefix(&remaining_path) else {
+            // entries that can't be reached with the typed keys anymore are dimmed as a whole,
+            // so mistyped prefixes are obvious
+            let keys = format!("{keys}:");
+            let keys = pad_str(&keys, keysection_len, Alignment::Left, None);
+            writeln!(out_proxy, "{}", style(format!("{keys} {node}")).dim())?;
+            continue;
         };
+        let plain_keys = format!("{keys}:");
+        let keys = format!(
+            "{}{}",
+            themed(&theme.prefix, "green.bright.bold").apply_to(&remaining_path),
+            themed(&theme.keys, "").apply_to(format!("{rest}:"))
+        );
         let keys = pad_str(&keys, keysection_len, Alignment::Left, None);
-        writeln!(out_proxy, "{keys} {node}")?;
-    }
-    Ok(())
-}
-
-fn follow_path<'a>(node: &'a Node, input_chars: &[char], pos: usize) -> (Option<&'a Node>, usize) {
-    match node {
-        Node::Menu(this) => match find_submenus_for(this, input_chars, pos) {
-            Submenus::Exact(next_node, new_pos) => follow_path(next_node, input_chars, new_pos),
-            Submenus::Incomplete(new_pos) => (Some(node), new_pos),
-            Submenus::None => (None, 0),
-        },
-        Node::Command(_) => (Some(node), pos),
-    }
-}
-
-fn find_submenus_for<'a>(menu: &'a Menu, input_chars: &[char], pos: usize) -> Submenus<'a> {
-    // The base idea here is to compare the path with valid entries character wise.
-    // A vec of options of chars is used, so it can be set to none, if it doesn't match any more
-    // If it matches, the first char is removed. If after the removal of the char, the slice is
-    // empty, we have an exact match and return it.
-    // If we don't have any options left after checking the complete path, that means the path was
-    // invalid, otherwise it's not yet complete
-    let mut entries: Vec<_> = menu
-        .entries
-        .iter()
-        .map(|(chars, nodes)| (Some(chars.as_slice()), nodes))
-        .collect();
-    for (i, c) in input_chars[pos..].iter().enumerate() {
-        for (chars_opt, node) in &mut entries {
-            if let Some(chars) = chars_opt {
-                // this could panic, but empty menu entries aren't allowed and won't happen.
-                // and, since it is immediately checked whether an entry is empty uppon removal,
-                // we won't produce that state either
-                if chars[0] == *c {
-                    *chars = &chars[1..];
-                    if chars.is_empty() {
-                        return Submenus::Exact(node, pos + i + 1);
-                    }
-                } else {
-                    *chars_opt = None;
-                }
+        let (label, hint) = match node {
+            Node::Command(cmd) => (
+                command_label(cmd),
+                vars_hint(cmd) + &last_run_hint(cmd, &full_keys)?,
+            ),
+            Node::Menu(menu) => {
+                // the entries of a dynamic menu aren't known before it is entered
+                let n = match menu.dynamic {
+                    Some(_) => "…".to_string(),
+                    None => menu.entries.len().to_string(),
+                };
+                (
+                    themed(&theme.menu, "").apply_to(node).to_string(),
+                    format!(" {}", style(format!("({n})")).dim()),
+                )
             }
+        };
+        if selected == Some(i) {
+            // without the inner styles, whose resets would end the highlight
+            let keys = pad_str(&plain_keys, keysection_len, Alignment::Left, None);
+            let line = style(format!("{keys} {node}")).reverse();
+            writeln!(out_proxy, "{line}{hint}")?;
+        } else {
+            writeln!(out_proxy, "{keys} {label}{hint}")?;
         }
     }
-
-    if entries.iter().all(|(chars, _)| chars.is_none()) {
-        Submenus::None
-    } else {
-        Submenus::Incomplete(pos)
+    if current_menu.entries.is_empty() {
+        writeln!(out_proxy, "{}", style(tr("no_entries", &[])).dim())?;
     }
+    if let Some(footer) = &current_menu.footer {
+        writeln!(out_proxy, "{}", style(footer).dim())?;
+    }
+    Ok(())
 }
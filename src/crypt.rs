@@ -0,0 +1,234 @@
+//! Reading and writing of the state files that contain values of variables, which are
+//! encrypted with the `encrypt_history` setting. Files that aren't encrypted yet are read as
+//! they are, and encrypted when they are written the next time. Append-only files are
+//! encrypted line by line instead
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::parser::KeySource;
+use crate::rt_conf;
+
+/// The start of encrypted files
+const MAGIC: &[u8] = b"dtenc1";
+/// The start of encrypted lines, which are followed by the data in hex
+const LINE_PREFIX: &str = "dtenc1:";
+
+/// The content of the file, or None if it doesn't exist
+pub fn read(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let bytes = fs::read(path).context(format!("reading {}", path.display()))?;
+    let bytes = match bytes.strip_prefix(MAGIC) {
+        Some(data) => imp::decrypt(data).context(format!("decrypting {}", path.display()))?,
+        None => bytes,
+    };
+    String::from_utf8(bytes)
+        .context(format!("reading {}", path.display()))
+        .map(Some)
+}
+
+/// Writes the file, encrypted if the `encrypt_history` setting is set
+pub fn write(path: &Path, content: &str) -> Result<()> {
+    fs::create_dir_all(path.parent().context("Getting state file dir")?)
+        .context("creating state file dir")?;
+    let bytes = match rt_conf::settings().encrypt_history {
+        Some(source) => {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(imp::encrypt(source, content.as_bytes())?);
+            bytes
+        }
+        None => content.as_bytes().to_vec(),
+    };
+    fs::write(path, bytes).context(format!("writing {}", path.display()))
+}
+
+/// The line, encrypted if the `encrypt_history` setting is set
+pub fn seal_line(line: &str) -> Result<String> {
+    Ok(match rt_conf::settings().encrypt_history {
+        Some(source) => format!(
+            "{LINE_PREFIX}{}",
+            to_hex(&imp::encrypt(source, line.as_bytes())?)
+        ),
+        None => line.to_string(),
+    })
+}
+
+/// Decrypts a line written by `seal_line`. Lines that aren't encrypted are returned as they are
+pub fn open_line(line: &str) -> Result<String> {
+    let Some(hex) = line.strip_prefix(LINE_PREFIX) else {
+        return Ok(line.to_string());
+    };
+    let data = from_hex(hex).context("invalid encrypted line")?;
+    String::from_utf8(imp::decrypt(&data)?).context("invalid encrypted line")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(not(feature = "encryption"))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    use super::KeySource;
+    use crate::i18n::tr;
+
+    pub fn encrypt(_source: KeySource, _plain: &[u8]) -> Result<Vec<u8>> {
+        bail!(tr("no_encryption_support", &[]))
+    }
+
+    pub fn decrypt(_data: &[u8]) -> Result<Vec<u8>> {
+        bail!(tr("no_encryption_support", &[]))
+    }
+}
+
+#[cfg(feature = "encryption")]
+mod imp {
+    use std::sync::Mutex;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use argon2::Argon2;
+    use chacha20poly1305::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    use hashbrown::HashMap;
+    use once_cell::sync::{Lazy, OnceCell};
+
+    use super::{from_hex, to_hex, KeySource};
+    use crate::i18n::tr;
+
+    const SALT_LEN: usize = 16;
+    const NONCE_LEN: usize = 24;
+    const KEYRING_SERVICE: &str = "dotree";
+    const KEYRING_USER: &str = "history";
+
+    static KEYRING_KEY: OnceCell<[u8; 32]> = OnceCell::new();
+    static PASSPHRASE: OnceCell<String> = OnceCell::new();
+    /// The salt for new data. It's the one of the first data that was decrypted, so that the
+    /// lines of a file share it, and the key only needs to be derived once
+    static SALT: OnceCell<[u8; SALT_LEN]> = OnceCell::new();
+    /// The keys derived from the passphrase, by salt
+    static DERIVED_KEYS: Lazy<Mutex<HashMap<Vec<u8>, [u8; 32]>>> = Lazy::new(Default::default);
+
+    fn source_byte(source: KeySource) -> u8 {
+        match source {
+            KeySource::Keyring => 0,
+            KeySource::Passphrase => 1,
+        }
+    }
+
+    /// The key source, the salt if the key is derived from a passphrase, the nonce, and the
+    /// ciphertext
+    pub fn encrypt(source: KeySource, plain: &[u8]) -> Result<Vec<u8>> {
+        let mut res = vec![source_byte(source)];
+        let key = match source {
+            KeySource::Keyring => keyring_key()?,
+            KeySource::Passphrase => {
+                let salt = SALT.get_or_init(|| {
+                    let mut salt = [0; SALT_LEN];
+                    OsRng.fill_bytes(&mut salt);
+                    salt
+                });
+                res.extend(salt);
+                passphrase_key(salt)?
+            }
+        };
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        let ciphertext = cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| anyhow!("encryption failed"))?;
+        res.extend(nonce);
+        res.extend(ciphertext);
+        Ok(res)
+    }
+
+    /// Decrypts data written by `encrypt`. The key source is taken from the data, so files
+    /// can still be read after the setting changed
+    pub fn decrypt(data: &[u8]) -> Result<Vec<u8>> {
+        let (&source, rest) = data.split_first().context("empty file")?;
+        let (key, rest) = if source == source_byte(KeySource::Keyring) {
+            (keyring_key()?, rest)
+        } else if source == source_byte(KeySource::Passphrase) && rest.len() >= SALT_LEN {
+            let (salt, rest) = rest.split_at(SALT_LEN);
+            let _ = SALT.set(salt.try_into()?);
+            (passphrase_key(salt)?, rest)
+        } else {
+            bail!("unknown key source");
+        };
+        if rest.len() < NONCE_LEN {
+            bail!("the file is truncated");
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!(tr("wrong_key", &[])))
+    }
+
+    /// The key from the keyring of the OS. On the first use, a random one is created
+    fn keyring_key() -> Result<[u8; 32]> {
+        KEYRING_KEY
+            .get_or_try_init(|| {
+                let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+                match entry.get_password() {
+                    Ok(hex) => from_hex(&hex)
+                        .and_then(|key| key.try_into().ok())
+                        .context("invalid key in the keyring"),
+                    Err(keyring::Error::NoEntry) => {
+                        let key: [u8; 32] = XChaCha20Poly1305::generate_key(&mut OsRng).into();
+                        entry.set_password(&to_hex(&key))?;
+                        Ok(key)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            })
+            .copied()
+    }
+
+    /// Derives the key from the passphrase in $DT_PASSPHRASE, or the one the user enters
+    fn passphrase_key(salt: &[u8]) -> Result<[u8; 32]> {
+        let mut keys = DERIVED_KEYS.lock().unwrap();
+        if let Some(key) = keys.get(salt) {
+            return Ok(*key);
+        }
+        let passphrase = PASSPHRASE.get_or_try_init(|| match std::env::var("DT_PASSPHRASE") {
+            Ok(passphrase) => Ok::<_, anyhow::Error>(passphrase),
+            Err(_) => Ok(rpassword::prompt_password(
+                tr("passphrase_prompt", &[]) + " ",
+            )?),
+        })?;
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow!("deriving the key: {e}"))?;
+        keys.insert(salt.to_vec(), key);
+        Ok(key)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_round_trip() -> Result<()> {
+            std::env::set_var("DT_PASSPHRASE", "correct horse");
+            let data = encrypt(KeySource::Passphrase, b"host=db.internal")?;
+            assert!(!data.windows(4).any(|w| w == b"host"));
+            assert_eq!(decrypt(&data)?, b"host=db.internal");
+            let mut tampered = data.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(decrypt(&tampered).is_err());
+            assert_eq!(from_hex(&to_hex(&[7, 255])), Some(vec![7, 255]));
+            Ok(())
+        }
+    }
+}
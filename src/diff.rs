@@ -0,0 +1,285 @@
+//! Comparison of two parsed configs on the level of the tree, instead of the source
+use std::fmt::{Debug, Display};
+
+use crate::parser::{Command, Config, Menu, Node};
+
+/// A difference between two configs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        keys: String,
+        node: String,
+    },
+    Removed {
+        keys: String,
+        node: String,
+    },
+    /// A property of an entry, a setting, or a snippet changed
+    Changed {
+        what: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added { keys, node } => write!(f, "+ {keys}: {node}"),
+            Change::Removed { keys, node } => write!(f, "- {keys}: {node}"),
+            Change::Changed { what, old, new } => write!(f, "~ {what}: {old} -> {new}"),
+        }
+    }
+}
+
+/// All differences between `old` and `new`. Entries are identified by their keys, so an entry
+/// that got different keys is reported as removed and added
+pub fn diff(old: &Config, new: &Config) -> Vec<Change> {
+    let mut res = vec![];
+    let settings = [
+        (
+            "shell",
+            debug_str(&old.settings.shell_def),
+            debug_str(&new.settings.shell_def),
+        ),
+        (
+            "echo",
+            old.settings.echo_by_default.to_string(),
+            new.settings.echo_by_default.to_string(),
+        ),
+        (
+            "on_error",
+            opt_str(&old.settings.on_error),
+            opt_str(&new.settings.on_error),
+        ),
+        (
+            "lang",
+            opt_str(&old.settings.lang),
+            opt_str(&new.settings.lang),
+        ),
+        (
+            "encrypt_history",
+            debug_str(&old.settings.encrypt_history),
+            debug_str(&new.settings.encrypt_history),
+        ),
+    ];
+    for (name, old, new) in settings {
+        push_changed(&mut res, format!("setting {name}"), old, new);
+    }
+
+    let mut snippets: Vec<_> = old
+        .snippet_table
+        .keys()
+        .chain(new.snippet_table.keys())
+        .collect();
+    snippets.sort();
+    snippets.dedup();
+    for name in snippets {
+        push_changed(
+            &mut res,
+            format!("snippet {name}"),
+            opt_str(&old.snippet_table.get(name)),
+            opt_str(&new.snippet_table.get(name)),
+        );
+    }
+
+    diff_menus("", &old.menu, &new.menu, &mut res);
+    res
+}
+
+fn diff_menus(prefix: &str, old: &Menu, new: &Menu, res: &mut Vec<Change>) {
+    let mut keys: Vec<_> = old.entries.keys().chain(new.entries.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for k in keys {
+        let keys = format!("{prefix}{}", String::from_iter(k));
+        match (old.entries.get(k), new.entries.get(k)) {
+            (Some(Node::Menu(old)), Some(Node::Menu(new))) => {
+                push_changed(
+                    res,
+                    format!("{keys} menu"),
+                    Node::Menu(old.clone()).to_string(),
+                    Node::Menu(new.clone()).to_string(),
+                );
+                let source = |m: &Menu| {
+                    m.dynamic
+                        .as_ref()
+                        .map(|d| format!("{} as {}", d.exec_str, d.var))
+                };
+                push_changed(
+                    res,
+                    format!("{keys} dynmenu"),
+                    opt_str(&source(old)),
+                    opt_str(&source(new)),
+                );
+                if let (Some(old), Some(new)) = (&old.dynamic, &new.dynamic) {
+                    diff_commands(&keys, &old.command, &new.command, res);
+                }
+                diff_menus(&keys, old, new, res);
+            }
+            (Some(Node::Command(old)), Some(Node::Command(new))) => {
+                diff_commands(&keys, old, new, res)
+            }
+            (old, new) => {
+                if let Some(node) = old {
+                    res.push(Change::Removed {
+                        keys: keys.clone(),
+                        node: node.to_string(),
+                    });
+                }
+                if let Some(node) = new {
+                    res.push(Change::Added {
+                        keys,
+                        node: node.to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn diff_commands(keys: &str, old: &Command, new: &Command, res: &mut Vec<Change>) {
+    let var_names =
+        |cmd: &Command| -> Vec<String> { cmd.env_vars.iter().map(|v| v.name.clone()).collect() };
+    let bindings = |cmd: &Command| -> Vec<String> {
+        cmd.bound_vars
+            .iter()
+            .map(|(name, val)| format!("{name}={val}"))
+            .collect()
+    };
+    let fields = [
+        ("exec", old.exec_str.to_string(), new.exec_str.to_string()),
+        ("preamble", opt_str(&old.preamble), opt_str(&new.preamble)),
+        ("name", opt_str(&old.name), opt_str(&new.name)),
+        (
+            "localized names",
+            debug_str(&old.localized_names),
+            debug_str(&new.localized_names),
+        ),
+        (
+            "settings",
+            debug_str(&old.settings),
+            debug_str(&new.settings),
+        ),
+        (
+            "vars",
+            debug_str(&var_names(old)),
+            debug_str(&var_names(new)),
+        ),
+        (
+            "var definitions",
+            debug_str(&old.env_vars),
+            debug_str(&new.env_vars),
+        ),
+        ("action", opt_str(&old.action), opt_str(&new.action)),
+        ("shell", opt_str(&old.shell), opt_str(&new.shell)),
+        (
+            "echo toggle",
+            old.toggle_echo_setting.to_string(),
+            new.toggle_echo_setting.to_string(),
+        ),
+        ("on_error", opt_str(&old.on_error), opt_str(&new.on_error)),
+        ("finally", opt_str(&old.finally), opt_str(&new.finally)),
+        ("stdin", opt_str(&old.stdin), opt_str(&new.stdin)),
+        (
+            "path_prepend",
+            debug_str(&old.path_prepend),
+            debug_str(&new.path_prepend),
+        ),
+        (
+            "bound vars",
+            debug_str(&bindings(old)),
+            debug_str(&bindings(new)),
+        ),
+        (
+            "unset_env",
+            debug_str(&old.unset_env),
+            debug_str(&new.unset_env),
+        ),
+    ];
+    let mut vars_changed = false;
+    for (field, old, new) in fields {
+        // the definitions are only reported, if the names are the same
+        if field == "var definitions" && vars_changed {
+            continue;
+        }
+        vars_changed |= field == "vars" && old != new;
+        push_changed(res, format!("{keys} {field}"), old, new);
+    }
+}
+
+fn push_changed(res: &mut Vec<Change>, what: String, old: String, new: String) {
+    if old != new {
+        res.push(Change::Changed { what, old, new });
+    }
+}
+
+fn opt_str(val: &Option<impl Display>) -> String {
+    val.as_ref()
+        .map_or_else(|| "none".to_string(), |v| v.to_string())
+}
+
+fn debug_str(val: &impl Debug) -> String {
+    format!("{val:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use anyhow::Result;
+
+    const OLD: &str = r#"
+        snippet remote = "origin"
+
+        menu root {
+            g: git
+            v: "vpn up" - "wg-quick up wg0"
+        }
+
+        menu git {
+            p: "git push " + $remote
+            s: "git status"
+        }
+    "#;
+
+    const NEW: &str = r#"
+        echo off
+        snippet remote = "upstream"
+
+        menu root {
+            g: git
+            d: "docker ps"
+        }
+
+        menu git {
+            p: "git push " + $remote
+            s: cmd {
+                vars args
+                "git status $args"
+            }
+        }
+    "#;
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let changes: Vec<_> = diff(&parser::parse(OLD)?, &parser::parse(NEW)?)
+            .into_iter()
+            .map(|c| c.to_string())
+            .collect();
+        k9::snapshot!(
+            changes,
+            r#"
+[
+    "~ setting echo: true -> false",
+    "~ snippet remote: "origin" -> "upstream"",
+    "+ d: "docker ps"",
+    "~ gs exec: "git status" -> "git status $args"",
+    "~ gs vars: [] -> ["args"]",
+    "- v: vpn up",
+]
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! Dynamic menus, whose entries are the lines of the output of a command. The command runs
+//! when the menu is entered, and again, when it is entered the next time after it was left
+use std::process::{self, Stdio};
+
+use anyhow::{ensure, Context, Result};
+use indexmap::IndexMap;
+
+use crate::exec::{effective_shell, working_dir};
+use crate::parser::{Command, DynamicEntries, Menu, Node, SnippetTable, StringExpr};
+
+/// Generates the entries of the dynamic menus of a tree, and keeps track of the ones that were
+/// generated
+#[derive(Debug, Default)]
+pub struct Expander {
+    /// The keys that lead to the dynamic menus that have entries
+    expanded: Vec<Vec<char>>,
+}
+
+impl Expander {
+    /// Generates the entries of the dynamic menus `keys` lead through, unless they already
+    /// have them. The ones of the menus that were left are removed
+    pub fn expand(
+        &mut self,
+        tree: &mut Node,
+        keys: &[char],
+        snippet_table: &SnippetTable,
+    ) -> Result<()> {
+        let (left, kept) = self
+            .expanded
+            .drain(..)
+            .partition(|path| !keys.starts_with(path));
+        self.expanded = kept;
+        for path in left {
+            if let Some(menu) = menu_at(tree, &path) {
+                menu.entries.clear();
+            }
+        }
+        self.expand_from(tree, keys, 0, snippet_table)
+    }
+
+    fn expand_from(
+        &mut self,
+        node: &mut Node,
+        keys: &[char],
+        pos: usize,
+        snippet_table: &SnippetTable,
+    ) -> Result<()> {
+        let Node::Menu(menu) = node else {
+            return Ok(());
+        };
+        if let Some(dynamic) = &menu.dynamic {
+            if !self.expanded.iter().any(|path| path == &keys[..pos]) {
+                let lines = output_lines(dynamic, snippet_table)?;
+                menu.entries = entries(dynamic, &lines);
+                self.expanded.push(keys[..pos].to_vec());
+            }
+        }
+        let rest = &keys[pos..];
+        match menu.entries.iter_mut().find(|(k, _)| rest.starts_with(k)) {
+            Some((entry_keys, child)) => {
+                let pos = pos + entry_keys.len();
+                self.expand_from(child, keys, pos, snippet_table)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// The menu the keys lead to
+fn menu_at<'a>(node: &'a mut Node, keys: &[char]) -> Option<&'a mut Menu> {
+    let Node::Menu(menu) = node else {
+        return None;
+    };
+    if keys.is_empty() {
+        return Some(menu);
+    }
+    let (entry_keys, child) = menu.entries.iter_mut().find(|(k, _)| keys.starts_with(k))?;
+    let n = entry_keys.len();
+    menu_at(child, &keys[n..])
+}
+
+/// Runs the command of the dynamic menu like a helper of a variable, in the shell and working
+/// dir of the command of the entries. Empty lines are skipped
+fn output_lines(dynamic: &DynamicEntries, snippet_table: &SnippetTable) -> Result<Vec<String>> {
+    let exec_str = dynamic
+        .exec_str
+        .resolve(snippet_table)
+        .context(format!("resolving {}", dynamic.exec_str))?;
+    let cmd = &dynamic.command;
+    let shell = effective_shell(cmd);
+    let mut proc = process::Command::new(&shell.name);
+    proc.args(shell.args_with(&cmd.script(&exec_str)))
+        .stdin(Stdio::null());
+    if let Some(dir) = working_dir(Some(cmd))? {
+        proc.current_dir(dir);
+    }
+    let output = proc.output().context(format!("running {exec_str}"))?;
+    ensure!(
+        output.status.success(),
+        "{exec_str} failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// An entry for each line, named after it, that runs the command with the line bound to the
+/// variable
+fn entries(dynamic: &DynamicEntries, lines: &[String]) -> IndexMap<Vec<char>, Node> {
+    entry_keys(lines.len())
+        .into_iter()
+        .zip(lines)
+        .map(|(keys, line)| {
+            let mut cmd = Command {
+                name: Some(line.clone()),
+                localized_names: vec![],
+                ..dynamic.command.clone()
+            };
+            cmd.env_vars.retain(|v| v.name != dynamic.var);
+            cmd.bound_vars
+                .insert(0, (dynamic.var.clone(), StringExpr::plain(line)));
+            (keys, Node::Command(Box::new(cmd)))
+        })
+        .collect()
+}
+
+/// Keys for n entries: the letters, or combinations of them of the same length, if there are
+/// more entries than letters
+fn entry_keys(n: usize) -> Vec<Vec<char>> {
+    const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+    let base = LETTERS.len();
+    let mut len = 1;
+    while base.pow(len) < n {
+        len += 1;
+    }
+    (0..n)
+        .map(|mut i| {
+            let mut keys = vec![' '; len as usize];
+            for key in keys.iter_mut().rev() {
+                *key = LETTERS[i % base] as char;
+                i /= base;
+            }
+            keys
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn test_entry_keys() {
+        let keys: Vec<String> = entry_keys(3).iter().map(String::from_iter).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+        let keys = entry_keys(28);
+        assert_eq!(keys[0], ['a', 'a']);
+        assert_eq!(keys[25], ['a', 'z']);
+        assert_eq!(keys[27], ['b', 'b']);
+    }
+
+    #[test]
+    fn test_entries() -> Result<()> {
+        let conf = parser::parse(
+            r#"
+            menu root {
+                p: dynmenu "projects" {
+                    "ls ~/projects"
+                    as project: cmd {
+                        vars project, editor = "vim"
+                        "cd ~/projects/$project && $editor ."
+                    }
+                }
+            }
+            "#,
+        )?;
+        let Node::Menu(menu) = &conf.menu.entries[&vec!['p']] else {
+            panic!("expected a menu");
+        };
+        let lines = ["dotree".to_string(), "website".to_string()];
+        let entries = entries(menu.dynamic.as_ref().unwrap(), &lines);
+        let Node::Command(cmd) = &entries[&vec!['b']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.to_string(), "website");
+        assert_eq!(cmd.bound_vars[0].0, "project");
+        assert_eq!(cmd.bound_vars[0].1.as_plain_str(), Some("website"));
+        assert_eq!(cmd.env_vars.len(), 1);
+        assert_eq!(cmd.env_vars[0].name, "editor");
+        Ok(())
+    }
+}
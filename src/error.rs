@@ -0,0 +1,113 @@
+//! The error type of the library functions, also available as `dotree::Error`. Internally,
+//! errors are anyhow errors, they are converted at the public functions, so consumers can match
+//! on the kinds they care about, with `DotreeError::kind`
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// An error of dotree. Errors that don't have their own kind are `Other`
+#[derive(Debug, thiserror::Error)]
+pub enum DotreeError {
+    /// The config doesn't match the grammar. Line and column start at 1
+    #[error("{line}:{col}: {message}\n{excerpt}")]
+    ParseError {
+        line: usize,
+        col: usize,
+        message: String,
+        /// The line with the error, and a caret under the column
+        excerpt: String,
+    },
+    /// A menu or command that is referenced, but not defined
+    #[error("Undefined symbol: {0}")]
+    UndefinedSymbol(String),
+    /// A snippet that is referenced, but not defined
+    #[error("Undefined snippet: {0}")]
+    UndefinedSnippet(String),
+    /// A file includes itself, directly or through other files. Contains the chain of files,
+    /// which starts and ends with the same one
+    #[error("Include cycle: {}", .0.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> "))]
+    IncludeCycle(Vec<PathBuf>),
+    /// A snippet references itself, directly or through other snippets. Contains the chain of
+    /// snippets, which starts and ends with the same one
+    #[error("Snippet cycle: {}", .0.join(" -> "))]
+    SnippetCycle(Vec<String>),
+    /// The shell, a command should be run with, isn't installed
+    #[error("Shell not found: {0}")]
+    ShellNotFound(String),
+    #[error("Process didn't exit successfully: {status}")]
+    ExecFailed { status: ExitStatus },
+    /// The command ran longer than the timeout from the command line, and was killed
+    #[error("Command timed out after {}", humantime::format_duration(*.0))]
+    Timeout(Duration),
+    /// Reading or writing a file, or starting a process failed. `context` says what was done
+    #[error("{context}: {source}")]
+    Io { context: String, source: io::Error },
+    /// Any other error, and the ones of the other kinds that were given context. It is shown
+    /// with the whole chain of context
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = DotreeError> = std::result::Result<T, E>;
+
+impl DotreeError {
+    /// The error to match on: the error of another kind in the chain of `Other`, below the
+    /// context it was given, or else the error itself
+    pub fn kind(&self) -> &DotreeError {
+        match self {
+            DotreeError::Other(err) => err.chain().find_map(|e| e.downcast_ref()).unwrap_or(self),
+            _ => self,
+        }
+    }
+}
+
+impl From<anyhow::Error> for DotreeError {
+    /// Recovers a DotreeError, if `err` is one without context. Otherwise, it becomes `Other`,
+    /// and keeps the context. IO errors become `Io`, with the context as text
+    fn from(err: anyhow::Error) -> Self {
+        // downcasting would also see through the context
+        if err.chain().next().is_some_and(|e| e.is::<DotreeError>()) {
+            return err.downcast().unwrap();
+        }
+        if err.downcast_ref::<io::Error>().is_none() || err.chain().any(|e| e.is::<Self>()) {
+            return DotreeError::Other(err);
+        }
+        let context: Vec<_> = err
+            .chain()
+            .take_while(|e| !e.is::<io::Error>())
+            .map(ToString::to_string)
+            .collect();
+        let context = match context.is_empty() {
+            true => "IO error".to_string(),
+            false => context.join(": "),
+        };
+        let source = err.downcast::<io::Error>().unwrap();
+        DotreeError::Io { context, source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow() {
+        let read = std::fs::read("/does/not/exist").context("reading the config");
+        let err = DotreeError::from(read.context("loading").unwrap_err());
+        let DotreeError::Io { context, source } = &err else {
+            panic!("expected an IO error, got {err:?}");
+        };
+        assert_eq!(context, "loading: reading the config");
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+        let cycle = anyhow::Error::from(DotreeError::SnippetCycle(vec!["a".into(), "a".into()]));
+        let err = DotreeError::from(cycle.context("resolving $a"));
+        assert_eq!(err.to_string(), "resolving $a: Snippet cycle: a -> a");
+        assert!(matches!(err.kind(), DotreeError::SnippetCycle(_)));
+        let other = DotreeError::from(anyhow::anyhow!("something").context("doing"));
+        assert_eq!(other.to_string(), "doing: something");
+        assert!(matches!(other.kind(), DotreeError::Other(_)));
+    }
+}
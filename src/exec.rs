@@ -0,0 +1,684 @@
+//! Everything that is needed to run commands without a terminal: navigating the tree,
+//! resolving the shell and working directory, and spawning commands with their input
+use std::env;
+use std::ffi::OsString;
+use std::io::Read;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, ExitStatus, Output, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use hashbrown::HashMap;
+use once_cell::sync::Lazy;
+
+use crate::actions;
+use crate::error::{self, DotreeError};
+use crate::i18n::tr;
+use crate::parser::{self, Action, CommandSetting, Menu, Node, ShellDef, SnippetTable, VarKind};
+use crate::rt_conf;
+use crate::state;
+
+/// The PATH dotree was started with, which path_prepend adds to
+static INHERITED_PATH: Lazy<Option<OsString>> = Lazy::new(|| env::var_os("PATH"));
+
+#[derive(Debug, Clone)]
+enum Submenus<'a> {
+    Exact(&'a Node, usize),
+    Incomplete(usize),
+    None,
+}
+
+/// Follows the `input_chars` from `pos` on through the menus below `node`. Returns the node
+/// they lead to, or None if they don't match any entry, and the position after the keys of
+/// the last entry that was matched completely
+pub fn follow_path<'a>(
+    node: &'a Node,
+    input_chars: &[char],
+    pos: usize,
+) -> (Option<&'a Node>, usize) {
+    match node {
+        Node::Menu(this) => match find_submenus_for(this, input_chars, pos) {
+            Submenus::Exact(next_node, new_pos) => follow_path(next_node, input_chars, new_pos),
+            Submenus::Incomplete(new_pos) => (Some(node), new_pos),
+            Submenus::None => (None, 0),
+        },
+        Node::Command(_) => (Some(node), pos),
+    }
+}
+
+fn find_submenus_for<'a>(menu: &'a Menu, input_chars: &[char], pos: usize) -> Submenus<'a> {
+    // The base idea here is to compare the path with valid entries character wise.
+    // A vec of options of chars is used, so it can be set to none, if it doesn't match any more
+    // If it matches, the first char is removed. If after the removal of the char, the slice is
+    // empty, we have an exact match and return it.
+    // If we don't have any options left after checking the complete path, that means the path was
+    // invalid, otherwise it's not yet complete
+    let mut entries: Vec<_> = menu
+        .entries
+        .iter()
+        .map(|(chars, nodes)| (Some(chars.as_slice()), nodes))
+        .collect();
+    for (i, c) in input_chars[pos..].iter().enumerate() {
+        for (chars_opt, node) in &mut entries {
+            if let Some(chars) = chars_opt {
+                // this could panic, but empty menu entries aren't allowed and won't happen.
+                // and, since it is immediately checked whether an entry is empty uppon removal,
+                // we won't produce that state either
+                if chars[0] == *c {
+                    *chars = &chars[1..];
+                    if chars.is_empty() {
+                        return Submenus::Exact(node, pos + i + 1);
+                    }
+                } else {
+                    *chars_opt = None;
+                }
+            }
+        }
+    }
+
+    // an empty dynamic menu is a valid target, as long as no keys follow
+    if input_chars.len() > pos && entries.iter().all(|(chars, _)| chars.is_none()) {
+        Submenus::None
+    } else {
+        Submenus::Incomplete(pos)
+    }
+}
+
+/// Returns the command that is reached by typing `keys`, if they lead to one
+pub fn find_command<'a>(root_node: &'a Node, keys: &str) -> Option<&'a parser::Command> {
+    let keys: Vec<char> = keys.chars().collect();
+    match follow_path(root_node, &keys, 0) {
+        (Some(Node::Command(c)), _) => Some(c),
+        _ => None,
+    }
+}
+
+/// The shell that is used for commands without their own shell definition
+pub fn global_shell() -> ShellDef {
+    rt_conf::cli_options()
+        .shell
+        .clone()
+        .or_else(|| rt_conf::settings().shell_def.clone())
+        .unwrap_or_default()
+}
+
+/// The shell for `cmd`, which also runs its hooks and helpers. The command itself runs in
+/// `command_shell`
+pub(crate) fn effective_shell(cmd: &parser::Command) -> ShellDef {
+    match cmd.wsl() {
+        Some(distro) => wsl_shell(distro, wsl_inner_shell(cmd)),
+        None => host_shell(cmd),
+    }
+}
+
+/// The shell the command itself runs in: the `effective_shell`, but as root if it has the
+/// sudo setting. Its hooks and helpers don't run as root
+pub(crate) fn command_shell(cmd: &parser::Command) -> Result<ShellDef> {
+    if !cmd.sudo() {
+        return Ok(effective_shell(cmd));
+    }
+    Ok(match cmd.wsl() {
+        Some(distro) => wsl_shell(distro, sudo_shell(cmd, wsl_inner_shell(cmd), None)),
+        None if cfg!(windows) => windows_sudo_shell(host_shell(cmd)),
+        None => sudo_shell(cmd, host_shell(cmd), Some(child_path(cmd)?)),
+    })
+}
+
+fn host_shell(cmd: &parser::Command) -> ShellDef {
+    rt_conf::cli_options()
+        .shell
+        .clone()
+        .or_else(|| cmd.shell.clone())
+        .unwrap_or_else(global_shell)
+}
+
+/// The shell inside WSL, where the shell of the host doesn't exist
+fn wsl_inner_shell(cmd: &parser::Command) -> ShellDef {
+    cmd.shell.clone().unwrap_or_else(|| ShellDef {
+        name: "bash".into(),
+        args: vec!["-c".into()],
+    })
+}
+
+/// Runs `inner` as root via sudo. sudo resets the environment, so the variables of the
+/// command are kept explicitly, and the PATH is passed on with env, because sudo replaces it
+/// with its secure path, which lacks the directories of the user. Inside WSL, where the PATH
+/// of the host doesn't apply, `path` is None
+fn sudo_shell(cmd: &parser::Command, inner: ShellDef, path: Option<OsString>) -> ShellDef {
+    let names: Vec<&str> = cmd
+        .env_vars
+        .iter()
+        .map(|v| v.name.as_str())
+        .chain(cmd.bound_vars.iter().map(|(name, _)| name.as_str()))
+        .chain(["DT_COUNT"])
+        .collect();
+    let mut args = vec![format!("--preserve-env={}", names.join(",")), "--".into()];
+    if let Some(path) = path {
+        args.push("env".into());
+        args.push(format!("PATH={}", path.to_string_lossy()));
+    }
+    args.push(inner.name);
+    args.extend(inner.args);
+    ShellDef {
+        name: "sudo".into(),
+        args,
+    }
+}
+
+/// Whether `cmd` is started in a new tmux window or pane, because it has a tmux setting and
+/// dotree runs inside tmux. Then dotree doesn't wait for it, and doesn't know its result
+#[cfg(feature = "tui")]
+pub(crate) fn runs_in_tmux(cmd: &parser::Command) -> bool {
+    cmd.tmux().is_some() && env::var_os("TMUX").is_some()
+}
+
+/// Wraps `shell`, so the command is started in a new tmux window or pane, if it
+/// `runs_in_tmux`. The new window gets the environment of the tmux server, so the variables of
+/// `env` are passed on with -e, and the working dir with -c. Variables marked as secret would be
+/// visible on the command line of tmux, so commands with them can't be run like that
+#[cfg(feature = "tui")]
+pub(crate) fn tmux_shell(
+    cmd: &parser::Command,
+    shell: ShellDef,
+    env: &ChildEnv,
+) -> Result<ShellDef> {
+    let (Some(target), true) = (cmd.tmux(), runs_in_tmux(cmd)) else {
+        return Ok(shell);
+    };
+    if let Some(name) = tmux_secret(cmd, env) {
+        bail!(tr("tmux_secret", &[("name", &name)]));
+    }
+    let mut args = match target {
+        parser::TmuxTarget::Window => vec!["new-window".into(), "-n".into(), cmd.to_string()],
+        parser::TmuxTarget::Pane => vec!["split-window".into()],
+    };
+    let cwd = env::current_dir().context("getting cwd")?;
+    args.extend(["-c".into(), cwd.to_string_lossy().into_owned()]);
+    for (name, val) in &env.set {
+        args.extend(["-e".into(), format!("{name}={}", val.to_string_lossy())]);
+    }
+    args.push("--".into());
+    args.push(shell.name);
+    args.extend(shell.args);
+    Ok(ShellDef {
+        name: "tmux".into(),
+        args,
+    })
+}
+
+/// The first variable of `env` that is marked as secret. Names that only look like secrets are
+/// passed on, as the user chose to run the command in tmux
+#[cfg(feature = "tui")]
+fn tmux_secret<'a>(cmd: &parser::Command, env: &'a ChildEnv) -> Option<&'a str> {
+    env.set
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .find(|name| cmd.env_vars.iter().any(|v| v.name == *name && v.secret))
+}
+
+/// Whether the variable is marked as secret, or its name suggests that its value is one
+#[cfg(feature = "tui")]
+pub(crate) fn is_secret(cmd: &parser::Command, name: &str) -> bool {
+    let marked = cmd.env_vars.iter().any(|v| v.name == name && v.secret);
+    let upper = name.to_uppercase();
+    marked
+        || ["PASS", "SECRET", "TOKEN", "KEY"]
+            .iter()
+            .any(|part| upper.contains(part))
+}
+
+/// Runs `inner` elevated via the sudo of Windows, which asks for the permission with UAC. It
+/// can only pass on the whole environment
+fn windows_sudo_shell(inner: ShellDef) -> ShellDef {
+    let mut args = vec!["--preserve-env".to_string(), inner.name];
+    args.extend(inner.args);
+    ShellDef {
+        name: "sudo".into(),
+        args,
+    }
+}
+
+/// Runs `inner` inside WSL via wsl.exe
+fn wsl_shell(distro: Option<&str>, inner: ShellDef) -> ShellDef {
+    let mut args = vec![];
+    if let Some(distro) = distro {
+        args.extend(["-d".to_string(), distro.to_string()]);
+    }
+    args.push("--".into());
+    args.push(inner.name);
+    args.extend(inner.args);
+    ShellDef {
+        name: "wsl.exe".into(),
+        args,
+    }
+}
+
+/// What the processes of a command get in addition to the environment of dotree, which isn't
+/// changed for them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChildEnv {
+    /// the variables and their values, then the PATH with path_prepend, and WSLENV inside WSL
+    pub set: Vec<(String, OsString)>,
+    /// the variables of unset_env, which are removed after the others were set
+    pub unset: Vec<String>,
+}
+
+impl ChildEnv {
+    /// The environment of the processes of `cmd`, with the variables in `vals`. `wsl_extra`
+    /// are the ones of `vals` that aren't variables of `cmd`, but are shared with WSL too
+    pub(crate) fn new(
+        cmd: &parser::Command,
+        vals: &[(String, String)],
+        wsl_extra: &[&str],
+    ) -> Result<Self> {
+        let mut set: Vec<(String, OsString)> = vals
+            .iter()
+            .map(|(name, val)| (name.clone(), val.into()))
+            .collect();
+        set.push(("PATH".into(), child_path(cmd)?));
+        if let Some(wslenv) = wslenv(cmd, wsl_extra) {
+            set.push(("WSLENV".into(), wslenv.into()));
+        }
+        Ok(ChildEnv {
+            set,
+            unset: cmd.unset_env.clone(),
+        })
+    }
+
+    pub(crate) fn apply(&self, child: &mut process::Command) {
+        child.envs(self.set.iter().map(|(name, val)| (name, val)));
+        for name in &self.unset {
+            child.env_remove(name);
+        }
+    }
+}
+
+/// The value for WSLENV, which lists the env vars that are shared with WSL, if `cmd` runs
+/// inside it. It contains the vars of the command and `extra`, and the values of `from dirs`
+/// vars are translated between windows and linux paths
+pub(crate) fn wslenv(cmd: &parser::Command, extra: &[&str]) -> Option<String> {
+    cmd.wsl()?;
+    let mut entries: Vec<String> = env::var("WSLENV")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|e| !e.is_empty())
+        .map(String::from)
+        .collect();
+    let vars = cmd.env_vars.iter().map(|v| {
+        let flags = if v.kind == VarKind::Dir { "/p" } else { "" };
+        format!("{}{flags}", v.name)
+    });
+    let vars = vars.chain(cmd.bound_vars.iter().map(|(name, _)| name.clone()));
+    for entry in vars.chain(extra.iter().map(|e| e.to_string())) {
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    Some(entries.join(":"))
+}
+
+/// The values of the variables the entry binds. They are resolved before the other variables,
+/// so their defaults can use them
+pub(crate) fn bound_values(
+    cmd: &parser::Command,
+    snippet_table: &SnippetTable,
+) -> Result<Vec<(String, String)>> {
+    let mut vals = vec![];
+    for (name, val) in &cmd.bound_vars {
+        let val = val
+            .resolve_with_vars(snippet_table, &vals)
+            .context(format!("resolving the value of {name}"))?;
+        vals.push((name.clone(), val));
+    }
+    Ok(vals)
+}
+
+/// The PATH for `cmd`: the one dotree was started with, after the directories of its
+/// path_prepend. `~` is expanded, and relative directories are relative to its working dir
+pub(crate) fn child_path(cmd: &parser::Command) -> Result<OsString> {
+    let base = match working_dir(Some(cmd))? {
+        Some(dir) => dir,
+        None => env::current_dir().context("getting cwd")?,
+    };
+    // collecting the components drops the `.`s
+    let prepended = cmd.path_prepend.iter().map(|dir| {
+        base.join(expand_home(dir))
+            .components()
+            .collect::<PathBuf>()
+    });
+    let inherited = INHERITED_PATH.iter().flat_map(env::split_paths);
+    env::join_paths(prepended.chain(inherited)).context("joining PATH")
+}
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_default();
+    match path.strip_prefix('~') {
+        Some("") => home,
+        Some(rest) if rest.starts_with(['/', '\\']) => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The directory commands should be run in, if it differs from the current one. `cmd` can
+/// ask for the git root with its settings
+pub fn working_dir(cmd: Option<&parser::Command>) -> Result<Option<PathBuf>> {
+    let cli_options = rt_conf::cli_options();
+    if let Some(cwd) = &cli_options.cwd {
+        return Ok(Some(cwd.clone()));
+    }
+    let wants_git_root = cmd.is_some_and(|cmd| cmd.settings.contains(&CommandSetting::GitRootCwd));
+    if cli_options.git_root || wants_git_root {
+        return git_root().map(Some);
+    }
+    Ok(rt_conf::local_conf_dir())
+}
+
+/// The closest directory from the cwd to the file system root, which contains a `.git`
+fn git_root() -> Result<PathBuf> {
+    let cwd = env::current_dir().context("getting cwd")?;
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+        .ok_or(anyhow!("{} is not inside a git repository", cwd.display()))
+}
+
+/// Runs a command non-interactively and captures its output. Vars that are not contained
+/// in `vars` fall back to their default value, if they have none, an error is returned.
+/// `keys` lead to the command from the root menu.
+pub fn run_captured(
+    cmd: &parser::Command,
+    keys: &str,
+    vars: &HashMap<String, String>,
+    snippet_table: &SnippetTable,
+) -> error::Result<Output> {
+    Ok(capture(cmd, keys, vars, snippet_table)?)
+}
+
+fn capture(
+    cmd: &parser::Command,
+    keys: &str,
+    vars: &HashMap<String, String>,
+    snippet_table: &SnippetTable,
+) -> Result<Output> {
+    for name in vars.keys() {
+        ensure!(
+            cmd.env_vars.iter().any(|v| &v.name == name),
+            tr("unknown_variable", &[("name", name)])
+        );
+    }
+    let mut env_vals = bound_values(cmd, snippet_table)?;
+    for var in cmd.vars_in_prompt_order() {
+        let val = if let Some(val) = vars.get(&var.name) {
+            val.clone()
+        } else if let Some(default_val) = &var.value {
+            default_val
+                .resolve_with_vars(snippet_table, &env_vals)
+                .context(format!("resolving the default of {}", var.name))?
+        } else {
+            bail!(tr("missing_value", &[("name", &var.name)]));
+        };
+        env_vals.push((var.name.clone(), val));
+    }
+
+    if let Some(action) = cmd.action {
+        let arg = cmd
+            .exec_str
+            .resolve_with_vars(snippet_table, &env_vals)
+            .context(format!("resolving {}", cmd.exec_str))?;
+        let mut stdout = vec![];
+        match action {
+            Action::Print => stdout = format!("{arg}\n").into_bytes(),
+            _ => actions::run(action, &arg)?,
+        }
+        return Ok(Output {
+            status: ExitStatus::default(),
+            stdout,
+            stderr: vec![],
+        });
+    }
+    let shell = command_shell(cmd)?;
+    let arg = cmd
+        .exec_str
+        .resolve(snippet_table)
+        .context(format!("resolving {}", cmd.exec_str))?;
+    let input = resolve_stdin(cmd, snippet_table)?;
+    let mut child = std::process::Command::new(&shell.name);
+    child
+        .args(shell.args_with(&cmd.script(&arg)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    ChildEnv::new(cmd, &env_vals, &[])?.apply(&mut child);
+    if let Some(wd) = working_dir(Some(cmd))? {
+        child.current_dir(wd);
+    }
+    let _lock = if cmd.settings.contains(&CommandSetting::Exclusive) {
+        Some(state::lock_command(keys)?)
+    } else {
+        None
+    };
+    let child = spawn_with_input(&mut child, input.as_deref())?;
+    wait_with_output(child).context("running command")
+}
+
+/// Like Child::wait_with_output, but with the timeout from the command line
+fn wait_with_output(mut child: Child) -> Result<Output> {
+    let (done_tx, done_rx) = mpsc::channel();
+    let read_all = |pipe: Option<Box<dyn Read + Send>>| {
+        let buf = Arc::new(Mutex::new(vec![]));
+        let thread_buf = buf.clone();
+        let done_tx = done_tx.clone();
+        thread::spawn(move || {
+            let mut chunk = [0; 4096];
+            let res = pipe.map_or(Ok(()), |mut pipe| loop {
+                match pipe.read(&mut chunk) {
+                    Ok(0) => break Ok(()),
+                    Ok(n) => thread_buf.lock().unwrap().extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => break Err(e),
+                }
+            });
+            let _ = done_tx.send(res);
+        });
+        buf
+    };
+    let stdout = read_all(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read_all(child.stderr.take().map(|p| Box::new(p) as _));
+    let status = wait_with_timeout(&mut child)?;
+    // processes the command started in the background can keep the pipes open, so the rest
+    // of the output is only waited for briefly
+    for _ in 0..2 {
+        match done_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(res) => res.context("reading output")?,
+            Err(_) => break,
+        }
+    }
+    let take = |buf: Arc<Mutex<Vec<u8>>>| std::mem::take(&mut *buf.lock().unwrap());
+    Ok(Output {
+        status,
+        stdout: take(stdout),
+        stderr: take(stderr),
+    })
+}
+
+/// The exit code for a command that exited with `status`: its own one, or 128 plus the number
+/// of the signal that killed it, like shells report it
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Waits for the child to exit. If that takes longer than the timeout from the command line,
+/// the child is killed, together with the processes it started, and an error is returned
+pub(crate) fn wait_with_timeout(child: &mut Child) -> Result<ExitStatus> {
+    let res = wait_or_kill(child);
+    #[cfg(unix)]
+    reclaim_terminal(child.id());
+    res
+}
+
+fn wait_or_kill(child: &mut Child) -> Result<ExitStatus> {
+    let Some(timeout) = rt_conf::cli_options().timeout else {
+        return Ok(child.wait()?);
+    };
+    match poll_until(timeout, || child.try_wait())? {
+        Some(status) => Ok(status),
+        None => {
+            #[cfg(unix)]
+            kill_group(child.id()).context("killing command")?;
+            #[cfg(not(unix))]
+            child.kill().context("killing command")?;
+            child.wait()?;
+            Err(DotreeError::Timeout(timeout).into())
+        }
+    }
+}
+
+/// Kills the process group that the process with `pid` leads, which holds the processes it
+/// started, unless they left it
+#[cfg(unix)]
+pub(crate) fn kill_group(pid: u32) -> io::Result<()> {
+    // a negative pid stands for the group
+    if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Puts the command in its own process group, so it can be killed together with the
+/// processes it starts. If dotree's group is the foreground group of the terminal, the one of
+/// the command becomes it instead, so it can still read from the terminal, and gets ctrl+c
+#[cfg(unix)]
+fn own_process_group(child: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+    child.process_group(0);
+    if unsafe { libc::tcgetpgrp(0) != libc::getpgrp() } {
+        return;
+    }
+    // Safety: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        child.pre_exec(|| {
+            // stdin can be a pipe, but then the output can go to the terminal
+            if let Some(fd) = (0..3).find(|&fd| libc::isatty(fd) == 1) {
+                // a process outside of the foreground group is stopped when it changes it,
+                // unless it ignores SIGTTOU
+                libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+                libc::tcsetpgrp(fd, libc::getpgrp());
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Makes dotree's group the foreground group of the terminal again, if the one of the child
+/// with `pid` is it
+#[cfg(unix)]
+fn reclaim_terminal(pid: u32) {
+    unsafe {
+        if libc::tcgetpgrp(0) == pid as libc::pid_t {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(0, libc::getpgrp());
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+/// Calls `poll` until it returns a value, or `timeout` passed, in which case it returns None
+pub(crate) fn poll_until<T>(
+    timeout: Duration,
+    mut poll: impl FnMut() -> io::Result<Option<T>>,
+) -> io::Result<Option<T>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(res) = poll()? {
+            return Ok(Some(res));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+pub(crate) fn resolve_stdin(
+    cmd: &parser::Command,
+    snippet_table: &SnippetTable,
+) -> Result<Option<String>> {
+    cmd.stdin
+        .as_ref()
+        .map(|stdin| {
+            stdin
+                .resolve(snippet_table)
+                .context(format!("resolving {stdin}"))
+        })
+        .transpose()
+}
+
+/// Spawns `child` in its own process group, and writes `input` to its stdin, if it is given
+pub(crate) fn spawn_with_input(
+    child: &mut std::process::Command,
+    input: Option<&str>,
+) -> Result<std::process::Child> {
+    if input.is_some() {
+        child.stdin(Stdio::piped());
+    }
+    #[cfg(unix)]
+    own_process_group(child);
+    let mut child = match child.spawn() {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let shell = child.get_program().to_string_lossy().into_owned();
+            return Err(DotreeError::ShellNotFound(shell).into());
+        }
+        res => res.context("spawning command")?,
+    };
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        let input = input.to_string();
+        // from a thread, so a command that doesn't read all of its input can't block us
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    Ok(child)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_tmux_secret() -> Result<()> {
+        let conf = parser::parse(
+            r#"
+            menu root {
+                d: cmd {
+                    vars token secret, ssh_key_path
+                    "deploy"
+                }
+            }
+        "#,
+        )?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['d']] else {
+            panic!("expected a command");
+        };
+        let env = |names: &[&str]| ChildEnv {
+            set: names.iter().map(|n| (n.to_string(), "x".into())).collect(),
+            unset: vec![],
+        };
+        assert_eq!(tmux_secret(cmd, &env(&["ssh_key_path", "PATH"])), None);
+        assert!(is_secret(cmd, "ssh_key_path"));
+        assert_eq!(
+            tmux_secret(cmd, &env(&["ssh_key_path", "token"])),
+            Some("token")
+        );
+        Ok(())
+    }
+}
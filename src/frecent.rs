@@ -0,0 +1,138 @@
+//! Directories ranked by how often and how recently they were used, for `from dirs` variables.
+//! The ranking comes from zoxide if it is installed, otherwise dotree keeps its own
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::state;
+
+fn store_path() -> Result<PathBuf> {
+    Ok(state::state_dir()?.join("dt_dirs"))
+}
+
+fn load_store() -> Result<Map<String, Value>> {
+    let path = store_path()?;
+    if !path.exists() {
+        return Ok(Default::default());
+    }
+    let src = fs::read_to_string(path).context("reading directory store")?;
+    serde_json::from_str(&src).context("parsing directory store")
+}
+
+/// Runs zoxide, and returns its output. None if it isn't installed, or failed
+fn zoxide(args: &[&str]) -> Option<String> {
+    let output = Command::new("zoxide")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn now_secs() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// All known directories, the best ranked first
+pub fn ranked_dirs() -> Result<Vec<String>> {
+    if let Some(list) = zoxide(&["query", "--list"]) {
+        return Ok(list.lines().map(String::from).collect());
+    }
+    let now = now_secs()?;
+    let mut dirs: Vec<_> = load_store()?
+        .into_iter()
+        .filter(|(dir, _)| Path::new(dir).is_dir())
+        .filter_map(|(dir, entry)| {
+            let count = entry[0].as_f64()?;
+            let last_use = entry[1].as_u64()?;
+            Some((frecency(count, now.saturating_sub(last_use)), dir))
+        })
+        .collect();
+    dirs.sort_by(|a, b| b.0.total_cmp(&a.0));
+    Ok(dirs.into_iter().map(|(_, dir)| dir).collect())
+}
+
+/// Records a use of `dir`, which has to exist
+pub fn record_dir(dir: &Path) -> Result<()> {
+    let dir = dir
+        .canonicalize()
+        .context(format!("resolving {}", dir.display()))?;
+    let dir = dir
+        .to_str()
+        .ok_or(anyhow!("{} is not valid unicode", dir.display()))?;
+    if zoxide(&["add", dir]).is_some() {
+        return Ok(());
+    }
+    let mut store = load_store()?;
+    let count = store.get(dir).and_then(|e| e[0].as_f64()).unwrap_or(0.0);
+    store.insert(dir.to_string(), json!([count + 1.0, now_secs()?]));
+    let path = store_path()?;
+    fs::create_dir_all(path.parent().context("Getting state file dir")?)
+        .context("creating state file dir")?;
+    fs::write(path, Value::Object(store).to_string()).context("storing directory store")
+}
+
+/// The score zoxide uses: the number of uses, weighted by how long ago the last one was
+fn frecency(count: f64, age_secs: u64) -> f64 {
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+    const WEEK: u64 = 7 * DAY;
+    let factor = match age_secs {
+        age if age < HOUR => 4.0,
+        age if age < DAY => 2.0,
+        age if age < WEEK => 0.5,
+        _ => 0.25,
+    };
+    count * factor
+}
+
+/// Whether `dir` contains all whitespace separated terms of `query` in order, ignoring case,
+/// like zoxide matches
+pub fn matches(dir: &str, query: &str) -> bool {
+    let dir = dir.to_lowercase();
+    let query = query.to_lowercase();
+    let mut rest = dir.as_str();
+    for term in query.split_whitespace() {
+        match rest.find(term) {
+            Some(i) => rest = &rest[i + term.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The best ranked directory that matches `query`
+pub fn best_match<'a>(dirs: &'a [String], query: &str) -> Option<&'a str> {
+    dirs.iter()
+        .find(|dir| matches(dir, query))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching() {
+        let dirs = vec![
+            "/home/me/projects/dotree".to_string(),
+            "/home/me/Documents".to_string(),
+        ];
+        assert_eq!(best_match(&dirs, "me"), Some("/home/me/projects/dotree"));
+        assert_eq!(best_match(&dirs, "doc"), Some("/home/me/Documents"));
+        assert_eq!(
+            best_match(&dirs, "PROJ tree"),
+            Some("/home/me/projects/dotree")
+        );
+        assert_eq!(best_match(&dirs, "dotree proj"), None);
+        assert!(frecency(2.0, 0) > frecency(5.0, 60 * 60 * 24 * 30));
+    }
+}
@@ -0,0 +1,292 @@
+//! An alternative frontend, that shows the menus in the alternate screen, with a scrollable
+//! list of the entries, a preview of what the highlighted entry runs, and a status bar. The
+//! keys are the same as in the inline menu, and the tree is followed the same way
+use std::io::{self, stdout, Stdout, Write};
+
+use anyhow::Result;
+use console::{style, Term};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::core::{breadcrumb, get_input, ignore_ctrlc, parent_menu, run_command, Input};
+use crate::dynmenu;
+use crate::error;
+use crate::exec::follow_path;
+use crate::i18n::tr;
+use crate::inspect;
+use crate::parser::{CommandSetting, Menu, Node, SnippetTable};
+use crate::rt_conf;
+use crate::screen;
+
+type Screen = Terminal<CrosstermBackend<Stdout>>;
+
+/// Like `core::run`, but in the alternate screen. The screen is left for the commands, and
+/// entered again after repeat commands
+pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> error::Result<i32> {
+    let res = run_tree(root_node, input, snippet_table);
+    // also when there was an error, so it isn't printed to the alternate screen
+    leave()?;
+    Ok(res?)
+}
+
+fn run_tree(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> Result<i32> {
+    let mut input_chars: Vec<char> = match input.first() {
+        Some(input) => input.chars().collect(),
+        None => vec![],
+    };
+    let arg_vals = if input.len() > 1 { &input[1..] } else { &[] };
+
+    let term = Term::stdout();
+    // a copy, that gets the entries of the dynamic menus that are entered
+    let mut tree = root_node.clone();
+    let mut dynamic = dynmenu::Expander::default();
+    // see core::run_tree
+    ignore_ctrlc()?;
+
+    let mut screen = enter()?;
+    // the highlighted entry of the current menu
+    let mut selected = 0;
+    loop {
+        dynamic.expand(&mut tree, &input_chars, snippet_table)?;
+        let (found_node, input_pos) = follow_path(&tree, &input_chars, 0);
+        let Some(node) = found_node else {
+            input_chars.clear();
+            continue;
+        };
+        let menu = match node {
+            Node::Menu(menu) => menu,
+            Node::Command(c) => {
+                let keys = String::from_iter(&input_chars[..input_pos]);
+                let (menu, menu_keys) =
+                    parent_menu(&tree, &input_chars[..input_pos]).expect("commands are in menus");
+                // commands of a sticky menu run like repeat commands, and return to the menu
+                let mut cmd = (**c).clone();
+                if menu.sticky && !cmd.repeat() {
+                    cmd.settings.push(CommandSetting::Repeat);
+                }
+                leave()?;
+                let presets = &rt_conf::cli_options().var_presets;
+                // only repeat commands return to the menu
+                if let Some(code) =
+                    run_command(&cmd, &term, arg_vals, snippet_table, &keys, presets, None)?
+                {
+                    return Ok(code);
+                }
+                println!("{}", style(tr("tui_return", &[])).dim());
+                match screen::read_key() {
+                    Err(e) if e.kind() != io::ErrorKind::Interrupted => return Err(e.into()),
+                    _ => {}
+                }
+                selected = menu
+                    .entries
+                    .get_index_of(&input_chars[menu_keys..input_pos])
+                    .unwrap_or(0);
+                input_chars.truncate(menu_keys);
+                screen = enter()?;
+                continue;
+            }
+        };
+
+        let rest = &input_chars[input_pos..];
+        screen.draw(|frame| {
+            draw(
+                frame,
+                &tree,
+                menu,
+                &input_chars[..input_pos],
+                rest,
+                selected,
+                snippet_table,
+            )
+        })?;
+
+        match get_input(&mut input_chars)? {
+            Input::Continue => {
+                // the highlight follows the typed keys
+                let rest = &input_chars[input_pos.min(input_chars.len())..];
+                if let Some(i) = menu.entries.keys().position(|k| k.starts_with(rest)) {
+                    selected = i;
+                }
+            }
+            Input::Move(delta) if !menu.entries.is_empty() => {
+                input_chars.truncate(input_pos);
+                let n = menu.entries.len() as isize;
+                selected = (selected as isize + delta).rem_euclid(n) as usize;
+            }
+            Input::Select => {
+                if let Some((keys, _)) = menu.entries.get_index(selected) {
+                    input_chars.truncate(input_pos);
+                    input_chars.extend(keys);
+                    selected = 0;
+                }
+            }
+            Input::Back => {
+                if input_chars.len() > input_pos {
+                    input_chars.truncate(input_pos);
+                } else if let Some((parent, n)) = parent_menu(&tree, &input_chars) {
+                    // the highlight returns to the submenu that was left
+                    selected = parent.entries.get_index_of(&input_chars[n..]).unwrap_or(0);
+                    input_chars.truncate(n);
+                }
+            }
+            Input::Root => {
+                input_chars.clear();
+                selected = 0;
+            }
+            input @ (Input::Exit | Input::Idle) => {
+                leave()?;
+                if let Input::Idle = input {
+                    eprintln!("{}", style(tr("idle_exit", &[])).dim());
+                }
+                return Ok(0);
+            }
+            // pinning, editing, retrying and browsing are only offered by the inline menu
+            _ => {}
+        }
+    }
+}
+
+fn enter() -> Result<Screen> {
+    execute!(stdout(), EnterAlternateScreen, cursor::Hide)?;
+    Ok(Terminal::new(CrosstermBackend::new(stdout()))?)
+}
+
+fn leave() -> Result<()> {
+    execute!(stdout(), LeaveAlternateScreen, cursor::Show)?;
+    Ok(stdout().flush()?)
+}
+
+fn draw(
+    frame: &mut Frame,
+    tree: &Node,
+    menu: &Menu,
+    menu_keys: &[char],
+    rest: &[char],
+    selected: usize,
+    snippet_table: &SnippetTable,
+) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.size());
+    let [list, preview] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(main);
+    draw_list(frame, list, tree, menu, menu_keys, rest, selected);
+    if let Some((_, node)) = menu.entries.get_index(selected) {
+        let title = format!(" {node} ");
+        let preview_text = Paragraph::new(preview_lines(node, snippet_table))
+            .block(Block::bordered().title(title))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(preview_text, preview);
+    } else {
+        frame.render_widget(Block::bordered(), preview);
+    }
+
+    let mut names = breadcrumb(tree, menu_keys).join(" › ");
+    if !rest.is_empty() {
+        names = format!("{names} › {}", String::from_iter(rest));
+    }
+    let hint = tr("tui_hint", &[]);
+    let [left, right] = Layout::horizontal([
+        Constraint::Min(0),
+        Constraint::Length(hint.chars().count() as u16),
+    ])
+    .areas(status);
+    frame.render_widget(Paragraph::new(names).style(Style::new().bold()), left);
+    frame.render_widget(Paragraph::new(hint).style(Style::new().dim()), right);
+}
+
+fn draw_list(
+    frame: &mut Frame,
+    area: Rect,
+    tree: &Node,
+    menu: &Menu,
+    menu_keys: &[char],
+    rest: &[char],
+    selected: usize,
+) {
+    let title = match &menu.header {
+        Some(header) => format!(" {header} "),
+        None => format!(" {} ", breadcrumb(tree, menu_keys).last().unwrap()),
+    };
+    let block = Block::bordered().title(title);
+    if menu.entries.is_empty() {
+        let empty = Paragraph::new(tr("no_entries", &[])).style(Style::new().dim());
+        frame.render_widget(empty.block(block), area);
+        return;
+    }
+    let keys_len = menu.entries.keys().map(Vec::len).max().unwrap_or(0) + 1;
+    let items: Vec<_> = menu
+        .entries
+        .iter()
+        .map(|(keys, node)| {
+            let label = format!("{:keys_len$} {node}", String::from_iter(keys) + ":");
+            let hint = match node {
+                Node::Command(cmd) if !cmd.env_vars.is_empty() => {
+                    let names: Vec<_> = cmd.env_vars.iter().map(|v| v.name.as_str()).collect();
+                    format!(" ⟨{}⟩", names.join(", "))
+                }
+                Node::Command(_) => String::new(),
+                // the entries of a dynamic menu aren't known before it is entered
+                Node::Menu(m) if m.dynamic.is_some() => " (…)".to_string(),
+                Node::Menu(m) => format!(" ({})", m.entries.len()),
+            };
+            let line = Line::from(vec![
+                Span::raw(label),
+                Span::styled(hint, Style::new().dim()),
+            ]);
+            // entries that can't be reached with the typed keys anymore are dimmed
+            if keys.starts_with(rest) {
+                ListItem::new(line)
+            } else {
+                ListItem::new(line).style(Style::new().dim())
+            }
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// What the entry runs: the resolved command and the variables it asks for, or the entries
+/// of the menu
+fn preview_lines<'a>(node: &'a Node, snippet_table: &SnippetTable) -> Text<'a> {
+    match node {
+        Node::Command(cmd) => {
+            let mut lines: Vec<Line> = inspect::exec_string(cmd, snippet_table)
+                .lines()
+                .map(|l| Line::raw(l.to_string()))
+                .collect();
+            if !cmd.env_vars.is_empty() {
+                let names: Vec<_> = cmd.env_vars.iter().map(|v| v.name.as_str()).collect();
+                lines.push(Line::raw(""));
+                lines.push(Line::styled(
+                    format!("⟨{}⟩", names.join(", ")),
+                    Style::new().dim(),
+                ));
+            }
+            Text::from(lines)
+        }
+        Node::Menu(menu) => match &menu.dynamic {
+            Some(dynamic) if menu.entries.is_empty() => {
+                let exec_str = dynamic
+                    .exec_str
+                    .resolve(snippet_table)
+                    .unwrap_or_else(|_| dynamic.exec_str.to_string());
+                Text::styled(exec_str, Style::new().dim())
+            }
+            _ if menu.entries.is_empty() => Text::styled(tr("no_entries", &[]), Style::new().dim()),
+            _ => menu
+                .entries
+                .iter()
+                .map(|(keys, node)| Line::raw(format!("{}: {node}", String::from_iter(keys))))
+                .collect(),
+        },
+    }
+}
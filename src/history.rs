@@ -0,0 +1,220 @@
+//! The history of the values that were entered for variables. It's an append-only log with a
+//! line per value, so that copies that were synced between machines can be merged by
+//! concatenating them, without losing or clobbering entries
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::crypt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Seconds since the unix epoch
+    pub time: u64,
+    /// The variable the value was entered for. None for entries of the old format, which was
+    /// a plain list of values
+    pub var: Option<String>,
+    pub value: String,
+}
+
+impl Entry {
+    pub fn now(var: &str, value: &str) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Entry {
+            time,
+            var: Some(var.to_string()),
+            value: value.to_string(),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        json!({"t": self.time, "var": self.var, "val": self.value}).to_string()
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        Some(Entry {
+            time: value["t"].as_u64()?,
+            var: value["var"].as_str().map(String::from),
+            value: value["val"].as_str()?.to_string(),
+        })
+    }
+}
+
+/// All entries of the file, oldest first
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let src = fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+    let lines = src
+        .lines()
+        .map(crypt::open_line)
+        .collect::<Result<Vec<_>>>()
+        .context(format!("decrypting {}", path.display()))?;
+    Ok(parse(lines.iter().map(String::as_str)))
+}
+
+/// Lines of the old format become entries without a variable and time. The entries are sorted
+/// by time, as merged files aren't
+fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<Entry> {
+    let mut entries: Vec<_> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Entry::from_line(line).unwrap_or_else(|| Entry {
+                time: 0,
+                var: None,
+                value: line.to_string(),
+            })
+        })
+        .collect();
+    entries.sort_by_key(|e| e.time);
+    entries
+}
+
+/// The values that were entered for the variable, oldest first. It's unknown which variable
+/// the entries of the old format belong to, so they are included for every variable
+pub fn values_for(entries: &[Entry], var: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|e| e.var.as_deref().is_none_or(|v| v == var))
+        .map(|e| e.value.clone())
+        .collect()
+}
+
+/// The entries without the older ones of values that were entered again for the same
+/// variable, and with at most `max` entries per variable, the newest ones
+pub fn compact(entries: Vec<Entry>, max: Option<usize>) -> Vec<Entry> {
+    let mut seen = HashSet::new();
+    let mut counts: HashMap<Option<String>, usize> = HashMap::new();
+    let mut kept: Vec<_> = entries
+        .into_iter()
+        .rev()
+        .filter(|e| {
+            if !seen.insert((e.var.clone(), e.value.clone())) {
+                return false;
+            }
+            let count = counts.entry(e.var.clone()).or_default();
+            *count += 1;
+            max.is_none_or(|max| *count <= max)
+        })
+        .collect();
+    kept.reverse();
+    kept
+}
+
+/// Rewrites the file without the entries `compact` drops, if there are any. Unlike `append`,
+/// this replaces the file, which is only done when it shrinks
+pub fn prune(path: &Path, max: Option<usize>) -> Result<()> {
+    let entries = load(path)?;
+    let n = entries.len();
+    let kept = compact(entries, max);
+    if kept.len() == n {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for entry in &kept {
+        lines += &crypt::seal_line(&entry.to_line())?;
+        lines.push('\n');
+    }
+    // written next to it first, so the history isn't lost if writing fails
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, lines).context(format!("writing {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path).context(format!("replacing {}", path.display()))
+}
+
+/// Adds the entries to the end of the file
+pub fn append(path: &Path, entries: &[Entry]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(path.parent().context("Getting history file dir")?)
+        .context("creating history file dir")?;
+    let mut lines = String::new();
+    for entry in entries {
+        lines += &crypt::seal_line(&entry.to_line())?;
+        lines.push('\n');
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("opening {}", path.display()))?;
+    // a file of the old format might not end with a newline
+    if file.metadata()?.len() > 0 && !fs::read(path)?.ends_with(b"\n") {
+        lines.insert(0, '\n');
+    }
+    file.write_all(lines.as_bytes())
+        .context(format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merged_history() {
+        let a = Entry::now("host", "db.internal");
+        let b = Entry {
+            time: 5,
+            var: Some("port".into()),
+            value: "{\"not\": \"json\"}".into(),
+        };
+        // an old plain value, and two lines that were concatenated out of order
+        let src = format!("old value\n{}\n{}\n", a.to_line(), b.to_line());
+        let entries = parse(src.lines());
+        let old = Entry {
+            time: 0,
+            var: None,
+            value: "old value".into(),
+        };
+        assert_eq!(entries, vec![old, b, a]);
+    }
+
+    #[test]
+    fn test_values_for() {
+        let entries = parse(
+            [
+                "old value",
+                &Entry::now("branch", "main").to_line(),
+                &Entry::now("port", "8080").to_line(),
+                &Entry::now("branch", "dev").to_line(),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(values_for(&entries, "branch"), ["old value", "main", "dev"]);
+        assert_eq!(values_for(&entries, "port"), ["old value", "8080"]);
+        assert_eq!(values_for(&entries, "message"), ["old value"]);
+    }
+
+    #[test]
+    fn test_compact() {
+        let entry = |time, var: &str, value: &str| Entry {
+            time,
+            var: Some(var.into()),
+            value: value.into(),
+        };
+        let entries = vec![
+            entry(1, "branch", "main"),
+            entry(2, "branch", "dev"),
+            entry(3, "port", "80"),
+            entry(4, "branch", "main"),
+            entry(5, "branch", "fix"),
+        ];
+        let values = |entries: &[Entry]| -> Vec<String> {
+            entries.iter().map(|e| e.value.clone()).collect()
+        };
+        assert_eq!(
+            values(&compact(entries.clone(), None)),
+            ["dev", "80", "main", "fix"]
+        );
+        assert_eq!(values(&compact(entries, Some(2))), ["80", "main", "fix"]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Translations of the texts dotree shows. The catalogs are in the locales directory, and
+//! are selected by the `lang` setting, or the locale from the environment
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+use hashbrown::HashMap;
+use once_cell::sync::OnceCell;
+
+const FALLBACK: &str = "en";
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.txt")),
+    ("de", include_str!("../locales/de.txt")),
+];
+
+static CATALOG: OnceCell<Catalog> = OnceCell::new();
+static LANG: OnceCell<Option<String>> = OnceCell::new();
+
+type Catalog = HashMap<&'static str, &'static str>;
+
+fn parse_catalog(src: &'static str) -> Catalog {
+    src.lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .map(|(key, text)| (key.trim(), text.trim()))
+        .collect()
+}
+
+/// The catalog for `lang`, with the texts that it lacks taken from the fallback
+fn load(lang: &str) -> Option<Catalog> {
+    let (_, src) = CATALOGS.iter().find(|(name, _)| *name == lang)?;
+    let (_, fallback) = CATALOGS.iter().find(|(name, _)| *name == FALLBACK).unwrap();
+    let mut res = parse_catalog(fallback);
+    res.extend(parse_catalog(src));
+    Some(res)
+}
+
+/// The language from the first of LC_ALL, LC_MESSAGES and LANG that is set, e.g. de for
+/// de_DE.UTF-8
+fn env_lang() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|val| !val.is_empty())
+        .map(|val| {
+            val.split(['_', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+}
+
+fn env_catalog() -> Catalog {
+    env_lang()
+        .and_then(|lang| load(&lang))
+        .unwrap_or_else(|| load(FALLBACK).unwrap())
+}
+
+/// Selects the language, unless there is no `lang` setting, in which case the locale from
+/// the environment is used. Texts that are shown before this are always in that language
+pub fn init(lang: Option<&str>) -> Result<()> {
+    let catalog = match lang {
+        Some(lang) => load(lang).ok_or(anyhow!(
+            "unsupported language: {lang}, available are: {}",
+            CATALOGS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?,
+        None => env_catalog(),
+    };
+    let _ = CATALOG.set(catalog);
+    let _ = LANG.set(lang.map(String::from).or_else(env_lang));
+    Ok(())
+}
+
+/// The code of the selected language, like `de`. Until it is selected, it is the one from the
+/// environment
+pub fn lang() -> Option<&'static str> {
+    LANG.get_or_init(env_lang).as_deref()
+}
+
+/// The text `key` in the selected language, in which every `{name}` is replaced with the
+/// value of the argument called name
+pub fn tr(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let catalog = CATALOG.get_or_init(env_catalog);
+    let mut res = catalog.get(key).copied().unwrap_or(key).to_string();
+    for (name, val) in args {
+        res = res.replace(&format!("{{{name}}}"), &val.to_string());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut res: Vec<_> = regex::Regex::new(r"\{\w+\}")
+            .unwrap()
+            .find_iter(text)
+            .map(|m| m.as_str())
+            .collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn test_catalogs_are_complete() {
+        let fallback = parse_catalog(CATALOGS[0].1);
+        for (lang, src) in CATALOGS {
+            let catalog = parse_catalog(src);
+            for (key, text) in &fallback {
+                let translation = catalog
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{lang} lacks {key}"));
+                assert_eq!(
+                    placeholders(text),
+                    placeholders(translation),
+                    "{lang}: {key}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Non-interactive inspection of a parsed tree
+use anyhow::{Context, Result};
+#[cfg(feature = "tui")]
+use console::{pad_str, style, Alignment};
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::parser::{Command, Menu, Node, SnippetTable, VarKind};
+
+/// A command together with the keys that lead to it from the root menu
+#[derive(Debug, Clone)]
+pub struct Leaf<'a> {
+    pub keys: String,
+    pub command: &'a Command,
+}
+
+/// Returns all commands reachable from `menu`, sorted by their key path
+pub fn leaves(menu: &Menu) -> Vec<Leaf<'_>> {
+    fn collect<'a>(menu: &'a Menu, prefix: &str, res: &mut Vec<Leaf<'a>>) {
+        for (keys, node) in &menu.entries {
+            let keys = format!("{prefix}{}", String::from_iter(keys));
+            match node {
+                Node::Menu(m) => collect(m, &keys, res),
+                Node::Command(command) => res.push(Leaf { keys, command }),
+            }
+        }
+    }
+
+    let mut res = vec![];
+    collect(menu, "", &mut res);
+    res.sort_by(|a, b| a.keys.cmp(&b.keys));
+    res
+}
+
+/// Returns all leaves whose keys, name or command contain `term`, ignoring the case.
+/// If `is_regex` is true, `term` is interpreted as regular expression instead
+pub fn search<'a>(menu: &'a Menu, term: &str, is_regex: bool) -> Result<Vec<Leaf<'a>>> {
+    let matcher: Box<dyn Fn(&str) -> bool> = if is_regex {
+        let re = Regex::new(term).context("Compiling regex")?;
+        Box::new(move |s| re.is_match(s))
+    } else {
+        let term = term.to_lowercase();
+        Box::new(move |s| s.to_lowercase().contains(&term))
+    };
+
+    Ok(leaves(menu)
+        .into_iter()
+        .filter(|leaf| {
+            matcher(&leaf.keys)
+                || leaf.command.localized_name().is_some_and(&matcher)
+                || matcher(&leaf.command.exec_str.to_string())
+        })
+        .collect())
+}
+
+/// The entries of `menu` and its submenus, as lines that are indented by their level, in the
+/// order of the config. Below `depth` levels, menus are shown with the number of their
+/// entries instead
+pub fn tree(menu: &Menu, depth: Option<usize>) -> Vec<String> {
+    fn collect(menu: &Menu, level: usize, depth: Option<usize>, res: &mut Vec<String>) {
+        for (keys, node) in &menu.entries {
+            let line = format!("{}{}: {node}", "  ".repeat(level), String::from_iter(keys));
+            match node {
+                Node::Menu(m) if depth.is_some_and(|d| level + 1 >= d) => {
+                    res.push(format!("{line} ({} hidden)", m.entries.len()));
+                }
+                Node::Menu(m) => {
+                    res.push(line);
+                    collect(m, level + 1, depth, res);
+                }
+                Node::Command(_) => res.push(line),
+            }
+        }
+    }
+
+    let mut res = vec![];
+    collect(menu, 0, depth, &mut res);
+    res
+}
+
+/// Returns the leaves whose key path starts with `partial`, to complete it in a shell
+pub fn complete<'a>(menu: &'a Menu, partial: &str) -> Vec<Leaf<'a>> {
+    leaves(menu)
+        .into_iter()
+        .filter(|leaf| leaf.keys.starts_with(partial))
+        .collect()
+}
+
+/// Prints the leaves as one `keys<TAB>name` per line, which shells can use as completions
+/// with descriptions
+pub fn print_completions(leaves: &[Leaf]) {
+    for leaf in leaves {
+        println!("{}\t{}", leaf.keys, leaf.command);
+    }
+}
+
+/// The command that is run, with resolved snippets, and prefixed with its action, if it has
+/// one. If a snippet isn't defined, the unresolved expression is used
+pub fn exec_string(command: &Command, snippet_table: &SnippetTable) -> String {
+    let exec_str = command
+        .exec_str
+        .resolve(snippet_table)
+        .unwrap_or_else(|_| command.exec_str.to_string());
+    match command.action {
+        Some(action) => format!("{action} {exec_str}"),
+        None => exec_str,
+    }
+}
+
+/// The leaves as lines of `keys → name → command`, for grepping and cheatsheets. Commands
+/// without a name are shown with their command as name, like in the menu
+pub fn list(leaves: &[Leaf], snippet_table: &SnippetTable) -> Vec<String> {
+    leaves
+        .iter()
+        .map(|leaf| {
+            format!(
+                "{} → {} → {}",
+                leaf.keys,
+                leaf.command,
+                exec_string(leaf.command, snippet_table)
+            )
+        })
+        .collect()
+}
+
+/// The leaves as JSON array of objects with `keys`, `name` and `exec`. `name` is null for
+/// commands without a name
+pub fn list_json(leaves: &[Leaf], snippet_table: &SnippetTable) -> Value {
+    leaves
+        .iter()
+        .map(|leaf| {
+            json!({
+                "keys": leaf.keys,
+                "name": leaf.command.localized_name(),
+                "exec": exec_string(leaf.command, snippet_table),
+            })
+        })
+        .collect()
+}
+
+/// Describes the variables of `cmd` in the order their values can be passed as arguments, as
+/// tab separated name, kind, default value, and whether it is required or optional, which it
+/// is if it has a default value
+pub fn describe_vars(cmd: &Command) -> Vec<String> {
+    cmd.env_vars
+        .iter()
+        .map(|var| {
+            let kind = match var.kind {
+                VarKind::Input => "input",
+                VarKind::MultiSelect { .. } => "multi_select",
+                VarKind::Fzf { .. } => "fzf",
+                VarKind::Pick { .. } => "pick",
+                VarKind::Dir => "dir",
+            };
+            let default = var
+                .value
+                .as_ref()
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            let required = if var.value.is_some() {
+                "optional"
+            } else {
+                "required"
+            };
+            format!("{}\t{kind}\t{default}\t{required}", var.name)
+        })
+        .collect()
+}
+
+/// Prints leaves in a table of key path, name and command
+#[cfg(feature = "tui")]
+pub fn print_leaves(leaves: &[Leaf]) {
+    let keys_len = leaves.iter().map(|l| l.keys.len()).max().unwrap_or(0) + 1;
+    for leaf in leaves {
+        let keys = pad_str(&leaf.keys, keys_len, Alignment::Left, None);
+        if let Some(name) = leaf.command.localized_name() {
+            println!(
+                "{keys} {name} {}",
+                style(format!("({})", leaf.command.exec_str)).dim()
+            );
+        } else {
+            println!("{keys} {}", leaf.command.exec_str);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const CONF: &str = r#"
+        menu root {
+            g: git
+            v: "vpn up" - "wg-quick up wg0"
+        }
+
+        menu git {
+            am: "amend" - "git commit --amend --no-edit"
+            s: "git status"
+        }
+    "#;
+
+    fn keys_of(leaves: Vec<Leaf>) -> Vec<String> {
+        leaves.into_iter().map(|l| l.keys).collect()
+    }
+
+    #[test]
+    fn test_search() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        k9::snapshot!(
+            keys_of(search(&conf.menu, "VPN", false)?),
+            r#"
+[
+    "v",
+]
+"#
+        );
+        k9::snapshot!(
+            keys_of(search(&conf.menu, "git", false)?),
+            r#"
+[
+    "gam",
+    "gs",
+]
+"#
+        );
+        k9::snapshot!(
+            keys_of(search(&conf.menu, "^g.$", true)?),
+            r#"
+[
+    "gs",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_vars() -> Result<()> {
+        let conf = parser::parse(
+            r#"
+            menu root {
+                d: cmd {
+                    vars env = "prod", region, dir from dirs
+                    "deploy $env $region $dir"
+                }
+            }
+            "#,
+        )?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['d']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            describe_vars(cmd),
+            [
+                "env\tinput\t\"prod\"\toptional",
+                "region\tinput\t\trequired",
+                "dir\tdir\t\trequired",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        assert_eq!(
+            tree(&conf.menu, None),
+            ["g: git", "  am: amend", "  s: \"git status\"", "v: vpn up"]
+        );
+        assert_eq!(
+            tree(&conf.menu, Some(1)),
+            ["g: git (2 hidden)", "v: vpn up"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list() -> Result<()> {
+        let conf = parser::parse(
+            r#"
+            snippet host = "example.com"
+            menu root {
+                s: "ssh " + $host
+                w: "website" - open-url "https://" + $host
+            }
+            "#,
+        )?;
+        let leaves = leaves(&conf.menu);
+        assert_eq!(
+            list(&leaves, &conf.snippet_table),
+            [
+                "s → \"ssh \" + host → ssh example.com",
+                "w → website → open-url https://example.com",
+            ]
+        );
+        assert_eq!(
+            list_json(&leaves, &conf.snippet_table),
+            json!([
+                {"keys": "s", "name": null, "exec": "ssh example.com"},
+                {"keys": "w", "name": "website", "exec": "open-url https://example.com"},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        assert_eq!(keys_of(complete(&conf.menu, "")), ["gam", "gs", "v"]);
+        assert_eq!(keys_of(complete(&conf.menu, "g")), ["gam", "gs"]);
+        assert_eq!(keys_of(complete(&conf.menu, "ga")), ["gam"]);
+        assert!(complete(&conf.menu, "x").is_empty());
+        Ok(())
+    }
+}
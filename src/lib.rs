@@ -1,4 +1,44 @@
+#[cfg(feature = "tui")]
+pub mod accessible;
+pub mod actions;
+pub mod bootstrap;
+#[cfg(feature = "tui")]
+pub mod browse;
+#[cfg(feature = "tui")]
+pub mod completions;
+#[cfg(feature = "tui")]
 pub mod core;
+pub mod crypt;
+pub mod diff;
+pub mod dynmenu;
+pub mod error;
+pub mod exec;
+pub mod frecent;
+#[cfg(feature = "tui")]
+pub mod fullscreen;
+pub mod history;
+pub mod i18n;
+pub mod inspect;
+#[cfg(feature = "formats")]
+pub mod loader;
+#[cfg(feature = "tui")]
+pub mod logs;
+pub mod migrate;
+#[cfg(feature = "tui")]
 pub mod outproxy;
 pub mod parser;
 pub mod rt_conf;
+#[cfg(feature = "tui")]
+pub mod screen;
+#[cfg(unix)]
+pub mod serve;
+#[cfg(feature = "tui")]
+pub mod spinner;
+pub mod state;
+pub mod timings;
+#[cfg(feature = "tui")]
+pub mod watch;
+
+#[cfg(feature = "tui")]
+pub use crate::core::{run_config, RunOptions};
+pub use crate::error::{DotreeError as Error, Result};
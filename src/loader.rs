@@ -0,0 +1,181 @@
+//! Loads configs that are written in TOML or YAML, instead of the dotree language. They have the
+//! schema that `dt dump` prints, but all fields except the ones of dynamic menus are optional,
+//! and strings without snippets can be written as plain strings
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::error;
+use crate::parser::{self, Config, Menu, Node};
+
+/// The names of config files, in the order they are searched for in a directory
+pub const FILE_NAMES: [&str; 4] = ["dotree.dt", "dotree.toml", "dotree.yaml", "dotree.yml"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dt,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// By the extension of the file. Unknown extensions are treated as .dt, like before there
+    /// were other formats
+    pub fn of(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Dt,
+        }
+    }
+}
+
+/// The first of the `FILE_NAMES` that exists in the directory
+pub fn find_in(dir: &Path) -> Option<PathBuf> {
+    FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+}
+
+/// Parses the source of the config at `path`, in the format its extension stands for
+pub fn load(src: &str, path: &Path) -> error::Result<Config> {
+    match Format::of(path) {
+        Format::Dt => parser::parse_file(src, path),
+        Format::Toml => Ok(from_toml(src)?),
+        Format::Yaml => Ok(from_yaml(src)?),
+    }
+}
+
+pub fn from_toml(src: &str) -> Result<Config> {
+    let config = toml::from_str(src).context("Parsing TOML config")?;
+    check(config)
+}
+
+pub fn from_yaml(src: &str) -> Result<Config> {
+    let config = serde_yaml::from_str(src).context("Parsing YAML config")?;
+    check(config)
+}
+
+/// Does the checks of the parser that the schema can't express, and names the root menu
+fn check(mut config: Config) -> Result<Config> {
+    if config.menu.name.is_empty() {
+        config.menu.name = "root".into();
+    }
+    check_menu(&config.menu)?;
+    Ok(config)
+}
+
+fn check_menu(menu: &Menu) -> Result<()> {
+    let name = &menu.name;
+    for (i, (keys, node)) in menu.entries.iter().enumerate() {
+        let keys_str = String::from_iter(keys);
+        ensure!(!keys.is_empty(), "menu {name} has an entry without keys");
+        for (other, _) in menu.entries.iter().take(i) {
+            let (short, long) = if keys.starts_with(other) {
+                (String::from_iter(other), &keys_str)
+            } else if other.starts_with(keys) {
+                (keys_str.clone(), &String::from_iter(other))
+            } else {
+                continue;
+            };
+            bail!("entry {long} of menu {name} can't be reached, because {short} is a prefix");
+        }
+        if let Node::Menu(submenu) = node {
+            ensure!(
+                !submenu.name.is_empty(),
+                "the submenu {keys_str} of menu {name} has no name"
+            );
+            check_menu(submenu)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CommandSetting;
+
+    const TOML: &str = r#"
+        [settings]
+        echo_by_default = false
+
+        [snippet_table]
+        greet = "echo hi"
+
+        [menu.entries.g.Command]
+        name = "greet"
+        exec_str = [{ Symbol = "greet" }, { String = " there" }]
+        settings = ["Repeat"]
+
+        [menu.entries.s.Menu]
+        name = "sub"
+        entries.l.Command = { exec_str = "ls" }
+    "#;
+
+    #[test]
+    fn test_toml() -> Result<()> {
+        let conf = from_toml(TOML)?;
+        assert_eq!(conf.menu.name, "root");
+        assert!(!conf.settings.echo_by_default);
+        let Node::Command(greet) = &conf.menu.entries[&vec!['g']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            greet.exec_str.resolve(&conf.snippet_table)?,
+            "echo hi there"
+        );
+        assert_eq!(greet.settings, [CommandSetting::Repeat]);
+        let Node::Menu(sub) = &conf.menu.entries[&vec!['s']] else {
+            panic!("expected a menu");
+        };
+        let Node::Command(ls) = &sub.entries[&vec!['l']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(ls.exec_str.resolve(&conf.snippet_table)?, "ls");
+
+        let unreachable = TOML.replace("[menu.entries.s.", "[menu.entries.gs.");
+        assert_eq!(
+            from_toml(&unreachable).unwrap_err().to_string(),
+            "entry gs of menu root can't be reached, because g is a prefix"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml() -> Result<()> {
+        let src = "
+            menu:
+              entries:
+                b: !Command
+                  name: build
+                  exec_str: cargo build
+                t: !Command
+                  exec_str: cargo test
+        ";
+        let conf = from_yaml(src)?;
+        let names: Vec<_> = conf.menu.entries.values().map(Node::to_string).collect();
+        assert_eq!(names, ["build", "\"cargo test\""]);
+        assert!(conf.settings.echo_by_default);
+
+        // what dt dump prints can be read again
+        let dumped = serde_yaml::to_string(&parser::parse(
+            r#"menu root { a: "echo" - "echo " + $x } snippet x = "y""#,
+        )?)?;
+        let conf = from_yaml(&dumped)?;
+        let Node::Command(echo) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(echo.exec_str.resolve(&conf.snippet_table)?, "echo y");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_of() {
+        assert_eq!(Format::of(Path::new("a/dotree.toml")), Format::Toml);
+        assert_eq!(Format::of(Path::new("dotree.yml")), Format::Yaml);
+        assert_eq!(Format::of(Path::new("dotree.dt")), Format::Dt);
+        assert_eq!(Format::of(Path::new("config")), Format::Dt);
+    }
+}
@@ -0,0 +1,287 @@
+//! Logs of the output of commands with the `log` setting, and a viewer for them
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use console::{pad_str, style, Alignment, Term};
+use crossterm::terminal;
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+use crate::error::DotreeError;
+use crate::exec;
+use crate::i18n::tr;
+use crate::rt_conf;
+use crate::state;
+
+const KEYS_PREFIX: &str = "# keys: ";
+const NAME_PREFIX: &str = "# command: ";
+const STARTED_PREFIX: &str = "# started: ";
+const RESULT_PREFIX: &str = "# result: ";
+
+/// A past run of a command, as read from its log file
+#[derive(Debug, Clone)]
+pub struct LoggedRun {
+    pub path: PathBuf,
+    pub keys: String,
+    pub name: String,
+    pub started: String,
+    /// None if the run didn't finish (yet)
+    pub result: Option<String>,
+}
+
+pub fn log_dir() -> Result<PathBuf> {
+    Ok(state::state_dir()?.join("dotree_logs"))
+}
+
+/// Runs `prog` with `args` and the environment `env`, in a pseudo terminal, so it keeps its
+/// colors, and writes its output into a new log file. If `show_output` is true, the
+/// output is also written to stdout, and stdin is forwarded to the command, unless `input` is
+/// given, which is written instead.
+pub(crate) fn run_logged(
+    prog: &str,
+    args: &[&str],
+    env: &exec::ChildEnv,
+    keys: &str,
+    name: &str,
+    show_output: bool,
+    input: Option<&str>,
+) -> Result<ExitStatus> {
+    let dir = log_dir()?;
+    fs::create_dir_all(&dir).context("creating log dir")?;
+    let now = SystemTime::now();
+    let stamp = now.duration_since(UNIX_EPOCH)?.as_millis();
+    let mut file = File::create(dir.join(format!("{stamp}.log"))).context("creating log file")?;
+    writeln!(file, "{KEYS_PREFIX}{keys}")?;
+    writeln!(file, "{NAME_PREFIX}{name}")?;
+    writeln!(
+        file,
+        "{STARTED_PREFIX}{}",
+        humantime::format_rfc3339_seconds(now)
+    )?;
+    let file = Arc::new(Mutex::new(file));
+
+    let (rows, cols) = Term::stdout().size();
+    let pty = native_pty_system()
+        .openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .context("opening pty")?;
+    let mut builder = CommandBuilder::new(prog);
+    builder.args(args);
+    for (name, val) in &env.set {
+        builder.env(name, val);
+    }
+    for name in &env.unset {
+        builder.env_remove(name);
+    }
+    builder.cwd(std::env::current_dir().context("getting cwd")?);
+    let mut child = pty
+        .slave
+        .spawn_command(builder)
+        .context("spawning command")?;
+    // the output only ends when all handles to the slave side are closed
+    drop(pty.slave);
+
+    let stdout: Box<dyn Write + Send> = if show_output {
+        Box::new(io::stdout())
+    } else {
+        Box::new(io::sink())
+    };
+    let reader = pty.master.try_clone_reader().context("reading pty")?;
+    let out_thread = tee(reader, stdout, file.clone());
+    let mut writer = pty.master.take_writer().context("writing pty")?;
+    let raw_mode = show_output && input.is_none() && io::stdin().is_terminal();
+    if raw_mode {
+        // the pty handles line editing and signals, so every key goes there unprocessed
+        terminal::enable_raw_mode().context("enabling raw mode")?;
+    }
+    let input = input.map(String::from);
+    thread::spawn(move || {
+        // dropping the writer sends an end of file, so without shown output, the command
+        // gets no input, like with /dev/null
+        if let Some(input) = input {
+            writer.write_all(input.as_bytes())?;
+        } else if show_output {
+            io::copy(&mut io::stdin(), &mut writer)?;
+        }
+        io::Result::Ok(())
+    });
+    let status = match rt_conf::cli_options().timeout {
+        None => child.wait().context("waiting for command"),
+        Some(timeout) => match exec::poll_until(timeout, || child.try_wait()) {
+            Ok(Some(status)) => Ok(status),
+            Ok(None) => {
+                // the command leads its own session, and so a process group, which also holds
+                // the processes it started
+                match child.process_id() {
+                    #[cfg(unix)]
+                    Some(pid) => exec::kill_group(pid),
+                    _ => child.kill(),
+                }
+                .context("killing command")?;
+                child.wait()?;
+                Err(DotreeError::Timeout(timeout).into())
+            }
+            Err(e) => Err(e).context("waiting for command"),
+        },
+    };
+    if raw_mode {
+        terminal::disable_raw_mode().context("disabling raw mode")?;
+    }
+    let status = to_std_status(status?);
+    drop(pty.master);
+    out_thread
+        .join()
+        .map_err(|_| anyhow!("output thread panicked"))?
+        .context("copying output")?;
+
+    writeln!(file.lock().unwrap(), "\n{RESULT_PREFIX}{status}")?;
+    Ok(status)
+}
+
+/// portable_pty only keeps the name of the signal that killed a command, and reports the exit
+/// code 1 in that case, which is what the returned status contains then
+fn to_std_status(status: portable_pty::ExitStatus) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw((status.exit_code() as i32) << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(status.exit_code())
+    }
+}
+
+fn tee(
+    mut src: impl Read + Send + 'static,
+    mut dst: Box<dyn Write + Send>,
+    file: Arc<Mutex<File>>,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        loop {
+            // once the command exited, reading from the pty fails on some systems,
+            // instead of returning 0
+            let n = src.read(&mut buf).unwrap_or(0);
+            if n == 0 {
+                return Ok(());
+            }
+            dst.write_all(&buf[..n])?;
+            dst.flush()?;
+            file.lock().unwrap().write_all(&buf[..n])?;
+        }
+    })
+}
+
+/// Returns all logged runs, the most recent first. If `keys` is given, only runs of the
+/// command with these keys are returned.
+pub fn list_runs(keys: Option<&str>) -> Result<Vec<LoggedRun>> {
+    let dir = log_dir()?;
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).context("reading log dir")? {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) == Some("log") {
+            paths.push(path);
+        }
+    }
+    // the file names are timestamps of equal length, so sorting them sorts by time
+    paths.sort();
+    paths.reverse();
+
+    let mut res = vec![];
+    for path in paths {
+        let run = read_run(path)?;
+        if keys.is_none() || keys == Some(run.keys.as_str()) {
+            res.push(run);
+        }
+    }
+    Ok(res)
+}
+
+fn read_run(path: PathBuf) -> Result<LoggedRun> {
+    let mut run = LoggedRun {
+        path,
+        keys: String::new(),
+        name: String::new(),
+        started: String::new(),
+        result: None,
+    };
+    let file = File::open(&run.path).context(format!("opening {}", run.path.display()))?;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some(keys) = line.strip_prefix(KEYS_PREFIX) {
+            run.keys = keys.to_string();
+        } else if let Some(name) = line.strip_prefix(NAME_PREFIX) {
+            run.name = name.to_string();
+        } else if let Some(started) = line.strip_prefix(STARTED_PREFIX) {
+            run.started = started.to_string();
+        } else if let Some(result) = line.strip_prefix(RESULT_PREFIX) {
+            run.result = Some(result.to_string());
+        }
+    }
+    Ok(run)
+}
+
+/// Prints the logged runs, and, if running in a terminal, asks which one to open in the pager
+pub fn show_logs(keys: Option<&str>) -> Result<()> {
+    let runs = list_runs(keys)?;
+    if runs.is_empty() {
+        println!("{}", tr("no_logged_runs", &[]));
+        return Ok(());
+    }
+    let keys_len = runs.iter().map(|r| r.keys.len()).max().unwrap() + 1;
+    for (i, run) in runs.iter().enumerate() {
+        let keys = pad_str(&run.keys, keys_len, Alignment::Left, None);
+        let result = run.result.as_deref().unwrap_or("unfinished");
+        println!(
+            "{:>3}  {}  {keys} {} {}",
+            i + 1,
+            run.started,
+            run.name,
+            style(format!("({result})")).dim()
+        );
+    }
+
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Ok(());
+    }
+    print!("{} ", tr("open_run", &[]));
+    io::stdout().flush()?;
+    let line = term.read_line()?;
+    if line.trim().is_empty() {
+        return Ok(());
+    }
+    let run = line
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| runs.get(i.wrapping_sub(1)))
+        .ok_or(anyhow!("Invalid selection: {line}"))?;
+    open_in_pager(&run.path)
+}
+
+fn open_in_pager(path: &std::path::Path) -> Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut pager = pager.split_whitespace();
+    let prog = pager.next().ok_or(anyhow!("PAGER is empty"))?;
+    Command::new(prog)
+        .args(pager)
+        .arg(path)
+        .status()
+        .context(format!("running pager {prog}"))?;
+    Ok(())
+}
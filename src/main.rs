@@ -1,65 +1,472 @@
-use std::{env, fs, path::PathBuf, process::exit};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::exit,
+    time::Duration,
+};
 
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use console::Term;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{builder::ValueHint, CommandFactory, Parser, Subcommand, ValueEnum};
+use console::{style, Term};
+use dialoguer::{theme::ColorfulTheme, MultiSelect};
+#[cfg(unix)]
+use dotree::serve;
 use dotree::{
-    core::run,
-    parser::{self, Config, Node, ShellDef},
-    rt_conf,
+    accessible, actions, bootstrap, completions,
+    core::{self, run},
+    diff, exec, fullscreen, i18n, inspect,
+    loader::{self, Format},
+    logs, migrate,
+    parser::{self, Config, Menu, Node, ShellDef, SnippetTable},
+    rt_conf, screen, state, timings,
 };
 use log::debug;
 
+/// How many of the most frequent commands `dt init --from-history` proposes
+const MAX_INIT_CANDIDATES: usize = 30;
+
 fn main() -> Result<()> {
     pretty_env_logger::init();
-    let args = Args::parse();
+    let mut args = Args::parse();
+    set_colors(args.color);
+    if args.timings {
+        timings::enable();
+    }
+    if let Some(socket) = args.served_by.take() {
+        let (conf_path, local_conf_dir, config) =
+            fetch_served_config(&socket).context("getting the config from the server")?;
+        return run_menu(args, conf_path, local_conf_dir, config);
+    }
 
     let (conf_path, local_conf_dir) = if args.local_mode {
         if let Some(path) = search_local_config().context("Searching local config")? {
             let conf_dir = path.parent().unwrap().to_owned();
             (path, Some(conf_dir))
         } else {
-            eprintln!("Couldnt find a local config");
+            eprintln!("{}", i18n::tr("no_local_config", &[]));
             exit(1);
         }
-    } else if let Some(p) = args.conf_file {
+    } else if let Some(p) = args.conf_file.take() {
         (p, None)
     } else {
-        (
-            get_default_config_dir()
-                .ok_or(anyhow!("Couldn't determin config dir"))?
-                .join("dotree.dt"),
-            None,
-        )
+        let conf_dir = get_default_config_dir().ok_or(anyhow!("Couldn't determin config dir"))?;
+        let path = loader::find_in(&conf_dir).unwrap_or_else(|| conf_dir.join("dotree.dt"));
+        (path, None)
     };
 
+    if let Some(SubCommand::Init {
+        from_history,
+        local,
+    }) = args.command
+    {
+        let conf_path = if local {
+            let cwd = env::current_dir().context("getting cwd")?;
+            loader::find_in(&cwd).unwrap_or_else(|| cwd.join("dotree.dt"))
+        } else {
+            conf_path
+        };
+        return init_config(&conf_path, from_history, args.accessible);
+    }
+    if let Some(SubCommand::ShellInit { kind }) = args.command {
+        print!("{}", shell_init_script(kind));
+        return Ok(());
+    }
+    if let Some(SubCommand::Completions { kind }) = args.command {
+        print!("{}", completion_script(kind));
+        return Ok(());
+    }
+
     if !conf_path.exists() {
         eprintln!(
-            "Expected config file at {}, but couldn't find it. Please create one.",
-            conf_path.display()
+            "{}",
+            i18n::tr("no_config", &[("path", &conf_path.display())])
         );
         exit(1);
     }
 
-    let conf_src = fs::read_to_string(conf_path).context("loading config")?;
+    let conf_src =
+        timings::measure("loading", || fs::read_to_string(&conf_path)).context("loading config")?;
+    if let Some(SubCommand::Diff { old, new }) = &args.command {
+        return diff_configs(old, new.as_deref().unwrap_or(&conf_path));
+    }
+    if let Some(SubCommand::Migrate { .. } | SubCommand::Lint) = args.command {
+        if Format::of(&conf_path) != Format::Dt {
+            bail!(
+                "{} isn't a .dt config, only those can be linted and migrated",
+                conf_path.display()
+            );
+        }
+    }
+    if let Some(SubCommand::Migrate { write }) = args.command {
+        return migrate_config(&conf_path, &conf_src, write);
+    }
+    if let Some(SubCommand::Lint) = args.command {
+        // linting works on the source, so it can also report problems that prevent parsing
+        return lint(&conf_path, &conf_src);
+    }
+    let config = loader::load(&conf_src, &conf_path).context("Parsing Config")?;
+    if let Some(SubCommand::Dump { format }) = args.command {
+        return dump_config(&config, format);
+    }
+    run_menu(args, conf_path, local_conf_dir, config)
+}
+
+/// Runs the subcommand, or shows the menu of the parsed config
+fn run_menu(
+    args: Args,
+    conf_path: PathBuf,
+    local_conf_dir: Option<PathBuf>,
+    config: Config,
+) -> Result<()> {
     let Config {
         menu,
         mut settings,
         snippet_table,
-    } = parser::parse(&conf_src).context("Parsing Config")?;
+    } = config;
 
     let env_shell = get_shell_from_env().context("Getting Shell from Env")?;
     settings.shell_def = settings.shell_def.or(env_shell);
+    i18n::init(settings.lang.as_deref()).context("Selecting language")?;
     debug!("settings:\n{settings:#?}");
-    rt_conf::init(local_conf_dir, settings);
+    // commands change the working directory, so a relative one would be relative to the one
+    // of the last command
+    let cwd = match args.cwd {
+        Some(cwd) => Some(env::current_dir().context("getting cwd")?.join(cwd)),
+        None => None,
+    };
+    let cli_options = rt_conf::CliOptions {
+        var_presets: args.var_presets,
+        shell: args.shell,
+        cwd,
+        git_root: args.git_root,
+        no_history: args.no_history,
+        history_file: args.history_file,
+        accessible: args.accessible,
+        yes: args.yes,
+        timeout: args.timeout,
+        verbose: args.verbose,
+        dry_run: args.dry_run,
+    };
+    let ctx = rt_conf::RuntimeContext {
+        conf_path: conf_path.clone(),
+        local_conf_dir,
+        settings,
+        cli_options,
+    };
+    // for the subcommands and frontends that don't take the context explicitly
+    rt_conf::init(ctx.clone());
+    timings::report();
+
+    if let Some(command) = args.command {
+        return run_subcommand(command, &conf_path, menu, &snippet_table);
+    }
+    if let Some(keys) = args.list_vars {
+        let root = Node::Menu(menu);
+        let cmd = exec::find_command(&root, &keys).ok_or(anyhow!("No command at {keys}"))?;
+        for line in inspect::describe_vars(cmd) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    if args.retry_failed {
+        exit(core::retry_failed(&Node::Menu(menu), &snippet_table)?);
+    }
+
+    if args.accessible {
+        exit(accessible::run(
+            &Node::Menu(menu),
+            &args.input,
+            &snippet_table,
+        )?);
+    }
+    screen::init();
+    if args.tui {
+        exit(fullscreen::run(
+            &Node::Menu(menu),
+            &args.input,
+            &snippet_table,
+        )?);
+    }
+    screen::hide_cursor()?;
+    let res = run(&ctx, &Node::Menu(menu), &args.input, &snippet_table);
+    // otherwise, the menu showed it before it returned
+    if res.is_err() {
+        if let Err(e) = screen::show_cursor() {
+            eprintln!("Warning, couldn't show cursor again:\n{e:?}");
+        }
+    }
+    exit(res?)
+}
+
+fn run_subcommand(
+    command: SubCommand,
+    conf_path: &Path,
+    root: Menu,
+    snippet_table: &SnippetTable,
+) -> Result<()> {
+    match command {
+        #[cfg(unix)]
+        SubCommand::Serve { socket, terminal } => {
+            let socket = socket.unwrap_or_else(serve::default_socket_path);
+            let popup_cmd = get_popup_cmd(terminal, &socket)?;
+            let config = Config {
+                menu: root,
+                settings: rt_conf::settings().clone(),
+                snippet_table: snippet_table.clone(),
+            };
+            serve::serve(&socket, &config, popup_cmd.as_deref())
+        }
+        #[cfg(unix)]
+        SubCommand::Popup { socket } => {
+            let socket = socket.unwrap_or_else(serve::default_socket_path);
+            serve::request(&socket, "popup", serde_json::Value::Null)?;
+            Ok(())
+        }
+        SubCommand::Config { item } => {
+            print_config_item(item, conf_path)?;
+            Ok(())
+        }
+        SubCommand::Info => print_info(conf_path),
+        SubCommand::Lint => unreachable!("lint is handled before parsing"),
+        SubCommand::Diff { .. } => unreachable!("diff is handled before parsing"),
+        SubCommand::Migrate { .. } => unreachable!("migrate is handled before parsing"),
+        SubCommand::Init { .. } => unreachable!("init is handled before parsing"),
+        SubCommand::Dump { .. } => unreachable!("dump is handled before the settings are merged"),
+        SubCommand::ShellInit { .. } => unreachable!("shell-init is handled before parsing"),
+        SubCommand::Completions { .. } => unreachable!("completions are handled before parsing"),
+        SubCommand::Logs { keys } => logs::show_logs(keys.as_deref()),
+        SubCommand::Search { term, regex } => {
+            inspect::print_leaves(&inspect::search(&root, &term, regex)?);
+            Ok(())
+        }
+        SubCommand::List { json } => {
+            let leaves = inspect::leaves(&root);
+            if json {
+                println!("{:#}", inspect::list_json(&leaves, snippet_table));
+            } else {
+                for line in inspect::list(&leaves, snippet_table) {
+                    println!("{line}");
+                }
+            }
+            Ok(())
+        }
+        SubCommand::Tree { depth } => {
+            for line in inspect::tree(&root, depth) {
+                println!("{line}");
+            }
+            Ok(())
+        }
+        SubCommand::Complete { partial } => {
+            inspect::print_completions(&inspect::complete(&root, &partial));
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        SubCommand::Serve { .. } | SubCommand::Popup { .. } => {
+            Err(anyhow!("dt serve is only supported on unix systems"))
+        }
+    }
+}
+
+fn diff_configs(old: &Path, new: &Path) -> Result<()> {
+    let parse = |path: &Path| {
+        let src = fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+        loader::load(&src, path).context(format!("parsing {}", path.display()))
+    };
+    let changes = diff::diff(&parse(old)?, &parse(new)?);
+    for change in &changes {
+        let line = match change {
+            diff::Change::Added { .. } => style(change).green(),
+            diff::Change::Removed { .. } => style(change).red(),
+            diff::Change::Changed { .. } => style(change).yellow(),
+        };
+        println!("{line}");
+    }
+    if !changes.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
 
-    let term = Term::stdout();
-    term.hide_cursor()?;
-    let res = run(&Node::Menu(menu), &args.input, &snippet_table);
-    if let Err(e) = term.show_cursor() {
-        eprintln!("Warning, couldn't show cursor again:\n{e:?}");
+fn dump_config(config: &Config, format: DumpFormat) -> Result<()> {
+    match format {
+        DumpFormat::Json => println!("{}", serde_json::to_string_pretty(config)?),
+        DumpFormat::Yaml => print!("{}", serde_yaml::to_string(config)?),
     }
-    res
+    Ok(())
+}
+
+fn migrate_config(conf_path: &Path, conf_src: &str, write: bool) -> Result<()> {
+    let (migrated, applied) = migrate::migrate(conf_src, conf_path)?;
+    for description in &applied {
+        eprintln!("migration: {description}");
+    }
+    if !write {
+        print!("{migrated}");
+    } else if !applied.is_empty() {
+        let backup = conf_path.with_extension("dt.bak");
+        fs::copy(conf_path, &backup).context(format!("creating {}", backup.display()))?;
+        fs::write(conf_path, migrated).context("writing migrated config")?;
+        eprintln!(
+            "wrote {}, the old version is in {}",
+            conf_path.display(),
+            backup.display()
+        );
+    }
+    Ok(())
+}
+
+fn init_config(conf_path: &Path, from_history: bool, accessible: bool) -> Result<()> {
+    if conf_path.exists() {
+        bail!(i18n::tr("config_exists", &[("path", &conf_path.display())]));
+    }
+    let src = if from_history {
+        config_from_history(accessible)?
+    } else {
+        bootstrap::STARTER.to_string()
+    };
+    if let Some(dir) = conf_path.parent() {
+        fs::create_dir_all(dir).context(format!("creating {}", dir.display()))?;
+    }
+    fs::write(conf_path, src).context("writing config")?;
+    eprintln!(
+        "{}",
+        i18n::tr("config_written", &[("path", &conf_path.display())])
+    );
+    Ok(())
+}
+
+fn config_from_history(accessible: bool) -> Result<String> {
+    let mut commands = vec![];
+    for path in bootstrap::history_files() {
+        commands.extend(bootstrap::read_history(&path)?);
+    }
+    let candidates = bootstrap::candidates(&commands, MAX_INIT_CANDIDATES);
+    if candidates.is_empty() {
+        bail!(i18n::tr("no_history_commands", &[]));
+    }
+    let items: Vec<_> = candidates
+        .iter()
+        .map(|c| format!("{}× {}", c.count, c.command))
+        .collect();
+    let term = Term::stderr();
+    let prompt = i18n::tr("init_select", &[]);
+    // without a terminal, all candidates are taken
+    let selection = if !term.is_term() {
+        (0..items.len()).collect()
+    } else if accessible {
+        accessible::pick(&prompt, &items, true)?
+    } else {
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(&items)
+            .defaults(&vec![true; items.len()])
+            .interact_on(&term)?
+    };
+    let accepted: Vec<_> = selection
+        .into_iter()
+        .map(|i| candidates[i].command.clone())
+        .collect();
+    Ok(bootstrap::generate(&accepted))
+}
+
+fn lint(conf_path: &Path, conf_src: &str) -> Result<()> {
+    let warnings = parser::lint(conf_src)?;
+    for w in &warnings {
+        println!(
+            "{}:{}:{}: {}",
+            conf_path.display(),
+            w.line,
+            w.col,
+            w.message
+        );
+    }
+    if !warnings.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn print_config_item(item: ConfigItem, conf_path: &Path) -> Result<()> {
+    let conf_path = fs::canonicalize(conf_path).context("canonicalizing config path")?;
+    match item {
+        ConfigItem::Path => println!("{}", conf_path.display()),
+        ConfigItem::Shell => println!("{}", exec::global_shell()),
+        ConfigItem::Settings => print_settings(&conf_path)?,
+    }
+    Ok(())
+}
+
+fn print_settings(conf_path: &Path) -> Result<()> {
+    let mode = if rt_conf::local_conf_dir().is_some() {
+        "local"
+    } else {
+        "global"
+    };
+    println!("config file: {} ({mode})", conf_path.display());
+    println!("shell: {}", exec::global_shell());
+    let echo = if rt_conf::settings().echo_by_default {
+        "on"
+    } else {
+        "off"
+    };
+    println!("echo: {echo}");
+    if let Some(wd) = exec::working_dir(None)? {
+        println!("working directory: {}", wd.display());
+    }
+    if rt_conf::cli_options().no_history {
+        println!("history file: disabled");
+    } else {
+        println!("history file: {}", core::get_hist_path()?.display());
+    }
+    println!("state directory: {}", state::state_dir()?.display());
+    println!("language: {}", i18n::lang().unwrap_or("en"));
+    Ok(())
+}
+
+/// Everything `dt config settings` prints, and the version, for bug reports and for finding
+/// out why dotree behaves differently on another machine
+fn print_info(conf_path: &Path) -> Result<()> {
+    println!("version: {}", env!("CARGO_PKG_VERSION"));
+    print_settings(&fs::canonicalize(conf_path).context("canonicalizing config path")?)
+}
+
+/// The terminal command, followed by a dotree that shows the menu of the server at `socket`
+#[cfg(unix)]
+fn get_popup_cmd(terminal: Option<String>, socket: &Path) -> Result<Option<Vec<String>>> {
+    let terminal = if let Some(t) = terminal {
+        t
+    } else if let Ok(t) = env::var("TERMINAL") {
+        format!("{t} -e")
+    } else {
+        return Ok(None);
+    };
+    let mut res: Vec<String> = terminal.split_whitespace().map(String::from).collect();
+    res.push(
+        env::current_exe()
+            .context("getting current executable")?
+            .to_string_lossy()
+            .into(),
+    );
+    let socket = env::current_dir().context("getting cwd")?.join(socket);
+    res.extend(["--served-by".into(), socket.to_string_lossy().into()]);
+    Ok(Some(res))
+}
+
+/// Requests the config from the server at `socket`, with the path and local directory it was
+/// loaded with
+#[cfg(unix)]
+fn fetch_served_config(socket: &Path) -> Result<(PathBuf, Option<PathBuf>, Config)> {
+    let mut served = serve::request(socket, "config", serde_json::Value::Null)?;
+    Ok((
+        serde_json::from_value(served["conf_path"].take())?,
+        serde_json::from_value(served["local_conf_dir"].take())?,
+        serde_json::from_value(served["config"].take())?,
+    ))
+}
+
+#[cfg(not(unix))]
+fn fetch_served_config(_socket: &Path) -> Result<(PathBuf, Option<PathBuf>, Config)> {
+    bail!("dt serve is only supported on unix systems")
 }
 
 fn get_default_config_dir() -> Option<PathBuf> {
@@ -82,9 +489,8 @@ fn search_local_config() -> Result<Option<PathBuf>> {
     let cwd = std::env::current_dir().context("getting cwd")?;
     let mut cur_dir = cwd.as_path();
     loop {
-        let attempt = cur_dir.join("dotree.dt");
-        if attempt.exists() {
-            return Ok(Some(attempt));
+        if let Some(path) = loader::find_in(cur_dir) {
+            return Ok(Some(path));
         }
         if let Some(parent) = cur_dir.parent() {
             cur_dir = parent;
@@ -96,16 +502,300 @@ fn search_local_config() -> Result<Option<PathBuf>> {
 
 #[derive(Parser)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<SubCommand>,
+
     /// Input that will be process character by character, as if it was entered
+    // the keys are completed by the completion scripts themselves
+    #[arg(value_hint = ValueHint::Other)]
     input: Vec<String>,
 
-    /// path to config file. Defaults to $XDG_CONFIG_HOME/dotree.dt
-    #[arg(long, short)]
+    /// path to config file. Defaults to $XDG_CONFIG_HOME/dotree.dt, or dotree.toml or
+    /// dotree.yaml, if one of those exists instead
+    #[arg(long, short, global = true)]
     conf_file: Option<PathBuf>,
 
     /// instead of reading the config file, search all directories from current
-    /// to root for a dotree.dt file, and use this, if it is found.
+    /// to root for a dotree.dt, dotree.toml or dotree.yaml file, and use this, if it is found.
     /// All commands are executed from the files directory
-    #[arg(long, short)]
+    #[arg(long, short, global = true)]
     local_mode: bool,
+
+    /// preset the value of a variable, so it won't be queried. Can be used multiple times.
+    /// Positional values are used for the remaining variables
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var_preset)]
+    var_presets: Vec<(String, String)>,
+
+    /// shell invocation to use for this run, e.g. "sh -c". Takes precedence over all shell
+    /// definitions in the config and DT_DEFAULT_SHELL
+    #[arg(long, global = true, value_parser = parse_shell_arg)]
+    shell: Option<ShellDef>,
+
+    /// directory to run the command in. Takes precedence over the directory of the config
+    /// file in local mode
+    #[arg(long, global = true)]
+    cwd: Option<PathBuf>,
+
+    /// run the command in the root of the git repository that contains the current
+    /// directory, like with `set cwd git-root`
+    #[arg(long, global = true, conflicts_with = "cwd")]
+    git_root: bool,
+
+    /// don't read or write the history of variable values, e.g. when entering secrets
+    #[arg(long, global = true)]
+    no_history: bool,
+
+    /// file to read and store the history of variable values in, instead of the default one
+    #[arg(long, global = true, conflicts_with = "no_history")]
+    history_file: Option<PathBuf>,
+
+    /// print every menu once with numbered entries, and read selections line by line,
+    /// instead of redrawing the output in place. Meant for screen readers
+    #[arg(long, global = true)]
+    accessible: bool,
+
+    /// show the menus in the alternate screen, as a scrollable list with a preview of the
+    /// highlighted entry and a status bar, instead of the compact menu below the prompt
+    #[arg(long, global = true, conflicts_with = "accessible")]
+    tui: bool,
+
+    /// accept all confirmation prompts, like the ones of commands with the `danger` or
+    /// `cooldown` setting, for scripts. Accepted danger prompts are noted in the audit log
+    #[arg(long, short, global = true)]
+    yes: bool,
+
+    /// kill commands that run longer than this, e.g. 30s or 5min, regardless of their settings.
+    /// For running commands from CI or cron, where hangs are unacceptable
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// print the shell command line and the working directory before running a command. Given
+    /// twice, also print the environment variables dotree set, with the values of secrets masked
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// print the working directory, the shell command line with resolved snippets and the
+    /// environment variables dotree would set, instead of running the command. Variables are
+    /// still queried, but not stored in the history. The commands of `from` helpers and
+    /// dynamic menus still run
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// print how long loading, parsing and resolving the config took, and how long commands
+    /// ran, to stderr. For finding out what makes large configs slow
+    #[arg(long, global = true)]
+    timings: bool,
+
+    /// when to use colors. In auto mode, they are used if the output is a terminal, and
+    /// NO_COLOR isn't set
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// run the last command that didn't exit successfully again, with the same variable
+    /// values. Values passed with --var take precedence
+    #[arg(long, conflicts_with = "input")]
+    retry_failed: bool,
+
+    /// print the variables the command with these keys asks for, in the order they can be
+    /// passed as arguments, as tab separated name, kind, default value, and whether it's
+    /// required or optional
+    #[arg(long, value_name = "KEYS", conflicts_with = "input")]
+    list_vars: Option<String>,
+
+    /// show the config of the `dt serve` at this socket, instead of loading one. Used by popups
+    #[arg(long, value_name = "SOCKET", hide = true)]
+    served_by: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+fn set_colors(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // see https://no-color.org, an empty value doesn't count
+        ColorChoice::Auto if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => false,
+        // console detects whether the output is a terminal on its own
+        ColorChoice::Auto => return,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+fn parse_shell_arg(src: &str) -> Result<ShellDef> {
+    Ok(parser::parse_shell_string(&format!("shell {src}"))?)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    Json,
+    Yaml,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+fn shell_init_script(shell: ShellKind) -> &'static str {
+    match shell {
+        ShellKind::Bash | ShellKind::Zsh => actions::POSIX_SHELL_INIT,
+        ShellKind::Fish => actions::FISH_SHELL_INIT,
+    }
+}
+
+fn completion_script(shell: ShellKind) -> String {
+    // the name of the binary, not the one of the package
+    let cmd = Args::command().name("dt");
+    let shell = match shell {
+        ShellKind::Bash => clap_complete::Shell::Bash,
+        ShellKind::Zsh => clap_complete::Shell::Zsh,
+        ShellKind::Fish => clap_complete::Shell::Fish,
+    };
+    completions::script(shell, &cmd)
+}
+
+#[derive(Subcommand)]
+enum ConfigItem {
+    /// path of the config file
+    Path,
+    /// shell that is used for commands without a shell definition
+    Shell,
+    /// all settings, including the paths that dotree uses
+    Settings,
+}
+
+fn parse_var_preset(src: &str) -> Result<(String, String)> {
+    let (name, val) = src
+        .split_once('=')
+        .ok_or(anyhow!("expected NAME=VALUE, got: {src}"))?;
+    Ok((name.to_string(), val.to_string()))
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Serve the tree via JSON-RPC on a unix socket. Supported methods are `tree`, which
+    /// returns the parsed tree, `execute`, which takes a key `path` and optionally
+    /// an object of `vars`, runs the command and returns its exit status and output, `popup`,
+    /// which opens the menu in a new terminal with the --terminal command and returns its
+    /// `pid`, and, if dotree was built with the serde feature, `config`, which returns the
+    /// parsed config and the path it was loaded from.
+    Serve {
+        /// path of the socket. Defaults to $XDG_RUNTIME_DIR/dotree.sock
+        #[arg(long, short)]
+        socket: Option<PathBuf>,
+
+        /// command that opens a terminal and runs the arguments appended to it, used by
+        /// `dt popup`. Defaults to "$TERMINAL -e"
+        #[arg(long, short)]
+        terminal: Option<String>,
+    },
+    /// Print the effective configuration, after all command line arguments, environment
+    /// variables and config file settings were applied
+    Config {
+        #[command(subcommand)]
+        item: ConfigItem,
+    },
+    /// Print the version, the config file and whether it was found in local mode, the
+    /// effective shell and echo setting, and the files dotree stores its state in
+    Info,
+    /// Report unused snippets and menus, unreachable entries and references to undefined
+    /// snippets. Exits with 1 if there are any problems
+    Lint,
+    /// Compare two config files on the level of the tree, and print the added, removed and
+    /// changed entries, settings and snippets. Exits with 1 if there are any differences
+    Diff {
+        old: PathBuf,
+
+        /// defaults to the current config file
+        new: Option<PathBuf>,
+    },
+    /// Rewrite a config that uses outdated syntax to the current one, preserving comments and
+    /// formatting. Prints the result, and the applied migrations to stderr
+    Migrate {
+        /// replace the config file with the result, and keep the old version as .dt.bak
+        #[arg(long, short)]
+        write: bool,
+    },
+    /// List the logged runs of commands with the `log` setting, and open one in the pager
+    Logs {
+        /// only list runs of the command with these keys
+        keys: Option<String>,
+    },
+    /// Print all commands whose keys, name or command contain the search term
+    Search {
+        term: String,
+
+        /// interpret the term as regular expression
+        #[arg(long, short)]
+        regex: bool,
+    },
+    /// Print every command as `keys → name → command`, sorted by keys, with resolved snippets
+    List {
+        /// print a JSON array of objects with keys, name and exec instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Ask a running `dt serve` to open the menu in a new terminal window. Bind this to a
+    /// global hotkey to use dotree as a system wide command palette
+    Popup {
+        /// path of the socket. Defaults to $XDG_RUNTIME_DIR/dotree.sock
+        #[arg(long, short)]
+        socket: Option<PathBuf>,
+    },
+    /// Print the parsed config, with its menus, settings and snippets, for tools that
+    /// process the config without parsing it themselves
+    Dump {
+        #[arg(long, short, value_enum, default_value_t = DumpFormat::Json)]
+        format: DumpFormat,
+    },
+    /// Write a starter config with a commented example menu, a snippet and a command with
+    /// variables. Refuses to overwrite an existing config
+    Init {
+        /// propose the commands you run most often instead, taken from the history of bash,
+        /// zsh and fish. They are grouped by program and can be deselected, before the config
+        /// is written
+        #[arg(long)]
+        from_history: bool,
+
+        /// write the config to the current directory, for local mode, instead of the config
+        /// dir
+        #[arg(long, conflicts_with = "conf_file")]
+        local: bool,
+    },
+    /// Print a wrapper function for the shell, which lets `cd` entries change the directory
+    /// of the shell. Add `eval "$(dt shell-init bash)"` to your .bashrc, or the equivalent
+    /// for your shell
+    ShellInit {
+        // not called shell, which is the id of the global --shell
+        #[arg(value_name = "SHELL")]
+        kind: ShellKind,
+    },
+    /// Print a completion script for the shell, which completes the flags, the subcommands and
+    /// the keys of the commands in the config. Add `eval "$(dt completions bash)"` to your
+    /// .bashrc, or the equivalent for your shell
+    Completions {
+        #[arg(value_name = "SHELL")]
+        kind: ShellKind,
+    },
+    /// Print the tree of menus and commands
+    Tree {
+        /// only print this many levels, and the number of entries of the menus below
+        #[arg(long, short)]
+        depth: Option<usize>,
+    },
+    /// Print the key paths of the commands that start with the given keys, one per line and
+    /// followed by a tab and the name. Called by shell completion functions
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(default_value = "")]
+        partial: String,
+    },
 }
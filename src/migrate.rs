@@ -0,0 +1,119 @@
+//! Rewrites configs that use outdated syntax to the current one. The migrations edit the
+//! source text in place, so comments and formatting are preserved
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use pest::Parser;
+
+use crate::parser::{self, ConfigParser, Rule};
+
+/// An insertion of `text` at the byte offset `pos` of the source
+struct Edit {
+    pos: usize,
+    text: &'static str,
+}
+
+struct Migration {
+    description: &'static str,
+    edits: fn(&str) -> Result<Vec<Edit>>,
+}
+
+/// All migrations, in the order they are applied
+const MIGRATIONS: &[Migration] = &[Migration {
+    description: "separate the items of vars and set with commas",
+    edits: separate_items,
+}];
+
+/// The migrated source of the config at `path`, and the descriptions of the migrations that
+/// changed something. Fails if the source can't be parsed, before or after the migration
+pub fn migrate(src: &str, path: &Path) -> Result<(String, Vec<&'static str>)> {
+    let mut src = src.to_string();
+    let mut applied = vec![];
+    for migration in MIGRATIONS {
+        let mut edits = (migration.edits)(&src)?;
+        if edits.is_empty() {
+            continue;
+        }
+        edits.sort_by_key(|e| e.pos);
+        for edit in edits.iter().rev() {
+            src.insert_str(edit.pos, edit.text);
+        }
+        applied.push(migration.description);
+    }
+    let file = ConfigParser::parse(Rule::file, &src)
+        .map_err(parser::parse_error)
+        .context("Parsing migrated config")?
+        .next()
+        .unwrap();
+    // included files are migrated on their own, and might not be yet, so only the syntax of
+    // configs with includes is checked
+    if !file.into_inner().any(|p| p.as_rule() == Rule::include) {
+        parser::parse_file(&src, path).context("Parsing migrated config")?;
+    }
+    Ok((src, applied))
+}
+
+/// Items of `vars` and `set` used to be separated by whitespace only. This is still accepted,
+/// but ambiguous since variables can be followed by keywords like `multi`
+fn separate_items(src: &str) -> Result<Vec<Edit>> {
+    let file = ConfigParser::parse(Rule::file, src)
+        .map_err(parser::parse_error)?
+        .next()
+        .unwrap();
+    let mut res = vec![];
+    for p in file.into_inner().flatten() {
+        if !matches!(p.as_rule(), Rule::vars_def | Rule::cmd_settings) {
+            continue;
+        }
+        let items: Vec<_> = p.into_inner().map(|item| item.as_span()).collect();
+        for pair in items.windows(2) {
+            let (prev, next) = (pair[0], pair[1]);
+            if !src[prev.end()..next.start()].contains(',') {
+                // spans include the whitespace that follows them
+                res.push(Edit {
+                    pos: prev.start() + prev.as_str().trim_end().len(),
+                    text: ",",
+                });
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separate_items() -> Result<()> {
+        let src = r#"
+            menu root {
+                # deploys the app
+                d: cmd {
+                    vars env = "prod" region
+                    set ignore_result  cooldown 5s
+                    "deploy $env $region"
+                }
+                s: cmd {
+                    vars a, b
+                    "echo $a $b"
+                }
+            }
+        "#;
+        let path = Path::new("dotree.dt");
+        let (migrated, applied) = migrate(src, path)?;
+        assert_eq!(applied, ["separate the items of vars and set with commas"]);
+        assert_eq!(
+            migrated,
+            src.replace("prod\" region", "prod\", region")
+                .replace("ignore_result  cooldown", "ignore_result,  cooldown")
+        );
+        assert!(migrate(&migrated, path)?.1.is_empty());
+
+        assert!(migrate("menu root { m: missing }", path).is_err());
+        // the included file isn't read
+        let src = "include \"missing.dt\"\nmenu root { m: missing }";
+        assert!(migrate(src, path)?.1.is_empty());
+        Ok(())
+    }
+}
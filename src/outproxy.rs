@@ -27,18 +27,19 @@ impl Default for OutProxy {
     }
 }
 
-#[cfg(target_os = "windows")]
+/// Windows ends lines with "\r\n", but the output of Rust's `writeln!` only has "\n", so the
+/// line feeds are counted on all platforms
 fn count_newlines(buf: &[u8]) -> usize {
-    let mut count = 0;
-    for i in 0..(buf.len() - 1) {
-        if buf[i] as char == '\r' && buf[i + 1] as char == '\n' {
-            count += 1;
-        }
-    }
-    count
+    buf.iter().filter(|x| **x == b'\n').count()
 }
 
-#[cfg(not(target_os = "windows"))]
-fn count_newlines(buf: &[u8]) -> usize {
-    buf.iter().filter(|x| **x as char == '\n').count()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_newlines() {
+        assert_eq!(count_newlines(b""), 0);
+        assert_eq!(count_newlines(b"a\nb\r\nc\r"), 2);
+    }
 }
@@ -1,90 +1,382 @@
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use log::debug;
 use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use pest::{
+    error::{ErrorVariant, LineColLocation},
     iterators::{Pair, Pairs},
     Parser,
 };
 use pest_derive::Parser;
 
-use anyhow::{anyhow, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::error::{self, DotreeError};
+use crate::exec::expand_home;
+use crate::i18n;
+use crate::timings;
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
-struct ConfigParser;
+pub(crate) struct ConfigParser;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     Menu(Menu),
-    Command(Command),
+    Command(Box<Command>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Menu {
     pub name: String,
     pub display_name: Option<String>,
-    pub entries: HashMap<Vec<char>, Node>,
+    /// Shown above the entries
+    pub header: Option<String>,
+    /// Shown below the entries
+    pub footer: Option<String>,
+    /// After one of its commands ran, the menu is shown again, as if the commands had the
+    /// repeat setting
+    pub sticky: bool,
+    /// Code that runs before the commands of the menu and its submenus
+    pub preamble: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "keys_as_strings"))]
+    /// In the order of the config, or sorted by their keys with `sort alphabetical`
+    pub entries: IndexMap<Vec<char>, Node>,
+    /// Set for a dynamic menu, whose entries are generated when it is entered
+    pub dynamic: Option<Box<DynamicEntries>>,
 }
 
+/// What the entries of a dynamic menu are generated from
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynamicEntries {
+    /// Its output has a line for each entry
+    pub exec_str: StringExpr,
+    /// The variable that holds the line of the selected entry
+    pub var: String,
+    /// The command the entries run
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Command {
     pub exec_str: StringExpr,
+    /// Runs instead of the shell, with the exec_str as argument
+    pub action: Option<Action>,
     pub settings: Vec<CommandSetting>,
     pub name: Option<String>,
     pub shell: Option<ShellDef>,
     pub env_vars: Vec<VarDef>,
+    /// Variables of a named command, that the entry sets to these values. They aren't part of
+    /// `env_vars`, so they aren't queried
+    pub bound_vars: Vec<(String, StringExpr)>,
     pub toggle_echo_setting: bool,
+    /// Runs when the command exits with a non-zero exit code
+    pub on_error: Option<StringExpr>,
+    /// Runs after the command, no matter how it exited
+    pub finally: Option<StringExpr>,
+    /// Content that is written to the command's stdin
+    pub stdin: Option<StringExpr>,
+    /// Directories that are prepended to the PATH of the command
+    pub path_prepend: Vec<String>,
+    /// Inherited environment variables that are removed for the command
+    pub unset_env: Vec<String>,
+    /// Variants of the name per language code, `name` is the one for en, or the first one
+    pub localized_names: Vec<(String, String)>,
+    /// The line of the entry in the config, starting at 1
+    pub line: usize,
+    /// The included file that contains the entry, None if it's the main config
+    pub file: Option<PathBuf>,
+    /// The preambles of the settings and the menus that contain the command
+    pub preamble: Option<String>,
+}
+
+/// What a command can do without a shell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Action {
+    /// Opens the URL with the default application of the platform
+    OpenUrl,
+    /// Copies the text to the clipboard
+    Copy,
+    /// Prints the text
+    Print,
+    /// Changes the directory of the shell dt was started from, see `actions::cd`
+    Cd,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommandSetting {
     Repeat,
     IgnoreResult,
+    Log,
+    /// Only one instance of the command may run at the same time
+    Exclusive,
+    /// Ask before running the command again, if it succeeded less than this long ago
+    Cooldown(Duration),
+    /// Run the command again, whenever a file that matches this glob changes
+    Watch(String),
+    /// Run the command in the root of the git repository that contains the cwd
+    GitRootCwd,
+    /// Run the command inside WSL, in the given distribution, or the default one
+    Wsl(Option<String>),
+    /// The command is shown in red, and has to be confirmed by typing its name
+    Danger,
+    /// The command has to be confirmed with y, after this question, or a default one
+    Confirm(Option<String>),
+    /// The menu shows how long ago the command was run
+    ShowLastRun,
+    /// Print the environment variables dotree set, the shell command line and the working
+    /// directory before running the command, like with -vv
+    TraceEnv,
+    /// When the command runs again in the same session, the values of its variables are
+    /// reused, instead of queried
+    ReuseVars,
+    /// Run the command as root, via sudo
+    Sudo,
+    /// Run the command in a new tmux window or pane, when dotree runs inside tmux
+    Tmux(TmuxTarget),
+}
+
+/// Where a command with a tmux setting is started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TmuxTarget {
+    Window,
+    Pane,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShellDef {
     pub name: String,
     pub args: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct VarDef {
     pub name: String,
-    pub value: Option<String>,
+    /// The default value, which can reference the variables that are queried before
+    pub value: Option<StringExpr>,
+    /// Where the variable is queried. Variables with the same order are asked together
+    pub order: Option<u32>,
+    pub kind: VarKind,
+    /// The values are neither stored in the history, nor as last value or in failed runs
+    pub nohist: bool,
+    /// The value isn't shown while it is typed, or when the environment is printed. Implies
+    /// nohist
+    pub secret: bool,
+}
+
+/// How the value of a variable is queried
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VarKind {
+    /// Typed in, with the history as help
+    #[default]
+    Input,
+    /// Picked from a checkbox list, the selected options are joined with the separator
+    MultiSelect {
+        options: Vec<String>,
+        separator: String,
+    },
+    /// Picked with a fuzzy finder from the lines that the helper command outputs
+    Fzf { helper: StringExpr },
+    /// Picked from a list of the lines that the helper command outputs
+    Pick { helper: StringExpr },
+    /// Typed in, with completions from the most frecently used directories
+    Dir,
+}
+
+impl VarKind {
+    /// The command whose output lines are the options, if the value is picked from them
+    pub fn helper(&self) -> Option<&StringExpr> {
+        match self {
+            VarKind::Fzf { helper } | VarKind::Pick { helper } => Some(helper),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct RawMenu<'a> {
     display_name: Option<String>,
     body: Pairs<'a, Rule>,
+    /// The included file the menu is defined in, None for the main config
+    file: Option<&'a Path>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StringExprElem {
     Symbol(String),
     String(String),
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Settings {
     pub shell_def: Option<ShellDef>,
     pub echo_by_default: bool,
+    /// Used by commands without their own on_error hook
+    pub on_error: Option<StringExpr>,
+    /// Language of the texts dotree shows, instead of the one from the environment
+    pub lang: Option<String>,
+    /// Where the key comes from, that encrypts the state files with values of variables
+    pub encrypt_history: Option<KeySource>,
+    /// Code that runs before every command
+    pub preamble: Option<String>,
+    /// Returns to the root menu, like the home key
+    pub root_key: Option<char>,
+    /// Returns to the menu above, like the left arrow key. Escape is stored as '\x1b'
+    pub back_key: Option<char>,
+    /// The menu is closed, when no key was pressed for this long
+    pub idle_timeout: Option<Duration>,
+    /// The number of values the history keeps per variable
+    pub history_max: Option<usize>,
+    pub theme: Theme,
 }
 
-#[derive(Debug, Clone)]
+/// The styles of the parts of the menu, as dotted style strings, like "green.bold". Parts
+/// without one are shown in their default style
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Theme {
+    /// The keys that were typed, in the entries that still match them
+    pub prefix: Option<String>,
+    /// The rest of the keys of the entries
+    pub keys: Option<String>,
+    /// The header of a menu
+    pub title: Option<String>,
+    /// The names of submenus
+    pub menu: Option<String>,
+    /// The names of commands, except dangerous ones, which are red
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeySource {
+    /// A random key, that is stored in the keyring of the OS
+    Keyring,
+    /// A key derived from a passphrase, that is asked for once per run
+    Passphrase,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
     pub menu: Menu,
     pub settings: Settings,
     pub snippet_table: SnippetTable,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct StringExpr(Vec<StringExprElem>);
 
 pub type SnippetTable = HashMap<String, StringExpr>;
 
+/// Serializes the entries of a menu with their keys as string, as most formats only support
+/// string keys in maps
+#[cfg(feature = "serde")]
+mod keys_as_strings {
+    use super::{IndexMap, Node};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        entries: &IndexMap<Vec<char>, Node>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let entries: IndexMap<String, &Node> = entries
+            .iter()
+            .map(|(keys, node)| (String::from_iter(keys), node))
+            .collect();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<IndexMap<Vec<char>, Node>, D::Error> {
+        let entries = IndexMap::<String, Node>::deserialize(deserializer)?;
+        Ok(entries
+            .into_iter()
+            .map(|(keys, node)| (keys.chars().collect(), node))
+            .collect())
+    }
+}
+
+/// A string expression without snippets can also be deserialized from a plain string, which is
+/// easier to write in TOML and YAML configs
+#[cfg(feature = "serde")]
+mod string_expr_de {
+    use std::fmt;
+
+    use super::{StringExpr, StringExprElem};
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+
+    struct StringExprVisitor;
+
+    impl<'de> Visitor<'de> for StringExprVisitor {
+        type Value = StringExpr;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string, or a list of String and Symbol parts")
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<StringExpr, E> {
+            Ok(StringExpr(vec![StringExprElem::String(s.to_string())]))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<StringExpr, A::Error> {
+            let mut parts = vec![];
+            while let Some(part) = seq.next_element()? {
+                parts.push(part);
+            }
+            Ok(StringExpr(parts))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for StringExpr {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(StringExprVisitor)
+        }
+    }
+}
+
 trait INext: Sized {
     fn inext(self) -> Self;
     fn nnext(mut self, n: usize) -> Self {
@@ -106,6 +398,15 @@ impl Default for Settings {
         Settings {
             shell_def: None,
             echo_by_default: true,
+            on_error: None,
+            lang: None,
+            encrypt_history: None,
+            preamble: None,
+            root_key: None,
+            back_key: None,
+            idle_timeout: None,
+            history_max: None,
+            theme: Theme::default(),
         }
     }
 }
@@ -114,16 +415,64 @@ fn from_string(p: Pair<'_, Rule>) -> String {
     p.nnext(2).as_str().to_string()
 }
 
-pub fn parse(src: &str) -> Result<Config> {
-    let mut pairs = ConfigParser::parse(Rule::file, src).context("Parsing source")?;
+/// Parses a config that isn't read from a file. Relative paths of includes are resolved
+/// against the cwd
+pub fn parse(src: &str) -> error::Result<Config> {
+    parse_with_includes(src, None)
+}
+
+/// Parses the config file at `path`, whose content is `src`. Relative paths of includes are
+/// resolved against its directory
+pub fn parse_file(src: &str, path: &Path) -> error::Result<Config> {
+    parse_with_includes(src, Some(path))
+}
+
+fn parse_with_includes(src: &str, path: Option<&Path>) -> error::Result<Config> {
+    let mut pairs = timings::measure("parsing", || ConfigParser::parse(Rule::file, src))
+        .map_err(parse_error)?;
     let file = pairs.next().unwrap();
     assert!(file.as_rule() == Rule::file);
 
-    let (settings, entries) = parse_settings(file.into_inner());
+    let (settings, entries) = timings::measure("settings", || parse_settings(file.into_inner()))?;
+
+    // a path that doesn't exist, can't be included, so it's fine if it can't be canonicalized
+    let mut chain: Vec<_> = path
+        .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+        .into_iter()
+        .collect();
+    let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+    let mut included = vec![];
+    timings::measure("includes", || {
+        load_includes(entries.clone(), dir, &mut chain, &mut included)
+    })?;
+    let included_files = included
+        .iter()
+        .map(|(path, src)| Ok((path.as_path(), parse_included(src, path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    // the definitions of the including file come last, so they win over the included ones
+    let mut items = vec![];
+    for (path, file) in included_files {
+        for item in file.into_inner() {
+            if item.as_rule() == Rule::setting {
+                let msg = "settings are only allowed in the main config";
+                return Err(anyhow!("{}: {msg}", path.display()).into());
+            }
+            items.push((Some(path), item));
+        }
+    }
+    items.extend(entries.map(|item| (None, item)));
 
-    let menus = get_menu_table(entries.clone());
-    let snippet_table = get_snippet_table(entries);
-    let menu = parse_menu("root", &menus)?;
+    let menus = get_menu_table(&items);
+    let commands = get_command_table(items.iter().map(|(_, item)| item.clone()));
+    if let Some(name) = commands.keys().find(|name| menus.contains_key(*name)) {
+        return Err(anyhow!("{name} is defined as menu and as command").into());
+    }
+    let snippet_table = timings::measure("snippets", || {
+        get_snippet_table(items.into_iter().map(|(_, item)| item))
+    });
+    let menu = timings::measure("menus", || {
+        parse_menu("root", &menus, &commands, settings.preamble.as_deref())
+    })?;
 
     Ok(Config {
         menu,
@@ -132,7 +481,127 @@ pub fn parse(src: &str) -> Result<Config> {
     })
 }
 
-fn parse_settings(mut entries: Pairs<Rule>) -> (Settings, Pairs<Rule>) {
+/// Reads the files that the `items` of the file in `dir` include, and the ones they include,
+/// in the order they are included. `chain` are the files that include the current one, to
+/// detect cycles. Files that are included more than once, are only read once
+fn load_includes<'a>(
+    items: impl Iterator<Item = Pair<'a, Rule>>,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+    res: &mut Vec<(PathBuf, String)>,
+) -> Result<()> {
+    for include in items.filter(|item| item.as_rule() == Rule::include) {
+        let path = dir.join(expand_home(&from_string(include.inext())));
+        let path = fs::canonicalize(&path).context(format!("including {}", path.display()))?;
+        if let Some(start) = chain.iter().position(|p| p == &path) {
+            let mut cycle = chain[start..].to_vec();
+            cycle.push(path);
+            return Err(DotreeError::IncludeCycle(cycle).into());
+        }
+        if res.iter().any(|(p, _)| p == &path) {
+            continue;
+        }
+        let src = fs::read_to_string(&path).context(format!("reading {}", path.display()))?;
+        let file = parse_included(&src, &path)?;
+        chain.push(path.clone());
+        load_includes(file.into_inner(), path.parent().unwrap(), chain, res)?;
+        chain.pop();
+        res.push((path, src));
+    }
+    Ok(())
+}
+
+/// The file rule of an included file. Errors get its path as context
+fn parse_included<'a>(src: &'a str, path: &Path) -> Result<Pair<'a, Rule>> {
+    let mut pairs = ConfigParser::parse(Rule::file, src)
+        .map_err(parse_error)
+        .context(path.display().to_string())?;
+    Ok(pairs.next().unwrap())
+}
+
+pub(crate) fn parse_error(err: pest::error::Error<Rule>) -> DotreeError {
+    let (line, col) = match err.line_col {
+        LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+    };
+    let message = match &err.variant {
+        ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+            let mut expected: Vec<String> = vec![];
+            for desc in positives.iter().map(describe_rule) {
+                if !expected.contains(&desc) {
+                    expected.push(desc);
+                }
+            }
+            match expected.split_last() {
+                Some((last, [])) => format!("expected {last}"),
+                Some((last, rest)) => format!("expected {}, or {last}", rest.join(", ")),
+                None => unreachable!("positives aren't empty"),
+            }
+        }
+        variant => variant.message().into_owned(),
+    };
+    DotreeError::ParseError {
+        line,
+        col,
+        message,
+        excerpt: excerpt(err.line(), line, col),
+    }
+}
+
+/// The line with the error, with a gutter for the line number, and a caret under the column
+fn excerpt(text: &str, line: usize, col: usize) -> String {
+    // pest shows the line break as visible character
+    let text = text.trim_end_matches(['␊', '␍']);
+    let gutter = " ".repeat(line.to_string().len());
+    // tabs are kept, so the caret lines up with the text, however wide they are shown
+    let indent: String = text
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!("{gutter} |\n{line} | {text}\n{gutter} | {indent}^")
+}
+
+/// How a rule is called in the hint of a syntax error, the ones without a better description
+/// by their name
+fn describe_rule(rule: &Rule) -> String {
+    let desc = match rule {
+        Rule::EOI => "the end of the file",
+        Rule::string | Rule::normal_string | Rule::protected_string => "a string",
+        Rule::string_expr | Rule::string_expr_elem => "a string or $snippet",
+        Rule::snippet_symbol => "a $snippet",
+        Rule::symbol => "a name",
+        Rule::keydef => "the keys of an entry",
+        Rule::entry => "an entry",
+        Rule::menu => "a menu",
+        Rule::snippet => "a snippet",
+        Rule::command_def => "a command definition",
+        Rule::include => "an include",
+        Rule::setting | Rule::cmd_settings | Rule::cmd_setting => "a setting",
+        Rule::quick_command | Rule::anon_command => "a command",
+        Rule::command_ref => "a command with bound variables",
+        Rule::dyn_menu => "a dynmenu",
+        Rule::vars_def => "vars",
+        Rule::var_def => "a variable",
+        Rule::header_def => "header",
+        Rule::footer_def => "footer",
+        Rule::sticky_def => "set sticky",
+        Rule::sort_def => "sort alphabetical",
+        Rule::preamble_def => "preamble",
+        Rule::os_guard => "when os",
+        Rule::shell_def => "shell",
+        Rule::on_error_def => "on_error",
+        Rule::finally_def => "finally",
+        Rule::stdin_def => "stdin",
+        Rule::path_prepend_def => "path_prepend",
+        Rule::unset_env_def => "unset_env",
+        Rule::setting_value => "a value",
+        Rule::string_list => "a list of strings",
+        _ => return format!("{rule:?}"),
+    };
+    desc.to_string()
+}
+
+fn parse_settings(mut entries: Pairs<Rule>) -> Result<(Settings, Pairs<Rule>)> {
     let mut res = Settings::default();
     debug!("Parsing settings: \n{entries:?}");
     while let Some(first_entry) = entries.peek() {
@@ -142,23 +611,74 @@ fn parse_settings(mut entries: Pairs<Rule>) -> (Settings, Pairs<Rule>) {
         let first_entry = first_entry.inext();
         match first_entry.as_rule() {
             Rule::shell_def => {
-                res.shell_def = Some(parse_shell_def(first_entry));
+                res.shell_def = Some(parse_shell_def(first_entry)?);
                 debug!("parsing shell_def result: {:?}", res.shell_def);
             }
             Rule::echo_setting => {
                 res.echo_by_default = parse_echo_setting(first_entry);
                 debug!("parsing echo_setting result: {:?}", res.echo_by_default);
             }
+            Rule::on_error_def => {
+                res.on_error = Some(parse_string_expr(first_entry.inext()));
+            }
+            Rule::lang_setting => {
+                res.lang = Some(first_entry.inext().as_str().to_string());
+            }
+            Rule::preamble_def => {
+                res.preamble = Some(from_string(first_entry.inext()));
+            }
+            Rule::root_key_setting => {
+                let key = from_string(first_entry.inext());
+                let mut chars = key.chars();
+                let (Some(c), None) = (chars.next(), chars.next()) else {
+                    bail!("the root key must be a single character, not \"{key}\"");
+                };
+                res.root_key = Some(c);
+            }
+            Rule::back_key_setting => {
+                let key = from_string(first_entry.inext());
+                let mut chars = key.chars();
+                res.back_key = Some(match (chars.next(), chars.next()) {
+                    _ if key == "esc" => '\x1b',
+                    (Some(c), None) => c,
+                    _ => bail!("the back key must be a single character or esc, not \"{key}\""),
+                });
+            }
+            Rule::idle_timeout_setting => {
+                let value = first_entry.inext().as_str();
+                res.idle_timeout = Some(
+                    humantime::parse_duration(value)
+                        .context(format!("invalid duration for idle_timeout: {value}"))?,
+                );
+            }
+            Rule::history_max_setting => {
+                let value = first_entry.inext().as_str();
+                let max = value
+                    .parse()
+                    .ok()
+                    .filter(|&max| max > 0)
+                    .context(format!("invalid number for history_max: {value}"))?;
+                res.history_max = Some(max);
+            }
+            Rule::theme_setting => res.theme = parse_theme(first_entry)?,
+            Rule::encrypt_setting => {
+                res.encrypt_history = Some(match first_entry.inext().as_str() {
+                    "keyring" => KeySource::Keyring,
+                    _ => KeySource::Passphrase,
+                });
+            }
             _ => {
                 panic!("unexpected rule:\n{first_entry:#?}");
             }
         }
         _ = entries.next();
     }
-    (res, entries)
+    Ok((res, entries))
 }
 
-fn get_snippet_table(entries: Pairs<'_, Rule>) -> HashMap<String, StringExpr> {
+fn get_snippet_table<'a>(
+    entries: impl Iterator<Item = Pair<'a, Rule>>,
+) -> HashMap<String, StringExpr> {
     let mut res = HashMap::new();
     for e in entries {
         if e.as_rule() == Rule::snippet {
@@ -172,12 +692,27 @@ fn get_snippet_table(entries: Pairs<'_, Rule>) -> HashMap<String, StringExpr> {
     res
 }
 
-pub fn parse_shell_string(src: &str) -> Result<ShellDef> {
-    let mut pairs = ConfigParser::parse(Rule::shell_def, src).context("Parsing shell def")?;
-    Ok(parse_shell_def(pairs.next().unwrap()))
+pub fn parse_shell_string(src: &str) -> error::Result<ShellDef> {
+    let mut pairs = ConfigParser::parse(Rule::shell_def, src).map_err(parse_error)?;
+    Ok(parse_shell_def(pairs.next().unwrap())?)
 }
 
-fn parse_shell_def(p: Pair<'_, Rule>) -> ShellDef {
+/// Shell invocations that can be selected with `shell preset:<name>`, with the options that
+/// make them behave like the default shell: no user config is loaded, and where possible, the
+/// script stops at the first failing command
+const SHELL_PRESETS: &[(&str, &[&str])] = &[
+    ("bash", &["bash", "-euo", "pipefail", "-c"]),
+    ("sh", &["sh", "-eu", "-c"]),
+    ("zsh", &["zsh", "-euo", "pipefail", "-c"]),
+    ("fish", &["fish", "--no-config", "-c"]),
+    ("nu", &["nu", "--no-config-file", "-c"]),
+    (
+        "pwsh",
+        &["pwsh", "-NoProfile", "-NonInteractive", "-Command"],
+    ),
+];
+
+fn parse_shell_def(p: Pair<'_, Rule>) -> Result<ShellDef> {
     let mut elems = VecDeque::new();
     for p in p.into_inner() {
         match p.as_rule() {
@@ -187,18 +722,31 @@ fn parse_shell_def(p: Pair<'_, Rule>) -> ShellDef {
             _ => panic!("unexpected rule: {p:?}"),
         }
     }
-    ShellDef {
+    if let Some(preset) = elems.front().and_then(|e| e.strip_prefix("preset:")) {
+        ensure!(elems.len() == 1, "a shell preset can't have arguments");
+        let Some((_, invocation)) = SHELL_PRESETS.iter().find(|(name, _)| *name == preset) else {
+            let names: Vec<_> = SHELL_PRESETS.iter().map(|(name, _)| *name).collect();
+            bail!(
+                "unknown shell preset: {preset}, available are: {}",
+                names.join(", ")
+            );
+        };
+        elems = invocation.iter().map(|e| e.to_string()).collect();
+    }
+    Ok(ShellDef {
         name: elems.pop_front().unwrap(),
         args: elems.into_iter().collect(),
-    }
+    })
 }
 
-fn get_menu_table(pairs: Pairs<'_, Rule>) -> HashMap<&str, RawMenu<'_>> {
-    pairs
-        .into_iter()
-        .filter(|x| x.as_rule() == Rule::menu)
-        .map(|menu| {
-            let mut menu_elems = menu.into_inner();
+fn get_menu_table<'a>(
+    items: &[(Option<&'a Path>, Pair<'a, Rule>)],
+) -> HashMap<&'a str, RawMenu<'a>> {
+    items
+        .iter()
+        .filter(|(_, x)| x.as_rule() == Rule::menu)
+        .map(|(file, menu)| {
+            let mut menu_elems = menu.clone().into_inner();
             let first_child = menu_elems.next().unwrap();
             let (display_name, menu_name) = if first_child.as_rule() == Rule::string {
                 (Some(from_string(first_child)), menu_elems.next().unwrap())
@@ -210,63 +758,274 @@ fn get_menu_table(pairs: Pairs<'_, Rule>) -> HashMap<&str, RawMenu<'_>> {
                 RawMenu {
                     display_name,
                     body: menu_elems.next().unwrap().into_inner(),
+                    file: *file,
                 },
             )
         })
         .collect()
 }
 
-fn parse_menu(name: &str, menus: &HashMap<&str, RawMenu<'_>>) -> Result<Menu> {
-    let mut entries = HashMap::new();
-    let RawMenu { display_name, body } = menus
+/// The bodies of the named commands, by name
+fn get_command_table<'a>(
+    pairs: impl Iterator<Item = Pair<'a, Rule>>,
+) -> HashMap<&'a str, Pair<'a, Rule>> {
+    pairs
+        .filter(|x| x.as_rule() == Rule::command_def)
+        .map(|def| {
+            let mut elems = def.into_inner();
+            let name = elems.next().unwrap().as_str();
+            (name, elems.next().unwrap())
+        })
+        .collect()
+}
+
+/// `inherited_preamble` is the one of the settings and the menus above, which the commands
+/// get together with the one of this menu
+fn parse_menu(
+    name: &str,
+    menus: &HashMap<&str, RawMenu<'_>>,
+    commands: &HashMap<&str, Pair<'_, Rule>>,
+    inherited_preamble: Option<&str>,
+) -> Result<Menu> {
+    let mut entries = IndexMap::new();
+    // the lines of the entries, for the errors about conflicting keys
+    let mut entry_lines: Vec<(Vec<char>, usize)> = vec![];
+    let mut header = None;
+    let mut footer = None;
+    let mut sticky = false;
+    let mut sort = false;
+    let RawMenu {
+        display_name,
+        body,
+        file,
+    } = menus
         .get(name)
-        .ok_or(anyhow!("Undefined symbol: {name}"))?
+        .ok_or(DotreeError::UndefinedSymbol(name.to_string()))?
         .clone();
+    let own_preamble = body
+        .clone()
+        .find(|p| p.as_rule() == Rule::preamble_def)
+        .map(|p| from_string(p.inext()));
+    let preamble = match (inherited_preamble, &own_preamble) {
+        (Some(inherited), Some(own)) => Some(format!("{inherited}\n{own}")),
+        (inherited, own) => inherited.map(String::from).or(own.clone()),
+    };
     for entry in body {
+        match entry.as_rule() {
+            Rule::preamble_def => continue,
+            Rule::header_def => {
+                header = Some(from_string(entry.inext()));
+                continue;
+            }
+            Rule::footer_def => {
+                footer = Some(from_string(entry.inext()));
+                continue;
+            }
+            Rule::sticky_def => {
+                sticky = true;
+                continue;
+            }
+            Rule::sort_def => {
+                sort = true;
+                continue;
+            }
+            _ => {}
+        }
+        let (line, _) = entry.line_col();
         let mut children = entry.into_inner();
-        let keys = children.next().unwrap().as_str().chars().collect();
+        let keys: Vec<char> = children.next().unwrap().as_str().chars().collect();
         let child_pair = children.next().unwrap();
+        if let Some(guard) = children.next() {
+            if !os_guard_matches(guard)? {
+                continue;
+            }
+        }
+        check_keys(name, &keys, line, &entry_lines)?;
+        entry_lines.push((keys.clone(), line));
         let next_node = match child_pair.as_rule() {
-            Rule::symbol => {
+            Rule::symbol if !commands.contains_key(child_pair.as_str()) => {
                 let submenu_name = child_pair.as_str();
                 Node::Menu(
-                    parse_menu(submenu_name, menus)
+                    parse_menu(submenu_name, menus, commands, preamble.as_deref())
                         .context(format!("Parsing submenu: {submenu_name}"))?,
                 )
             }
-            Rule::quick_command => {
-                let (display_name, toggle_echo_setting, exec_str) = parse_quick_command(child_pair);
-                Node::Command(Command {
-                    exec_str,
-                    name: display_name,
-                    settings: vec![],
-                    env_vars: vec![],
-                    shell: None,
-                    toggle_echo_setting,
+            Rule::dyn_menu => {
+                let mut elems = child_pair.into_inner();
+                let mut first = elems.next().unwrap();
+                let display_name = if first.as_rule() == Rule::string {
+                    let display_name = from_string(first);
+                    first = elems.next().unwrap();
+                    Some(display_name)
+                } else {
+                    None
+                };
+                let exec_str = parse_string_expr(first);
+                let var = elems.next().unwrap().as_str().to_string();
+                let cmd = parse_entry_command(elems.next().unwrap(), commands)
+                    .context(format!("Parsing dynmenu: {}", String::from_iter(&keys)))?;
+                Node::Menu(Menu {
+                    name: exec_str.to_string(),
+                    display_name,
+                    header: None,
+                    footer: None,
+                    sticky: false,
+                    preamble: None,
+                    entries: IndexMap::new(),
+                    dynamic: Some(Box::new(DynamicEntries {
+                        exec_str,
+                        var,
+                        command: Command {
+                            line,
+                            file: file.map(Path::to_path_buf),
+                            preamble: preamble.clone(),
+                            ..cmd
+                        },
+                    })),
                 })
             }
-            Rule::anon_command => Node::Command(parse_anon_command(child_pair)),
             _ => {
-                panic!("unexpected rule: {child_pair:?}")
+                let cmd = parse_entry_command(child_pair, commands)
+                    .context(format!("Parsing command: {}", String::from_iter(&keys)))?;
+                Node::Command(Box::new(Command {
+                    line,
+                    file: file.map(Path::to_path_buf),
+                    preamble: preamble.clone(),
+                    ..cmd
+                }))
             }
         };
         entries.insert(keys, next_node);
     }
+    if sort {
+        entries.sort_keys();
+    }
     Ok(Menu {
         name: name.to_string(),
         display_name,
+        header,
+        footer,
+        sticky,
+        preamble: own_preamble,
         entries,
+        dynamic: None,
     })
 }
 
-fn parse_anon_command(p: Pair<'_, Rule>) -> Command {
-    let body = p.inext();
+/// The values of `when os == ...`, the ones of std::env::consts::OS, and unix for its FAMILY
+const OS_NAMES: &[&str] = &[
+    "linux",
+    "macos",
+    "windows",
+    "freebsd",
+    "netbsd",
+    "openbsd",
+    "dragonfly",
+    "solaris",
+    "illumos",
+    "android",
+    "ios",
+    "unix",
+];
+
+/// Whether the current platform satisfies the guard of an entry
+fn os_guard_matches(p: Pair<'_, Rule>) -> Result<bool> {
+    let mut elems = p.into_inner();
+    let op = elems.next().unwrap().as_str();
+    let os = from_string(elems.next().unwrap());
+    ensure!(
+        OS_NAMES.contains(&os.as_str()),
+        "unknown os {os}, expected one of {}",
+        OS_NAMES.join(", ")
+    );
+    Ok(guard_matches_platform(
+        op,
+        &os,
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+    ))
+}
+
+/// Whether a guard with the operator and os name matches the platform with the os and family
+fn guard_matches_platform(op: &str, os: &str, platform: &str, family: &str) -> bool {
+    (os == platform || os == family) == (op == "==")
+}
+
+/// Whether there is a platform on which both guards match. No guard matches everywhere
+fn guards_overlap(a: Option<&(String, String)>, b: Option<&(String, String)>) -> bool {
+    let matches = |guard: Option<&(String, String)>, platform: &str| {
+        let family = if platform == "windows" {
+            "windows"
+        } else {
+            "unix"
+        };
+        match guard {
+            Some((op, os)) => guard_matches_platform(op, os, platform, family),
+            None => true,
+        }
+    };
+    OS_NAMES
+        .iter()
+        .filter(|&&name| name != "unix")
+        .any(|platform| matches(a, platform) && matches(b, platform))
+}
+
+/// The command of an entry, or the one the entries of a dynamic menu run
+fn parse_entry_command(
+    p: Pair<'_, Rule>,
+    commands: &HashMap<&str, Pair<'_, Rule>>,
+) -> Result<Command> {
+    match p.as_rule() {
+        Rule::symbol | Rule::command_ref => parse_command_ref(p, commands),
+        Rule::quick_command => Ok(parse_quick_command(p)),
+        Rule::anon_command => parse_anon_command(p),
+        _ => panic!("unexpected rule: {p:?}"),
+    }
+}
+
+fn parse_anon_command(p: Pair<'_, Rule>) -> Result<Command> {
+    parse_cmd_body(p.inext())
+}
+
+/// The named command a symbol or command_ref refers to. The variables the reference binds,
+/// are moved from the ones that are queried to the bound ones
+fn parse_command_ref(
+    p: Pair<'_, Rule>,
+    commands: &HashMap<&str, Pair<'_, Rule>>,
+) -> Result<Command> {
+    let (name, bindings) = match p.as_rule() {
+        Rule::command_ref => {
+            let mut elems = p.into_inner();
+            (elems.next().unwrap().as_str(), Some(elems))
+        }
+        _ => (p.as_str(), None),
+    };
+    let body = commands
+        .get(name)
+        .ok_or(DotreeError::UndefinedSymbol(name.to_string()))?;
+    let mut cmd = parse_cmd_body(body.clone())?;
+    for binding in bindings.into_iter().flatten() {
+        let mut elems = binding.into_inner();
+        let var = elems.next().unwrap().as_str();
+        let pos = cmd
+            .env_vars
+            .iter()
+            .position(|v| v.name == var)
+            .ok_or(anyhow!("{name} has no variable {var}, or it's bound twice"))?;
+        cmd.env_vars.remove(pos);
+        cmd.bound_vars
+            .push((var.to_string(), parse_string_expr(elems.next().unwrap())));
+    }
+    Ok(cmd)
+}
+
+fn parse_cmd_body(body: Pair<'_, Rule>) -> Result<Command> {
     let mut elems = body.into_inner();
     let mut parser = CmdBodyParser::default();
     loop {
         let p = elems.next().unwrap();
-        if let Some(cmd) = parser.parse(p) {
-            break cmd;
+        if let Some(cmd) = parser.parse(p)? {
+            break Ok(cmd);
         }
     }
 }
@@ -276,87 +1035,281 @@ struct CmdBodyParser {
     settings: Option<Vec<CommandSetting>>,
     vars: Option<Vec<VarDef>>,
     shell_def: Option<ShellDef>,
+    on_error: Option<StringExpr>,
+    finally: Option<StringExpr>,
+    stdin: Option<StringExpr>,
+    path_prepend: Option<Vec<String>>,
+    unset_env: Option<Vec<String>>,
 }
 
 impl CmdBodyParser {
-    fn parse(&mut self, p: Pair<'_, Rule>) -> Option<Command> {
-        match p.as_rule() {
+    fn parse(&mut self, p: Pair<'_, Rule>) -> Result<Option<Command>> {
+        Ok(match p.as_rule() {
             Rule::cmd_settings => {
-                self.settings = Some(parse_cmd_settings(p));
+                self.settings = Some(parse_cmd_settings(p)?);
                 None
             }
             Rule::vars_def => {
-                self.vars = Some(parse_vars_def(p));
+                self.vars = Some(parse_vars_def(p)?);
                 None
             }
             Rule::shell_def => {
-                self.shell_def = Some(parse_shell_def(p));
+                self.shell_def = Some(parse_shell_def(p)?);
                 None
             }
-            Rule::quick_command => {
-                let (display_name, toggle_echo_setting, exec_str) = parse_quick_command(p);
-                Some(Command {
-                    exec_str,
-                    settings: self.settings.take().unwrap_or_default(),
-                    name: display_name,
-                    env_vars: self.vars.take().unwrap_or_default(),
-                    shell: self.shell_def.take(),
-                    toggle_echo_setting,
-                })
+            Rule::on_error_def => {
+                self.on_error = Some(parse_string_expr(p.inext()));
+                None
+            }
+            Rule::finally_def => {
+                self.finally = Some(parse_string_expr(p.inext()));
+                None
+            }
+            Rule::stdin_def => {
+                self.stdin = Some(parse_string_expr(p.inext()));
+                None
             }
+            Rule::path_prepend_def => {
+                self.path_prepend = Some(parse_string_list(p.inext()));
+                None
+            }
+            Rule::unset_env_def => {
+                self.unset_env = Some(parse_string_list(p.inext()));
+                None
+            }
+            Rule::quick_command => Some(Command {
+                settings: self.settings.take().unwrap_or_default(),
+                env_vars: self.vars.take().unwrap_or_default(),
+                shell: self.shell_def.take(),
+                on_error: self.on_error.take(),
+                finally: self.finally.take(),
+                stdin: self.stdin.take(),
+                path_prepend: self.path_prepend.take().unwrap_or_default(),
+                unset_env: self.unset_env.take().unwrap_or_default(),
+                ..parse_quick_command(p)
+            }),
             _ => panic!("unexpected rule: {p:#?}"),
-        }
+        })
     }
 }
 
-fn parse_cmd_settings(p: Pair<'_, Rule>) -> Vec<CommandSetting> {
+fn parse_cmd_settings(p: Pair<'_, Rule>) -> Result<Vec<CommandSetting>> {
     let mut res = vec![];
     for pair in p.into_inner() {
-        assert!(pair.as_rule() == Rule::symbol);
-        res.push(match pair.as_str() {
-            "repeat" => CommandSetting::Repeat,
-            "ignore_result" => CommandSetting::IgnoreResult,
-            other => panic!("invalid command setting: {other}"),
+        assert!(pair.as_rule() == Rule::cmd_setting);
+        let mut inner = pair.into_inner();
+        let name = inner.next().unwrap().as_str();
+        let value = inner.next().map(|v| match v.as_rule() {
+            Rule::string => from_string(v),
+            _ => v.as_str().to_string(),
+        });
+        res.push(match (name, value.as_deref()) {
+            ("repeat", None) => CommandSetting::Repeat,
+            ("ignore_result", None) => CommandSetting::IgnoreResult,
+            ("log", None) => CommandSetting::Log,
+            ("exclusive", None) => CommandSetting::Exclusive,
+            ("danger", None) => CommandSetting::Danger,
+            ("show_last_run", None) => CommandSetting::ShowLastRun,
+            ("trace_env", None) => CommandSetting::TraceEnv,
+            ("reuse_vars", None) => CommandSetting::ReuseVars,
+            ("sudo", None) => CommandSetting::Sudo,
+            ("tmux_window", None) => CommandSetting::Tmux(TmuxTarget::Window),
+            ("tmux_pane", None) => CommandSetting::Tmux(TmuxTarget::Pane),
+            ("cooldown", Some(value)) => CommandSetting::Cooldown(
+                humantime::parse_duration(value)
+                    .context(format!("invalid duration for cooldown: {value}"))?,
+            ),
+            ("cooldown", None) => bail!("cooldown needs a duration, e.g. `set cooldown 1h`"),
+            ("watch", Some(pattern)) => CommandSetting::Watch(pattern.to_string()),
+            ("watch", None) => bail!("watch needs a pattern, e.g. `set watch \"src/**/*.rs\"`"),
+            ("cwd", Some("git-root")) => CommandSetting::GitRootCwd,
+            ("wsl", distro) => CommandSetting::Wsl(distro.map(String::from)),
+            ("confirm", question) => CommandSetting::Confirm(question.map(String::from)),
+            ("cwd", value) => bail!(
+                "invalid value for cwd: {}, the only supported one is git-root",
+                value.unwrap_or("none")
+            ),
+            (
+                name @ ("repeat" | "ignore_result" | "log" | "exclusive" | "danger"
+                | "show_last_run" | "trace_env" | "reuse_vars" | "sudo" | "tmux_window"
+                | "tmux_pane"),
+                Some(value),
+            ) => {
+                bail!("{name} doesn't take a value, but got {value}")
+            }
+            (other, _) => bail!("invalid command setting: {other}"),
         })
     }
-    res
+    let has_watch = res.iter().any(|s| matches!(s, CommandSetting::Watch(_)));
+    ensure!(
+        !has_watch || res.contains(&CommandSetting::Repeat),
+        "watch can only be used together with repeat"
+    );
+    let n_tmux = res
+        .iter()
+        .filter(|s| matches!(s, CommandSetting::Tmux(_)))
+        .count();
+    ensure!(
+        n_tmux <= 1,
+        "tmux_window and tmux_pane can't be used together"
+    );
+    Ok(res)
 }
 
-fn parse_vars_def(p: Pair<'_, Rule>) -> Vec<VarDef> {
-    fn parse_var_def(p: Pair<'_, Rule>) -> VarDef {
-        assert!(p.as_rule() == Rule::var_def, "unexpected rule: {p:#?}");
-        let mut p = p.into_inner();
-        let name_def = p.next().unwrap();
-        let value_def = p.next();
+fn parse_string_list(p: Pair<'_, Rule>) -> Vec<String> {
+    assert!(p.as_rule() == Rule::string_list, "unexpected rule: {p:#?}");
+    p.into_inner().map(from_string).collect()
+}
 
-        let name = name_def.as_str().to_string();
-        let value = value_def.map(|v| {
-            assert!(v.as_rule() == Rule::default_var, "unexpected rule: {p:#?}");
-            from_string(v.inext())
-        });
+fn parse_multi_select(p: Pair<'_, Rule>) -> VarKind {
+    let mut options = vec![];
+    let mut separator = " ".to_string();
+    for p in p.into_inner() {
+        match p.as_rule() {
+            Rule::string => options.push(from_string(p)),
+            Rule::multi_sep => separator = from_string(p.inext()),
+            _ => panic!("unexpected rule: {p:#?}"),
+        }
+    }
+    VarKind::MultiSelect { options, separator }
+}
 
-        VarDef { name, value }
+fn parse_vars_def(p: Pair<'_, Rule>) -> Result<Vec<VarDef>> {
+    fn parse_var_def(p: Pair<'_, Rule>) -> Result<VarDef> {
+        assert!(p.as_rule() == Rule::var_def, "unexpected rule: {p:#?}");
+        let mut p = p.into_inner();
+        let name = p.next().unwrap().as_str().to_string();
+        let mut var = VarDef {
+            name,
+            value: None,
+            order: None,
+            kind: VarKind::Input,
+            nohist: false,
+            secret: false,
+        };
+        for p in p {
+            match p.as_rule() {
+                Rule::var_order => {
+                    let order = p.as_str()[1..].parse().context("parsing prompt order")?;
+                    var.order = Some(order);
+                }
+                Rule::var_nohist => var.nohist = true,
+                Rule::var_secret => {
+                    var.secret = true;
+                    var.nohist = true;
+                }
+                Rule::default_var => var.value = Some(parse_string_expr(p.inext())),
+                Rule::multi_select => var.kind = parse_multi_select(p),
+                Rule::dirs_select => var.kind = VarKind::Dir,
+                Rule::fzf_select => {
+                    var.kind = VarKind::Fzf {
+                        helper: parse_string_expr(p.inext()),
+                    }
+                }
+                Rule::pick_select => {
+                    var.kind = VarKind::Pick {
+                        helper: parse_string_expr(p.inext()),
+                    }
+                }
+                _ => panic!("unexpected rule: {p:#?}"),
+            }
+        }
+        ensure!(
+            !var.secret || var.kind == VarKind::Input,
+            "{} can't be secret, only values that are typed in can",
+            var.name
+        );
+        Ok(var)
     }
 
     assert!(p.as_rule() == Rule::vars_def);
-    p.into_inner().map(parse_var_def).collect()
+    let vars = p
+        .into_inner()
+        .map(parse_var_def)
+        .collect::<Result<Vec<_>>>()?;
+    // defaults and helpers are resolved in prompt order, so they can only use the variables
+    // before them
+    let ordered = prompt_order(&vars);
+    for (i, var) in ordered.iter().enumerate() {
+        let helper = var.kind.helper();
+        for sym in var.value.iter().chain(helper).flat_map(StringExpr::symbols) {
+            ensure!(
+                !ordered[i..].iter().any(|v| v.name == sym),
+                "the default of {} uses {sym}, which isn't queried before it",
+                var.name
+            );
+        }
+    }
+    Ok(vars)
 }
 
-fn parse_quick_command(pair: Pair<'_, Rule>) -> (Option<String>, bool, StringExpr) {
+/// A command with only the parts of a quick command. The line is set by the menu that contains
+/// it, and the other parts by the body of a cmd
+fn parse_quick_command(pair: Pair<'_, Rule>) -> Command {
     assert!(pair.as_rule() == Rule::quick_command);
     let mut name = None;
+    let mut localized_names = vec![];
     let mut toggle_echo = false;
+    let mut action = None;
     let mut str_expr = None;
 
     for elem in pair.into_inner() {
         match elem.as_rule() {
-            Rule::command_name => name = Some(from_string(elem.inext())),
+            Rule::command_name => {
+                let name_pair = elem.inext();
+                if name_pair.as_rule() == Rule::localized_names {
+                    localized_names = parse_localized_names(name_pair);
+                    name = localized_names
+                        .iter()
+                        .find(|(lang, _)| lang == "en")
+                        .or(localized_names.first())
+                        .map(|(_, name)| name.clone());
+                } else {
+                    name = Some(from_string(name_pair));
+                }
+            }
             Rule::ECHO_TOGGLE_TOKEN => toggle_echo = true,
+            Rule::action => {
+                action = Some(match elem.as_str() {
+                    "open-url" => Action::OpenUrl,
+                    "copy" => Action::Copy,
+                    "cd" => Action::Cd,
+                    _ => Action::Print,
+                })
+            }
             Rule::string_expr => str_expr = Some(parse_string_expr(elem)),
             _ => panic!("unexpected pair: {elem:#?}"),
         }
     }
-    (name, toggle_echo, str_expr.unwrap())
+    Command {
+        exec_str: str_expr.unwrap(),
+        action,
+        settings: vec![],
+        name,
+        shell: None,
+        env_vars: vec![],
+        bound_vars: vec![],
+        toggle_echo_setting: toggle_echo,
+        on_error: None,
+        finally: None,
+        stdin: None,
+        path_prepend: vec![],
+        unset_env: vec![],
+        localized_names,
+        line: 0,
+        file: None,
+        preamble: None,
+    }
+}
+
+fn parse_localized_names(p: Pair<'_, Rule>) -> Vec<(String, String)> {
+    p.into_inner()
+        .map(|localized_name| {
+            let mut inner = localized_name.into_inner();
+            let lang = inner.next().unwrap().as_str().to_string();
+            (lang, from_string(inner.next().unwrap()))
+        })
+        .collect()
 }
 
 fn parse_string_expr(p: Pair<'_, Rule>) -> StringExpr {
@@ -375,6 +1328,273 @@ fn parse_string_expr(p: Pair<'_, Rule>) -> StringExpr {
     StringExpr(res)
 }
 
+/// A problem in a config file, that doesn't prevent it from being parsed
+#[derive(Debug, Clone)]
+pub struct LintWarning {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl LintWarning {
+    fn new(p: &Pair<'_, Rule>, message: String) -> Self {
+        let (line, col) = p.line_col();
+        LintWarning { line, col, message }
+    }
+}
+
+/// Checks a config for unused snippets, menus and commands, unreachable entries and references
+/// to undefined snippets. Empty menus can't occur, since the grammar requires an entry in every
+/// menu, besides its header, footer and settings. Files without a root menu can only be
+/// included, so their definitions aren't reported as unused, since the including file may use
+/// them, and their snippets aren't reported as undefined. Included files aren't checked.
+pub fn lint(src: &str) -> error::Result<Vec<LintWarning>> {
+    let mut pairs = ConfigParser::parse(Rule::file, src).map_err(parse_error)?;
+    let file = pairs.next().unwrap();
+    let mut res = vec![];
+
+    let mut menu_defs = vec![];
+    let mut command_defs = vec![];
+    let mut snippet_defs = vec![];
+    // the menus and commands entries refer to
+    let mut menu_refs = vec!["root"];
+    let mut snippet_refs = vec![];
+    // positions of references to variables in defaults, which look like snippet references
+    let mut var_refs = vec![];
+    // the snippets that seem to be undefined, might be defined in the included files
+    let mut has_includes = false;
+    for p in file.clone().into_inner() {
+        match p.as_rule() {
+            Rule::include => has_includes = true,
+            Rule::menu => {
+                let name = p
+                    .clone()
+                    .into_inner()
+                    .find(|x| x.as_rule() == Rule::symbol)
+                    .unwrap();
+                menu_defs.push(name);
+            }
+            Rule::command_def => command_defs.push(p.inext()),
+            Rule::snippet => snippet_defs.push(p.inext()),
+            _ => {}
+        }
+    }
+    for p in file.into_inner().flatten() {
+        match p.as_rule() {
+            Rule::entry => {
+                let target = p.into_inner().nth(1).unwrap();
+                match target.as_rule() {
+                    Rule::symbol => menu_refs.push(target.as_str()),
+                    Rule::command_ref => menu_refs.push(target.inext().as_str()),
+                    Rule::dyn_menu => {
+                        let mut elems: Vec<_> = target.into_inner().collect();
+                        let command = elems.pop().unwrap();
+                        let var = elems.pop().unwrap().as_str();
+                        match command.as_rule() {
+                            Rule::symbol => menu_refs.push(command.as_str()),
+                            Rule::command_ref => menu_refs.push(command.clone().inext().as_str()),
+                            _ => {}
+                        }
+                        // the line is bound to the variable, which actions refer to
+                        for sym in command.into_inner().flatten() {
+                            if sym.as_rule() == Rule::snippet_symbol && sym.as_str()[1..] == *var {
+                                var_refs.push(sym.as_span().start());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Rule::menu_body => res.extend(lint_menu_body(p)),
+            Rule::vars_def => {
+                // using a variable that isn't queried before is a parse error
+                let names: Vec<_> = p
+                    .clone()
+                    .into_inner()
+                    .map(|var_def| var_def.inext().as_str())
+                    .collect();
+                for sym in p.into_inner().flatten() {
+                    if sym.as_rule() == Rule::snippet_symbol && names.contains(&&sym.as_str()[1..])
+                    {
+                        var_refs.push(sym.as_span().start());
+                    }
+                }
+            }
+            Rule::cmd_body => {
+                // actions refer to variables like to snippets, since there is no shell
+                let names: Vec<_> = p
+                    .clone()
+                    .into_inner()
+                    .filter(|def| def.as_rule() == Rule::vars_def)
+                    .flat_map(|def| def.into_inner().map(|var_def| var_def.inext().as_str()))
+                    .collect();
+                let quick = p.clone().into_inner().last().unwrap();
+                if quick
+                    .clone()
+                    .into_inner()
+                    .any(|x| x.as_rule() == Rule::action)
+                {
+                    for sym in quick.into_inner().flatten() {
+                        if sym.as_rule() == Rule::snippet_symbol
+                            && names.contains(&&sym.as_str()[1..])
+                        {
+                            var_refs.push(sym.as_span().start());
+                        }
+                    }
+                }
+            }
+            Rule::snippet_symbol if !var_refs.contains(&p.as_span().start()) => {
+                snippet_refs.push(p)
+            }
+            _ => {}
+        }
+    }
+
+    let included = !menu_defs.iter().any(|def| def.as_str() == "root");
+    for def in menu_defs.iter().filter(|_| !included) {
+        if !menu_refs.contains(&def.as_str()) {
+            let msg = format!("menu {} is never used", def.as_str());
+            res.push(LintWarning::new(def, msg));
+        }
+    }
+    for def in command_defs.iter().filter(|_| !included) {
+        if !menu_refs.contains(&def.as_str()) {
+            let msg = format!("command {} is never used", def.as_str());
+            res.push(LintWarning::new(def, msg));
+        }
+    }
+    for def in snippet_defs.iter().filter(|_| !included) {
+        if !snippet_refs
+            .iter()
+            .any(|r| r.as_str()[1..] == *def.as_str())
+        {
+            let msg = format!("snippet {} is never used", def.as_str());
+            res.push(LintWarning::new(def, msg));
+        }
+    }
+    for r in snippet_refs.iter().filter(|_| !has_includes && !included) {
+        if !snippet_defs.iter().any(|d| d.as_str() == &r.as_str()[1..]) {
+            let msg = format!("undefined snippet: {}", &r.as_str()[1..]);
+            res.push(LintWarning::new(r, msg));
+        }
+    }
+    res.sort_by_key(|w| (w.line, w.col));
+    Ok(res)
+}
+
+fn lint_menu_body(body: Pair<'_, Rule>) -> Vec<LintWarning> {
+    // entries for platforms that exclude each other can share their keys
+    let keydefs: Vec<_> = body
+        .into_inner()
+        .filter(|p| p.as_rule() == Rule::entry)
+        .map(|entry| {
+            let guard = entry
+                .clone()
+                .into_inner()
+                .find(|p| p.as_rule() == Rule::os_guard)
+                .map(|guard| {
+                    let mut elems = guard.into_inner();
+                    let op = elems.next().unwrap().as_str().to_string();
+                    (op, from_string(elems.next().unwrap()))
+                });
+            (entry.inext(), guard)
+        })
+        .collect();
+    let mut res = vec![];
+    for (i, (keydef, guard)) in keydefs.iter().enumerate() {
+        let keys = keydef.as_str();
+        for (j, (other, other_guard)) in keydefs.iter().enumerate() {
+            if !guards_overlap(guard.as_ref(), other_guard.as_ref()) {
+                continue;
+            }
+            let other_keys = other.as_str();
+            if keys == other_keys && i > j {
+                res.push(LintWarning::new(keydef, format!("duplicate key {keys}")));
+                break;
+            } else if keys != other_keys && keys.starts_with(other_keys) {
+                res.push(LintWarning::new(
+                    keydef,
+                    format!("entry {keys} can't be reached, because {other_keys} is a prefix"),
+                ));
+                break;
+            }
+        }
+    }
+    res
+}
+
+/// Fails if an entry of the menu that was parsed before, in the given line, has the same keys,
+/// or if the keys of one are a prefix of the ones of the other, so it can't be reached
+fn check_keys(menu: &str, keys: &[char], line: usize, before: &[(Vec<char>, usize)]) -> Result<()> {
+    let keys_str = String::from_iter(keys);
+    for (other, other_line) in before {
+        let other_str = String::from_iter(other);
+        ensure!(
+            other != keys,
+            "menu {menu} has two entries with the keys {keys_str}, in line {other_line} and {line}"
+        );
+        let ((short, short_line), (long, long_line)) = if keys.starts_with(other) {
+            ((&other_str, *other_line), (&keys_str, line))
+        } else if other.starts_with(keys) {
+            ((&keys_str, line), (&other_str, *other_line))
+        } else {
+            continue;
+        };
+        bail!(
+            "entry {long} (line {long_line}) of menu {menu} can't be reached, because \
+             {short} (line {short_line}) is a prefix of its keys"
+        );
+    }
+    Ok(())
+}
+
+fn parse_theme(p: Pair<'_, Rule>) -> Result<Theme> {
+    assert!(p.as_rule() == Rule::theme_setting);
+    let mut theme = Theme::default();
+    for style in p.into_inner() {
+        let mut elems = style.into_inner();
+        let part = elems.next().unwrap().as_str();
+        let style = parse_style(&from_string(elems.next().unwrap()))
+            .context(format!("invalid style for {part}"))?;
+        let field = match part {
+            "prefix" => &mut theme.prefix,
+            "keys" => &mut theme.keys,
+            "title" => &mut theme.title,
+            "menu" => &mut theme.menu,
+            _ => &mut theme.command,
+        };
+        *field = Some(style);
+    }
+    Ok(theme)
+}
+
+/// Checks the words of a style, which are separated by spaces or dots, and returns them as
+/// dotted style string. The words are colors, `on_` and a color for the background, the 256
+/// colors by number, and text attributes
+fn parse_style(src: &str) -> Result<String> {
+    const COLORS: &[&str] = &[
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white", "bright",
+    ];
+    const ATTRIBUTES: &[&str] = &[
+        "bold",
+        "dim",
+        "underlined",
+        "blink",
+        "reverse",
+        "hidden",
+        "strikethrough",
+    ];
+    let words: Vec<_> = src.split([' ', '.']).filter(|w| !w.is_empty()).collect();
+    for word in &words {
+        let color = word.strip_prefix("on_").unwrap_or(word);
+        ensure!(
+            COLORS.contains(&color) || color.parse::<u8>().is_ok() || ATTRIBUTES.contains(word),
+            "unknown color or attribute: {word}"
+        );
+    }
+    Ok(words.join("."))
+}
+
 fn parse_echo_setting(p: Pair<'_, Rule>) -> bool {
     assert!(p.as_rule() == Rule::echo_setting);
     p.inext().as_str() == "on"
@@ -405,31 +1625,133 @@ impl std::fmt::Display for StringExpr {
 
 impl std::fmt::Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(name) = &self.name {
+        if let Some(name) = self.localized_name() {
             write!(f, "{name}")
+        } else if let Some(action) = self.action {
+            write!(f, "{action} {}", self.exec_str)
         } else {
             write!(f, "{}", self.exec_str)
         }
     }
 }
 
-impl Command {
-    pub fn repeat(&self) -> bool {
-        self.settings.contains(&CommandSetting::Repeat)
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self {
+            Action::OpenUrl => "open-url",
+            Action::Copy => "copy",
+            Action::Print => "print",
+            Action::Cd => "cd",
+        };
+        write!(f, "{keyword}")
     }
 }
 
-impl Default for ShellDef {
-    fn default() -> Self {
-        #[cfg(not(windows))]
-        let res = ShellDef {
-            name: "bash".into(),
-            args: vec!["-euo".into(), "pipefail".into(), "-c".into()],
-        };
-
-        #[cfg(windows)]
-        let res = ShellDef {
-            name: "cmd".into(),
+impl std::fmt::Display for ShellDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            if arg.contains(char::is_whitespace) {
+                write!(f, " {arg:?}")?;
+            } else {
+                write!(f, " {arg}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    /// The name in the selected language, or the default name if it has no variant for it
+    pub fn localized_name(&self) -> Option<&str> {
+        i18n::lang()
+            .and_then(|lang| self.localized_names.iter().find(|(l, _)| l == lang))
+            .map(|(_, name)| name.as_str())
+            .or(self.name.as_deref())
+    }
+
+    /// The code the shell runs for the resolved exec string: the preamble, followed by `arg`
+    pub fn script(&self, arg: &str) -> String {
+        match &self.preamble {
+            Some(preamble) => format!("{preamble}\n{arg}"),
+            None => arg.to_string(),
+        }
+    }
+
+    pub fn repeat(&self) -> bool {
+        self.settings.contains(&CommandSetting::Repeat)
+    }
+
+    pub fn danger(&self) -> bool {
+        self.settings.contains(&CommandSetting::Danger)
+    }
+
+    pub fn sudo(&self) -> bool {
+        self.settings.contains(&CommandSetting::Sudo)
+    }
+
+    pub fn tmux(&self) -> Option<TmuxTarget> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Tmux(target) => Some(*target),
+            _ => None,
+        })
+    }
+
+    pub fn watch(&self) -> Option<&str> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Watch(pattern) => Some(pattern.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn cooldown(&self) -> Option<Duration> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Cooldown(d) => Some(*d),
+            _ => None,
+        })
+    }
+
+    /// None if the command doesn't run inside WSL, otherwise the distribution, if it has one
+    pub fn wsl(&self) -> Option<Option<&str>> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Wsl(distro) => Some(distro.as_deref()),
+            _ => None,
+        })
+    }
+
+    /// None if the command doesn't have to be confirmed, otherwise its own question, if it
+    /// has one
+    pub fn confirm(&self) -> Option<Option<&str>> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Confirm(question) => Some(question.as_deref()),
+            _ => None,
+        })
+    }
+
+    pub fn vars_in_prompt_order(&self) -> Vec<&VarDef> {
+        prompt_order(&self.env_vars)
+    }
+}
+
+/// Variables with a prompt order come first, sorted by it, followed by the others. Apart from
+/// that, the order of the definition is kept
+fn prompt_order(vars: &[VarDef]) -> Vec<&VarDef> {
+    let mut res: Vec<_> = vars.iter().collect();
+    res.sort_by_key(|v| v.order.unwrap_or(u32::MAX));
+    res
+}
+
+impl Default for ShellDef {
+    fn default() -> Self {
+        #[cfg(not(windows))]
+        let res = ShellDef {
+            name: "bash".into(),
+            args: vec!["-euo".into(), "pipefail".into(), "-c".into()],
+        };
+
+        #[cfg(windows)]
+        let res = ShellDef {
+            name: "cmd".into(),
             args: vec!["/c".into()],
         };
 
@@ -449,25 +1771,65 @@ impl ShellDef {
 
 impl StringExpr {
     pub fn resolve(&self, snippet_table: &SnippetTable) -> Result<String> {
-        self.inner_resolve(snippet_table, vec![])
+        self.resolve_with_vars(snippet_table, &[])
+    }
+
+    /// Like `resolve`, but symbols that name one of `vars` are replaced by its value instead
+    /// of a snippet
+    pub fn resolve_with_vars(
+        &self,
+        snippet_table: &SnippetTable,
+        vars: &[(String, String)],
+    ) -> Result<String> {
+        self.inner_resolve(snippet_table, vars, vec![])
+    }
+
+    /// The names of the snippets or variables that are referenced
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|x| match x {
+            StringExprElem::Symbol(s) => Some(s.as_str()),
+            StringExprElem::String(_) => None,
+        })
     }
 
-    fn inner_resolve(&self, snippet_table: &SnippetTable, parents: Vec<String>) -> Result<String> {
+    /// An expression that consists of a single string
+    pub fn plain(s: impl Into<String>) -> Self {
+        Self(vec![StringExprElem::String(s.into())])
+    }
+
+    /// The content, if the expression consists of a single string
+    pub fn as_plain_str(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [StringExprElem::String(s)] => Some(s),
+            _ => None,
+        }
+    }
+
+    fn inner_resolve(
+        &self,
+        snippet_table: &SnippetTable,
+        vars: &[(String, String)],
+        parents: Vec<String>,
+    ) -> Result<String> {
         let elems: Vec<_> = self
             .0
             .iter()
             .map(|x| match x {
+                StringExprElem::Symbol(s) if vars.iter().any(|(name, _)| name == s) => {
+                    Ok(vars.iter().find(|(name, _)| name == s).unwrap().1.clone())
+                }
                 StringExprElem::Symbol(s) => {
                     let snip = snippet_table
                         .get(s)
-                        .ok_or(anyhow!("Undefined snippet: {s}"))?;
+                        .ok_or(DotreeError::UndefinedSnippet(s.clone()))?;
                     let mut parents = parents.clone();
-                    ensure!(
-                        !parents.contains(s),
-                        "Detected cycle while resolving String Expression: {parents:?}"
-                    );
+                    if let Some(start) = parents.iter().position(|p| p == s) {
+                        let mut cycle = parents[start..].to_vec();
+                        cycle.push(s.clone());
+                        return Err(DotreeError::SnippetCycle(cycle).into());
+                    }
                     parents.push(s.clone());
-                    snip.inner_resolve(snippet_table, parents)
+                    snip.inner_resolve(snippet_table, &[], parents)
                 }
                 StringExprElem::String(s) => Ok(s.clone()),
             })
@@ -550,6 +1912,56 @@ mod tests {
         }
     "#;
 
+    const WITH_COOLDOWN: &str = r#"
+        menu root {
+            a: cmd {
+                set log, cooldown 1h30m
+                "sync"
+            }
+        }
+    "#;
+
+    const WITH_ON_ERROR: &str = r#"
+        shell sh -c
+        on_error "notify-send failed"
+
+        menu root {
+            a: cmd {
+                on_error "echo $DT_EXIT_CODE"
+                finally "rm -rf /tmp/foo"
+                "false"
+            }
+            b: "false"
+        }
+    "#;
+
+    const WITH_VAR_REFS: &str = r#"
+        snippet registry = "registry.example.com/"
+
+        menu root {
+            a: cmd {
+                vars project, image = $registry + $project + ":latest"
+                "docker push $image"
+            }
+        }
+    "#;
+
+    const LINT_CONF: &str = r#"
+        snippet used = "a"
+        snippet unused = "b"
+
+        menu root {
+            a: $used + $undefined
+            ab: "unreachable"
+            c: "first"
+            c: "second"
+        }
+
+        menu lonely {
+            x: "echo x"
+        }
+    "#;
+
     #[test]
     fn test_parsing() -> Result<()> {
         let root = parse(CONF)?;
@@ -560,6 +1972,10 @@ Config {
     menu: Menu {
         name: "root",
         display_name: None,
+        header: None,
+        footer: None,
+        sticky: false,
+        preamble: None,
         entries: {
             [
                 'c',
@@ -567,46 +1983,73 @@ Config {
                 Menu {
                     name: "custom_commands",
                     display_name: None,
+                    header: None,
+                    footer: None,
+                    sticky: false,
+                    preamble: None,
                     entries: {
                         [
-                            'c',
+                            'h',
                         ]: Command(
                             Command {
                                 exec_str: StringExpr(
                                     [
                                         String(
-                                            "echo ciao",
+                                            "echo hi",
                                         ),
                                     ],
                                 ),
+                                action: None,
                                 settings: [],
-                                name: None,
+                                name: Some(
+                                    "print hi",
+                                ),
                                 shell: None,
                                 env_vars: [],
-                                toggle_echo_setting: true,
+                                bound_vars: [],
+                                toggle_echo_setting: false,
+                                on_error: None,
+                                finally: None,
+                                stdin: None,
+                                path_prepend: [],
+                                unset_env: [],
+                                localized_names: [],
+                                line: 8,
+                                file: None,
+                                preamble: None,
                             },
                         ),
                         [
-                            'h',
+                            'c',
                         ]: Command(
                             Command {
                                 exec_str: StringExpr(
                                     [
                                         String(
-                                            "echo hi",
+                                            "echo ciao",
                                         ),
                                     ],
                                 ),
+                                action: None,
                                 settings: [],
-                                name: Some(
-                                    "print hi",
-                                ),
+                                name: None,
                                 shell: None,
                                 env_vars: [],
-                                toggle_echo_setting: false,
+                                bound_vars: [],
+                                toggle_echo_setting: true,
+                                on_error: None,
+                                finally: None,
+                                stdin: None,
+                                path_prepend: [],
+                                unset_env: [],
+                                localized_names: [],
+                                line: 9,
+                                file: None,
+                                preamble: None,
                             },
                         ),
                     },
+                    dynamic: None,
                 },
             ),
             [
@@ -620,18 +2063,45 @@ Config {
                             ),
                         ],
                     ),
+                    action: None,
                     settings: [],
                     name: None,
                     shell: None,
                     env_vars: [],
+                    bound_vars: [],
                     toggle_echo_setting: false,
+                    on_error: None,
+                    finally: None,
+                    stdin: None,
+                    path_prepend: [],
+                    unset_env: [],
+                    localized_names: [],
+                    line: 4,
+                    file: None,
+                    preamble: None,
                 },
             ),
         },
+        dynamic: None,
     },
     settings: Settings {
         shell_def: None,
         echo_by_default: true,
+        on_error: None,
+        lang: None,
+        encrypt_history: None,
+        preamble: None,
+        root_key: None,
+        back_key: None,
+        idle_timeout: None,
+        history_max: None,
+        theme: Theme {
+            prefix: None,
+            keys: None,
+            title: None,
+            menu: None,
+            command: None,
+        },
     },
     snippet_table: {},
 }
@@ -647,10 +2117,14 @@ Config {
             root,
             r#"
 Err(
-    Error {
-        context: "Parsing submenu: missing",
-        source: "Undefined symbol: missing",
-    },
+    Other(
+        Error {
+            context: "Parsing submenu: missing",
+            source: UndefinedSymbol(
+                "missing",
+            ),
+        },
+    ),
 )
 "#
         );
@@ -664,13 +2138,56 @@ Err(
             root,
             r#"
 Err(
-    "Undefined symbol: root",
+    UndefinedSymbol(
+        "root",
+    ),
 )
 "#
         );
         Ok(())
     }
 
+    #[test]
+    fn test_syntax_error() {
+        let res = parse("menu root {\n    a:\n}");
+        assert!(
+            matches!(
+                res,
+                Err(DotreeError::ParseError {
+                    line: 2,
+                    col: 7,
+                    ..
+                })
+            ),
+            "{res:?}"
+        );
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "2:7: expected a dynmenu, a name, or a command\n  |\n2 |     a:\n  |       ^"
+        );
+        let err = parse("menu root {\n\ta: \"x\" when\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2:9: expected header, footer, set sticky, sort alphabetical, preamble, an entry, or \
+             when os\n  |\n2 | \ta: \"x\" when\n  | \t       ^"
+        );
+    }
+
+    #[test]
+    fn test_conflicting_keys() {
+        let err = parse("menu root {\n    c: \"first\"\n    c: \"second\"\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "menu root has two entries with the keys c, in line 2 and 3"
+        );
+        let err = parse("menu root {\n    ab: \"b\"\n    a: \"a\"\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "entry ab (line 2) of menu root can't be reached, because a (line 3) is a prefix of \
+             its keys"
+        );
+    }
+
     #[test]
     fn anon_cmd() -> Result<()> {
         let root = parse(ANON_CMD);
@@ -682,6 +2199,10 @@ Ok(
         menu: Menu {
             name: "root",
             display_name: None,
+            header: None,
+            footer: None,
+            sticky: false,
+            preamble: None,
             entries: {
                 [
                     'c',
@@ -694,18 +2215,45 @@ Ok(
                                 ),
                             ],
                         ),
+                        action: None,
                         settings: [],
                         name: None,
                         shell: None,
                         env_vars: [],
+                        bound_vars: [],
                         toggle_echo_setting: false,
+                        on_error: None,
+                        finally: None,
+                        stdin: None,
+                        path_prepend: [],
+                        unset_env: [],
+                        localized_names: [],
+                        line: 3,
+                        file: None,
+                        preamble: None,
                     },
                 ),
             },
+            dynamic: None,
         },
         settings: Settings {
             shell_def: None,
             echo_by_default: true,
+            on_error: None,
+            lang: None,
+            encrypt_history: None,
+            preamble: None,
+            root_key: None,
+            back_key: None,
+            idle_timeout: None,
+            history_max: None,
+            theme: Theme {
+                prefix: None,
+                keys: None,
+                title: None,
+                menu: None,
+                command: None,
+            },
         },
         snippet_table: {},
     },
@@ -725,6 +2273,10 @@ Config {
     menu: Menu {
         name: "root",
         display_name: None,
+        header: None,
+        footer: None,
+        sticky: false,
+        preamble: None,
         entries: {
             [
                 'c',
@@ -737,6 +2289,7 @@ Config {
                             ),
                         ],
                     ),
+                    action: None,
                     settings: [],
                     name: None,
                     shell: None,
@@ -744,20 +2297,54 @@ Config {
                         VarDef {
                             name: "foo",
                             value: None,
+                            order: None,
+                            kind: Input,
+                            nohist: false,
+                            secret: false,
                         },
                         VarDef {
                             name: "bar",
                             value: None,
+                            order: None,
+                            kind: Input,
+                            nohist: false,
+                            secret: false,
                         },
                     ],
+                    bound_vars: [],
                     toggle_echo_setting: false,
+                    on_error: None,
+                    finally: None,
+                    stdin: None,
+                    path_prepend: [],
+                    unset_env: [],
+                    localized_names: [],
+                    line: 3,
+                    file: None,
+                    preamble: None,
                 },
             ),
         },
+        dynamic: None,
     },
     settings: Settings {
         shell_def: None,
         echo_by_default: true,
+        on_error: None,
+        lang: None,
+        encrypt_history: None,
+        preamble: None,
+        root_key: None,
+        back_key: None,
+        idle_timeout: None,
+        history_max: None,
+        theme: Theme {
+            prefix: None,
+            keys: None,
+            title: None,
+            menu: None,
+            command: None,
+        },
     },
     snippet_table: {},
 }
@@ -776,6 +2363,10 @@ Config {
     menu: Menu {
         name: "root",
         display_name: None,
+        header: None,
+        footer: None,
+        sticky: false,
+        preamble: None,
         entries: {
             [
                 'm',
@@ -785,6 +2376,10 @@ Config {
                     display_name: Some(
                         "2nd menu",
                     ),
+                    header: None,
+                    footer: None,
+                    sticky: false,
+                    preamble: None,
                     entries: {
                         [
                             'f',
@@ -797,21 +2392,49 @@ Config {
                                         ),
                                     ],
                                 ),
+                                action: None,
                                 settings: [],
                                 name: None,
                                 shell: None,
                                 env_vars: [],
+                                bound_vars: [],
                                 toggle_echo_setting: false,
+                                on_error: None,
+                                finally: None,
+                                stdin: None,
+                                path_prepend: [],
+                                unset_env: [],
+                                localized_names: [],
+                                line: 7,
+                                file: None,
+                                preamble: None,
                             },
                         ),
                     },
+                    dynamic: None,
                 },
             ),
         },
+        dynamic: None,
     },
     settings: Settings {
         shell_def: None,
         echo_by_default: true,
+        on_error: None,
+        lang: None,
+        encrypt_history: None,
+        preamble: None,
+        root_key: None,
+        back_key: None,
+        idle_timeout: None,
+        history_max: None,
+        theme: Theme {
+            prefix: None,
+            keys: None,
+            title: None,
+            menu: None,
+            command: None,
+        },
     },
     snippet_table: {},
 }
@@ -830,6 +2453,10 @@ Config {
     menu: Menu {
         name: "root",
         display_name: None,
+        header: None,
+        footer: None,
+        sticky: false,
+        preamble: None,
         entries: {
             [
                 'a',
@@ -842,20 +2469,47 @@ Config {
                             ),
                         ],
                     ),
+                    action: None,
                     settings: [
                         Repeat,
                     ],
                     name: None,
                     shell: None,
                     env_vars: [],
+                    bound_vars: [],
                     toggle_echo_setting: false,
+                    on_error: None,
+                    finally: None,
+                    stdin: None,
+                    path_prepend: [],
+                    unset_env: [],
+                    localized_names: [],
+                    line: 3,
+                    file: None,
+                    preamble: None,
                 },
             ),
         },
+        dynamic: None,
     },
     settings: Settings {
         shell_def: None,
         echo_by_default: true,
+        on_error: None,
+        lang: None,
+        encrypt_history: None,
+        preamble: None,
+        root_key: None,
+        back_key: None,
+        idle_timeout: None,
+        history_max: None,
+        theme: Theme {
+            prefix: None,
+            keys: None,
+            title: None,
+            menu: None,
+            command: None,
+        },
     },
     snippet_table: {},
 }
@@ -874,6 +2528,10 @@ Config {
     menu: Menu {
         name: "root",
         display_name: None,
+        header: None,
+        footer: None,
+        sticky: false,
+        preamble: None,
         entries: {
             [
                 'a',
@@ -886,6 +2544,7 @@ Config {
                             ),
                         ],
                     ),
+                    action: None,
                     settings: [
                         Repeat,
                         IgnoreResult,
@@ -893,14 +2552,40 @@ Config {
                     name: None,
                     shell: None,
                     env_vars: [],
+                    bound_vars: [],
                     toggle_echo_setting: false,
+                    on_error: None,
+                    finally: None,
+                    stdin: None,
+                    path_prepend: [],
+                    unset_env: [],
+                    localized_names: [],
+                    line: 3,
+                    file: None,
+                    preamble: None,
                 },
             ),
         },
+        dynamic: None,
     },
     settings: Settings {
         shell_def: None,
         echo_by_default: true,
+        on_error: None,
+        lang: None,
+        encrypt_history: None,
+        preamble: None,
+        root_key: None,
+        back_key: None,
+        idle_timeout: None,
+        history_max: None,
+        theme: Theme {
+            prefix: None,
+            keys: None,
+            title: None,
+            menu: None,
+            command: None,
+        },
     },
     snippet_table: {},
 }
@@ -928,6 +2613,18 @@ Ok(
         );
     }
 
+    #[test]
+    fn test_shell_presets() -> Result<()> {
+        let fish = parse_shell_string("shell preset:fish")?;
+        assert_eq!(fish.name, "fish");
+        assert_eq!(fish.args, ["--no-config", "-c"]);
+        let conf = parse("shell preset:nu\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.shell_def.unwrap().name, "nu");
+        assert!(parse_shell_string("shell preset:tcsh").is_err());
+        assert!(parse_shell_string("shell preset:sh -x").is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_snippet_parsing() -> Result<()> {
         k9::snapshot!(
@@ -958,6 +2655,28 @@ StringExpr(
         Ok(())
     }
 
+    #[test]
+    fn test_snippet_cycle() -> Result<()> {
+        let src = r#"
+            snippet a = "x" + $b
+            snippet b = $c
+            snippet c = $b
+            menu root {
+                a: $a
+            }
+        "#;
+        let conf = parse(src)?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        let err = DotreeError::from(cmd.exec_str.resolve(&conf.snippet_table).unwrap_err());
+        let DotreeError::SnippetCycle(cycle) = &err else {
+            panic!("expected a snippet cycle, got {err:?}");
+        };
+        assert_eq!(cycle, &["b", "c", "b"]);
+        Ok(())
+    }
+
     #[test]
     fn test_echo_rule() -> Result<()> {
         k9::snapshot!(
@@ -982,4 +2701,763 @@ StringExpr(
         );
         Ok(())
     }
+
+    #[test]
+    fn test_lint() -> Result<()> {
+        let warnings: Vec<_> = lint(LINT_CONF)?
+            .into_iter()
+            .map(|w| format!("{}:{}: {}", w.line, w.col, w.message))
+            .collect();
+        k9::snapshot!(
+            warnings,
+            r#"
+[
+    "3:17: snippet unused is never used",
+    "6:24: undefined snippet: undefined",
+    "7:13: entry ab can't be reached, because a is a prefix",
+    "9:13: duplicate key c",
+    "12:14: menu lonely is never used",
+]
+"#
+        );
+        // an included file, whose definitions are used by the including one
+        let included = lint("menu git { s: \"git $flags status\" }\nsnippet unused = \"-v\"")?;
+        assert!(included.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cooldown_setting() -> Result<()> {
+        let conf = parse(WITH_COOLDOWN)?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        k9::snapshot!(
+            cmd.settings,
+            r#"
+[
+    Log,
+    Cooldown(
+        5400s,
+    ),
+]
+"#
+        );
+        k9::snapshot!(
+            parse(&WITH_COOLDOWN.replace("cooldown 1h30m", "cooldown")).is_err(),
+            "true"
+        );
+        k9::snapshot!(
+            parse(&WITH_COOLDOWN.replace("log,", "log 5,")).is_err(),
+            "true"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", r#"repeat, watch "src/**/*.rs""#))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.watch(), Some("src/**/*.rs"));
+        k9::snapshot!(
+            parse(&WITH_SETTING.replace("repeat", r#"watch "src/**/*.rs""#)).is_err(),
+            "true"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_error() -> Result<()> {
+        let conf = parse(WITH_ON_ERROR)?;
+        assert_eq!(
+            conf.settings.on_error.map(|h| h.to_string()).as_deref(),
+            Some(r#""notify-send failed""#)
+        );
+        let hooks: Vec<_> = ['a', 'b']
+            .into_iter()
+            .map(|key| match &conf.menu.entries[&vec![key]] {
+                Node::Command(cmd) => cmd.on_error.as_ref().map(|h| h.to_string()),
+                Node::Menu(_) => panic!("expected a command"),
+            })
+            .collect();
+        assert_eq!(hooks, [Some(r#""echo $DT_EXIT_CODE""#.to_string()), None]);
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            cmd.finally.as_ref().map(|h| h.to_string()).as_deref(),
+            Some(r#""rm -rf /tmp/foo""#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_default_refs() -> Result<()> {
+        let conf = parse(WITH_VAR_REFS)?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        let image = cmd.env_vars[1].value.as_ref().unwrap();
+        let vars = [("project".to_string(), "dotree".to_string())];
+        assert_eq!(
+            image.resolve_with_vars(&conf.snippet_table, &vars)?,
+            "registry.example.com/dotree:latest"
+        );
+        assert!(image.resolve(&conf.snippet_table).is_err());
+        assert!(lint(WITH_VAR_REFS)?.is_empty());
+        // defaults can't use the variables after them
+        assert!(parse(
+            &WITH_VAR_REFS
+                .replace("vars project, image", "vars image")
+                .replace(r#"":latest""#, r#"":latest", project"#)
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_var_order() -> Result<()> {
+        let vars_line = r#"vars project, image = $registry + $project + ":latest""#;
+        let conf = parse(&WITH_VAR_REFS.replace(
+            vars_line,
+            r#"vars tag, image @2 = $registry + $project, user @1, project @1"#,
+        ))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        let names: Vec<_> = cmd
+            .vars_in_prompt_order()
+            .into_iter()
+            .map(|v| v.name.as_str())
+            .collect();
+        assert_eq!(names, ["user", "project", "image", "tag"]);
+        assert!(lint(&WITH_VAR_REFS.replace(
+            vars_line,
+            r#"vars image @2 = $registry + $project, project @1"#
+        ))?
+        .is_empty());
+        // image would be queried before project
+        assert!(parse(&WITH_VAR_REFS.replace(
+            vars_line,
+            r#"vars image @1 = $registry + $project, project @2"#
+        ))
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_select() -> Result<()> {
+        let conf = parse(&WITH_VAR_REFS.replace(
+            "vars project, image",
+            r#"vars services multi ["api", "web",
+                    "worker",] sep ",", project, image"#,
+        ))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        k9::snapshot!(
+            &cmd.env_vars[0].kind,
+            r#"
+MultiSelect {
+    options: [
+        "api",
+        "web",
+        "worker",
+    ],
+    separator: ",",
+}
+"#
+        );
+        let conf = parse(&WITH_VAR_REFS.replace("vars project", r#"vars project multi ["a"]"#))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            cmd.env_vars[0].kind,
+            VarKind::MultiSelect {
+                options: vec!["a".into()],
+                separator: " ".into()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fzf_select() -> Result<()> {
+        let conf = parse(&WITH_VAR_REFS.replace(
+            "vars project, image",
+            r#"vars ns, pod from fzf "kubectl get pods -o name -n " + $ns, project, image"#,
+        ))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        let VarKind::Fzf { helper } = &cmd.env_vars[1].kind else {
+            panic!("expected a fzf variable");
+        };
+        assert_eq!(helper.symbols().collect::<Vec<_>>(), ["ns"]);
+        // the helper can only use variables that are queried before
+        assert!(parse(&WITH_VAR_REFS.replace(
+            "vars project, image",
+            r#"vars pod from fzf "kubectl get pods -n " + $ns, ns, project, image"#,
+        ))
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_select() -> Result<()> {
+        let conf = parse(&WITH_VAR_REFS.replace(
+            "vars project, image",
+            r#"vars branch from "git branch --format='%(refname:short)'", project, image"#,
+        ))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        let VarKind::Pick { helper } = &cmd.env_vars[0].kind else {
+            panic!("expected a pick variable");
+        };
+        assert_eq!(
+            helper.as_plain_str(),
+            Some("git branch --format='%(refname:short)'")
+        );
+        assert_eq!(cmd.env_vars[1].kind, VarKind::Input);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cwd_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "cwd git-root, repeat"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            cmd.settings,
+            [CommandSetting::GitRootCwd, CommandSetting::Repeat]
+        );
+        assert!(parse(&WITH_SETTING.replace("repeat", r#"cwd "/tmp""#)).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() -> Result<()> {
+        for src in [CONF, WITH_COOLDOWN, WITH_ON_ERROR, WITH_VAR_REFS] {
+            let conf = parse(src)?;
+            let json = serde_json::to_string(&conf)?;
+            let parsed: Config = serde_json::from_str(&json)?;
+            assert_eq!(crate::diff::diff(&conf, &parsed), []);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_wsl_setting() -> Result<()> {
+        let wsl = |setting: &str| -> Result<Option<Option<String>>> {
+            let conf = parse(&WITH_SETTING.replace("repeat", setting))?;
+            let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+                panic!("expected a command");
+            };
+            Ok(cmd.wsl().map(|distro| distro.map(String::from)))
+        };
+        assert_eq!(wsl("repeat")?, None);
+        assert_eq!(wsl("wsl")?, Some(None));
+        assert_eq!(
+            wsl(r#"wsl "Ubuntu-22.04""#)?,
+            Some(Some("Ubuntu-22.04".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_danger_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "danger"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert!(cmd.danger());
+        assert!(parse(&WITH_SETTING.replace("repeat", "danger 5")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_confirm_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "confirm"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.confirm(), Some(None));
+        let conf = parse(&WITH_SETTING.replace("repeat", r#"confirm "really prune?""#))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.confirm(), Some(Some("really prune?")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_os_guard() -> Result<()> {
+        let other = if cfg!(windows) { "linux" } else { "windows" };
+        let src = format!(
+            r#"
+            menu root {{
+                o: "xdg-open ." when os == "{os}"
+                o: "explorer ." when os == "{other}"
+                n: "uname" when os != "{other}"
+                w: "ver" when os != "{os}"
+            }}
+            "#,
+            os = std::env::consts::OS,
+        );
+        let conf = parse(&src)?;
+        let keys: Vec<_> = conf.menu.entries.keys().map(String::from_iter).collect();
+        assert_eq!(keys, ["o", "n"]);
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['o']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.exec_str.to_string(), r#""xdg-open .""#);
+        assert!(lint(&src)?.is_empty());
+        // both entries are shown on linux
+        let warnings = lint(&format!(
+            r#"menu root {{
+                o: "xdg-open ." when os == "linux"
+                o: "open ." when os == "unix"
+                ow: "explorer ." when os != "{other}"
+                p: "lp" when os != "macos"
+                p: "lpr" when os != "linux"
+            }}"#
+        ))?;
+        let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "duplicate key o",
+                "entry ow can't be reached, because o is a prefix",
+                "duplicate key p"
+            ]
+        );
+        assert!(parse(r#"menu root { o: "open ." when os == "mac" }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_env_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "trace_env"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.settings, vec![CommandSetting::TraceEnv]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reuse_vars_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "repeat, reuse_vars"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            cmd.settings,
+            vec![CommandSetting::Repeat, CommandSetting::ReuseVars]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sudo_setting() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "sudo"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert!(cmd.sudo());
+        assert!(parse(&WITH_SETTING.replace("repeat", "sudo \"root\"")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tmux_settings() -> Result<()> {
+        let conf = parse(&WITH_SETTING.replace("repeat", "tmux_pane"))?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.tmux(), Some(TmuxTarget::Pane));
+        let both = WITH_SETTING.replace("repeat", "tmux_window, tmux_pane");
+        assert!(parse(&both).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_and_footer() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                header "Production cluster - be careful"
+                h: "echo hi"
+                footer "ask ops before deleting anything"
+            }
+            "#,
+        )?;
+        assert_eq!(
+            conf.menu.header.as_deref(),
+            Some("Production cluster - be careful")
+        );
+        assert_eq!(
+            conf.menu.footer.as_deref(),
+            Some("ask ops before deleting anything")
+        );
+        assert_eq!(conf.menu.entries.len(), 1);
+        // a menu needs an entry besides its header and footer
+        assert!(parse(r#"menu root { header "nothing here" }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sticky_setting() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                o: ops
+            }
+
+            menu ops {
+                set sticky
+                r: "systemctl restart app"
+            }
+            "#,
+        )?;
+        assert!(!conf.menu.sticky);
+        let Some(Node::Menu(ops)) = conf.menu.entries.get(&vec!['o']) else {
+            panic!("ops isn't a menu");
+        };
+        assert!(ops.sticky);
+        assert_eq!(ops.entries.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_preamble() -> Result<()> {
+        let conf = parse(
+            r#"
+            preamble !"source ~/.config/dotree/lib.sh"!
+            menu root {
+                o: ops
+                h: "echo hi"
+            }
+
+            menu ops {
+                r: "restart_app"
+                preamble "set -x"
+            }
+            "#,
+        )?;
+        let Node::Command(hi) = &conf.menu.entries[&vec!['h']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            hi.script("echo hi"),
+            "source ~/.config/dotree/lib.sh\necho hi"
+        );
+        let Node::Menu(ops) = &conf.menu.entries[&vec!['o']] else {
+            panic!("expected a menu");
+        };
+        let Node::Command(restart) = &ops.entries[&vec!['r']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(
+            restart.script("restart_app"),
+            "source ~/.config/dotree/lib.sh\nset -x\nrestart_app"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_localized_names() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                h: { de: "hallo ausgeben", en: "print hi" } - !"echo hi"!
+                b: cmd {
+                    vars name
+                    {
+                        de: "begrüßen",
+                        fr: "saluer",
+                    } - "echo hi $name"
+                }
+            }
+            "#,
+        )?;
+        let cmd = |key| {
+            let Node::Command(cmd) = &conf.menu.entries[&vec![key]] else {
+                panic!("expected a command");
+            };
+            cmd.clone()
+        };
+        assert_eq!(cmd('h').name.as_deref(), Some("print hi"));
+        assert_eq!(
+            cmd('h').localized_names,
+            [
+                ("de".into(), "hallo ausgeben".into()),
+                ("en".into(), "print hi".into())
+            ]
+        );
+        // without an en variant, the first one is the default
+        assert_eq!(cmd('b').name.as_deref(), Some("begrüßen"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_prepend() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                t: cmd {
+                    path_prepend [
+                        "~/.local/bin",
+                        "./node_modules/.bin",
+                    ]
+                    "tsc"
+                }
+            }
+        "#,
+        )?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['t']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.path_prepend, ["~/.local/bin", "./node_modules/.bin"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_ref() -> Result<()> {
+        let src = r#"
+            snippet prod = "prod"
+            cmd deploy {
+                vars env, region = "eu"
+                "deploy $env $region"
+            }
+            menu root {
+                d: deploy
+                p: deploy(env = $prod)
+                s: deploy(env = "staging", region = "us")
+            }
+        "#;
+        let conf = parse(src)?;
+        let cmd = |key| match &conf.menu.entries[&vec![key]] {
+            Node::Command(c) => c,
+            _ => panic!("expected a command"),
+        };
+        assert_eq!(cmd('d').env_vars.len(), 2);
+        assert_eq!(cmd('p').env_vars[0].name, "region");
+        assert_eq!(cmd('p').bound_vars[0].0, "env");
+        assert!(cmd('s').env_vars.is_empty());
+        assert_eq!(cmd('s').line, 10);
+        assert!(parse(&src.replace("env = $prod", "stage = $prod")).is_err());
+        assert!(lint(src)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_order() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                z: "echo z"
+                a: sub
+                m: "echo m"
+            }
+            menu sub {
+                sort alphabetical
+                z: "echo z"
+                a: "echo a"
+            }
+        "#,
+        )?;
+        let keys = |menu: &Menu| String::from_iter(menu.entries.keys().flatten());
+        assert_eq!(keys(&conf.menu), "zam");
+        let Node::Menu(sub) = &conf.menu.entries[&vec!['a']] else {
+            panic!("expected a menu");
+        };
+        assert_eq!(keys(sub), "az");
+        Ok(())
+    }
+
+    #[test]
+    fn test_back_key_setting() -> Result<()> {
+        let conf = parse("set back_key \"h\"\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.back_key, Some('h'));
+        let conf = parse("set back_key \"esc\"\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.back_key, Some('\x1b'));
+        assert!(parse("set back_key \"hj\"\nmenu root { a: \"ls\" }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_key_setting() -> Result<()> {
+        let conf = parse("set root_key \"~\"\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.root_key, Some('~'));
+        assert!(parse("set root_key \"~~\"\nmenu root { a: \"ls\" }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_theme_setting() -> Result<()> {
+        let conf = parse(
+            "theme {\n\tprefix \"bright green bold\"\n\tcommand \"208\"\n}\nmenu root { a: \"ls\" }",
+        )?;
+        assert_eq!(
+            conf.settings.theme,
+            Theme {
+                prefix: Some("bright.green.bold".into()),
+                command: Some("208".into()),
+                ..Theme::default()
+            }
+        );
+        let conf = parse("theme { keys \"cyan.on_black\" }\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.theme.keys.as_deref(), Some("cyan.on_black"));
+        assert!(parse("theme { keys \"purple\" }\nmenu root { a: \"ls\" }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_timeout_setting() -> Result<()> {
+        let conf = parse("set idle_timeout 1min\nmenu root { a: \"ls\" }")?;
+        assert_eq!(conf.settings.idle_timeout, Some(Duration::from_secs(60)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_settings() -> Result<()> {
+        let conf = parse(
+            r#"
+            set history_max 50
+            menu root {
+                l: cmd {
+                    vars user, password nohist, nohistory
+                    "login"
+                }
+            }
+        "#,
+        )?;
+        assert_eq!(conf.settings.history_max, Some(50));
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['l']] else {
+            panic!("expected a command");
+        };
+        let nohist: Vec<_> = cmd
+            .env_vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.nohist))
+            .collect();
+        assert_eq!(
+            nohist,
+            [("user", false), ("password", true), ("nohistory", false)]
+        );
+        assert!(parse("set history_max 0\nmenu root { a: \"ls\" }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_var() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                d: cmd {
+                    vars token secret, env = "prod"
+                    "deploy"
+                }
+            }
+        "#,
+        )?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['d']] else {
+            panic!("expected a command");
+        };
+        let token = &cmd.env_vars[0];
+        assert!(token.secret && token.nohist);
+        assert!(!cmd.env_vars[1].secret);
+        let picked = r#"
+            menu root {
+                d: cmd {
+                    vars token secret from "pass ls"
+                    "deploy"
+                }
+            }
+        "#;
+        assert!(parse(picked).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unset_env() -> Result<()> {
+        let conf = parse(
+            r#"
+            menu root {
+                d: cmd {
+                    unset_env ["AWS_PROFILE", "DOCKER_HOST"]
+                    "docker ps"
+                }
+            }
+        "#,
+        )?;
+        let Node::Command(cmd) = &conf.menu.entries[&vec!['d']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(cmd.unset_env, ["AWS_PROFILE", "DOCKER_HOST"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("dotree_include_{}", std::process::id()));
+        fs::create_dir_all(dir.join("menus"))?;
+        fs::write(
+            dir.join("menus/git.dt"),
+            "include \"../snippets.dt\"\nmenu git {\n\ts: $status\n}\n",
+        )?;
+        fs::write(dir.join("snippets.dt"), "snippet status = \"git status\"\n")?;
+        let main = "include \"menus/git.dt\"\nmenu root {\n\tg: git\n}\n";
+        let conf = parse_file(main, &dir.join("main.dt"))?;
+        let Node::Menu(git) = &conf.menu.entries[&vec!['g']] else {
+            panic!("expected a menu");
+        };
+        let Node::Command(status) = &git.entries[&vec!['s']] else {
+            panic!("expected a command");
+        };
+        assert_eq!(status.exec_str.resolve(&conf.snippet_table)?, "git status");
+        assert_eq!(
+            (status.line, status.file.clone()),
+            (3, Some(fs::canonicalize(dir.join("menus/git.dt"))?))
+        );
+
+        fs::write(dir.join("snippets.dt"), "include \"menus/git.dt\"\n")?;
+        let err = parse_file(main, &dir.join("main.dt")).unwrap_err();
+        assert!(matches!(&err, DotreeError::IncludeCycle(cycle) if cycle.len() == 3));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_actions() -> Result<()> {
+        let src = r#"
+            menu root {
+                o: "docs" - open-url "https://docs.rs"
+                c: cmd {
+                    vars branch
+                    copy "git checkout " + $branch
+                }
+                p: print "hello"
+                s: "sources" - cd "~/src"
+            }
+        "#;
+        let conf = parse(src)?;
+        let action = |key| match &conf.menu.entries[&vec![key]] {
+            Node::Command(cmd) => cmd.action,
+            Node::Menu(_) => panic!("expected a command"),
+        };
+        assert_eq!(action('o'), Some(Action::OpenUrl));
+        assert_eq!(action('c'), Some(Action::Copy));
+        assert_eq!(action('p'), Some(Action::Print));
+        assert_eq!(action('s'), Some(Action::Cd));
+        assert!(lint(src)?.is_empty());
+        Ok(())
+    }
 }
@@ -1,23 +1,159 @@
+//! The runtime context, which holds what a run of a config needs to know besides the menus,
+//! like the options from the command line. It is read from the context that the current thread
+//! entered last, or else the one of the process, so it doesn't have to be passed through every
+//! function
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use once_cell::sync::OnceCell;
+use crate::parser::{Settings, ShellDef};
 
-use crate::parser::Settings;
+/// The context of the process, for all threads that didn't enter one
+static PROCESS: RwLock<Option<Arc<RuntimeContext>>> = RwLock::new(None);
 
-static LOCAL_CONF_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
-static SETTINGS: OnceCell<Settings> = OnceCell::new();
+thread_local! {
+    static ENTERED: RefCell<Option<Arc<RuntimeContext>>> = const { RefCell::new(None) };
+}
+
+/// Everything about a run of a config, that isn't part of its menus
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeContext {
+    /// The config file, which the state files are scoped to
+    pub conf_path: PathBuf,
+    /// The directory of the config, in local mode
+    pub local_conf_dir: Option<PathBuf>,
+    pub settings: Settings,
+    pub cli_options: CliOptions,
+}
+
+/// Options from the command line, that change how commands are run
+#[derive(Debug, Clone, Default)]
+pub struct CliOptions {
+    /// Values for variables by name, these won't be queried
+    pub var_presets: Vec<(String, String)>,
+    /// Overrides all shell definitions
+    pub shell: Option<ShellDef>,
+    /// Directory to run commands in, overrides the local mode directory
+    pub cwd: Option<PathBuf>,
+    /// Run commands in the root of the git repository that contains the cwd
+    pub git_root: bool,
+    /// Neither read nor write the history file
+    pub no_history: bool,
+    /// Use this file as history instead of the default one
+    pub history_file: Option<PathBuf>,
+    /// Print menus once and read selections line by line, for screen readers
+    pub accessible: bool,
+    /// Accept all confirmation prompts
+    pub yes: bool,
+    /// Commands that run longer than this are killed
+    pub timeout: Option<Duration>,
+    /// With 1, the shell command line and the working directory are printed before a command
+    /// runs, with 2 also the environment variables dotree set
+    pub verbose: u8,
+    /// Print what would be run, instead of running it
+    pub dry_run: bool,
+}
+
+/// Makes `ctx` the context of the process, which all threads use, unless they entered one
+pub fn init(ctx: RuntimeContext) {
+    *PROCESS.write().unwrap() = Some(Arc::new(ctx));
+}
 
-pub fn init(local_conf_dir: Option<PathBuf>, settings: Settings) {
-    LOCAL_CONF_DIR
-        .set(local_conf_dir)
-        .expect("initiating rt conf twice");
-    SETTINGS.set(settings).unwrap();
+/// Makes `ctx` the current context of this thread, until the returned guard is dropped, which
+/// restores the previous one. Other threads aren't affected, so they can run other configs at
+/// the same time, but threads that are spawned meanwhile don't see it
+pub fn enter(ctx: RuntimeContext) -> ContextGuard {
+    let previous = ENTERED.with(|entered| entered.borrow_mut().replace(Arc::new(ctx)));
+    ContextGuard {
+        previous,
+        _not_send: PhantomData,
+    }
 }
 
-pub fn local_conf_dir() -> Option<&'static PathBuf> {
-    LOCAL_CONF_DIR.get().expect("missing initiation").as_ref()
+#[must_use = "the context is left when the guard is dropped"]
+pub struct ContextGuard {
+    previous: Option<Arc<RuntimeContext>>,
+    /// the guard has to be dropped on the thread that entered the context
+    _not_send: PhantomData<*const ()>,
 }
 
-pub fn settings() -> &'static Settings {
-    SETTINGS.get().expect("missing initiation")
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ENTERED.with(|entered| *entered.borrow_mut() = previous);
+    }
+}
+
+/// A part of the current context. It stays valid when another context is entered meanwhile
+pub struct Part<T: 'static> {
+    ctx: Arc<RuntimeContext>,
+    get: fn(&RuntimeContext) -> &T,
+}
+
+impl<T> Deref for Part<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.get)(&self.ctx)
+    }
+}
+
+fn part<T>(get: fn(&RuntimeContext) -> &T) -> Part<T> {
+    let ctx = ENTERED
+        .with(|entered| entered.borrow().clone())
+        .or_else(|| PROCESS.read().unwrap().clone());
+    Part {
+        ctx: ctx.expect("missing initiation"),
+        get,
+    }
+}
+
+/// The config file that is used
+pub fn conf_path() -> PathBuf {
+    part(|ctx| &ctx.conf_path).clone()
+}
+
+pub fn local_conf_dir() -> Option<PathBuf> {
+    part(|ctx| &ctx.local_conf_dir).clone()
+}
+
+pub fn settings() -> Part<Settings> {
+    part(|ctx| &ctx.settings)
+}
+
+pub fn cli_options() -> Part<CliOptions> {
+    part(|ctx| &ctx.cli_options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter() {
+        let ctx = |path: &str| RuntimeContext {
+            conf_path: path.into(),
+            ..Default::default()
+        };
+        let _outer = enter(ctx("a.dt"));
+        let settings = {
+            let _inner = enter(ctx("b.dt"));
+            assert_eq!(conf_path(), PathBuf::from("b.dt"));
+            settings()
+        };
+        assert_eq!(conf_path(), PathBuf::from("a.dt"));
+        // parts that were taken before stay valid
+        assert_eq!(settings.history_max, None);
+        // other threads have their own context
+        std::thread::spawn(move || {
+            let _ctx = enter(ctx("c.dt"));
+            assert_eq!(conf_path(), PathBuf::from("c.dt"));
+        })
+        .join()
+        .unwrap();
+        assert_eq!(conf_path(), PathBuf::from("a.dt"));
+    }
 }
@@ -0,0 +1,106 @@
+//! The terminal backend of the menu, based on crossterm. Unlike console, it enables the
+//! virtual terminal processing of Windows consoles, and reads keys as key events, so Esc,
+//! Backspace and the control keys work the same in legacy Windows consoles as on unix.
+//! While a key is read, bracketed paste is enabled, so pasted text can be told apart from
+//! typed keys, and doesn't select random entries
+use std::io::{self, stdout};
+use std::time::{Duration, Instant};
+
+use console::Key;
+use crossterm::cursor;
+use crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEventKind, KeyModifiers,
+};
+use crossterm::execute;
+use crossterm::terminal::{self, ClearType};
+
+/// Enables escape sequences on Windows, where they are off by default in legacy consoles.
+/// Colors are turned off, if that isn't possible
+pub fn init() {
+    #[cfg(windows)]
+    if !crossterm::ansi_support::supports_ansi() {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+/// Waits for a key press. Ctrl+c is returned as interrupted error, like console does it
+pub fn read_key() -> io::Result<Key> {
+    Ok(read_key_within(None)?.expect("there is no timeout"))
+}
+
+/// Like `read_key`, but returns None if no key was pressed within the timeout
+pub fn read_key_within(timeout: Option<Duration>) -> io::Result<Option<Key>> {
+    terminal::enable_raw_mode()?;
+    // legacy Windows consoles don't support it, there pasted text still arrives as keys
+    let _ = execute!(stdout(), EnableBracketedPaste);
+    let key = next_key(timeout.map(|t| Instant::now() + t));
+    // commands that are run afterwards, might not expect the markers around pasted text
+    let _ = execute!(stdout(), DisableBracketedPaste);
+    terminal::disable_raw_mode()?;
+    key
+}
+
+fn next_key(deadline: Option<Instant>) -> io::Result<Option<Key>> {
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(remaining)? {
+                return Ok(None);
+            }
+        }
+        // pasted text is ignored, the prompts read it with rustyline instead
+        let Event::Key(event) = event::read()? else {
+            continue;
+        };
+        // Windows also reports when a key is released
+        if event.kind == KeyEventKind::Release {
+            continue;
+        }
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        return Ok(Some(match event.code {
+            KeyCode::Char('c') if ctrl => return Err(io::ErrorKind::Interrupted.into()),
+            // the control characters, which is how console reports ctrl + letter
+            KeyCode::Char(c) if ctrl && c.is_ascii_alphabetic() => {
+                Key::Char((c.to_ascii_lowercase() as u8 - b'a' + 1) as char)
+            }
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Esc => Key::Escape,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::BackTab => Key::BackTab,
+            KeyCode::Up => Key::ArrowUp,
+            KeyCode::Down => Key::ArrowDown,
+            KeyCode::Left => Key::ArrowLeft,
+            KeyCode::Right => Key::ArrowRight,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Delete => Key::Del,
+            KeyCode::Insert => Key::Insert,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            _ => Key::Unknown,
+        }));
+    }
+}
+
+/// Clears the last n lines of stdout, and moves the cursor to the start of the first one
+pub fn clear_last_lines(n: usize) -> io::Result<()> {
+    if n == 0 {
+        return Ok(());
+    }
+    execute!(
+        stdout(),
+        cursor::MoveToPreviousLine(n.try_into().unwrap_or(u16::MAX)),
+        terminal::Clear(ClearType::FromCursorDown)
+    )
+}
+
+pub fn hide_cursor() -> io::Result<()> {
+    execute!(stdout(), cursor::Hide)
+}
+
+pub fn show_cursor() -> io::Result<()> {
+    execute!(stdout(), cursor::Show)
+}
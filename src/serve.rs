@@ -0,0 +1,349 @@
+//! A small JSON-RPC 2.0 server that exposes the parsed tree and allows to execute commands
+//! over a unix socket. Requests and responses are separated by newlines.
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::{env, fs, io, thread};
+
+use anyhow::{bail, Context, Result};
+use hashbrown::HashMap;
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::exec;
+use crate::parser::{Command, Config, Menu, Node, SnippetTable, VarKind};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const EXECUTION_ERROR: i64 = -32000;
+
+type RpcResult = std::result::Result<Value, (i64, String)>;
+
+pub fn default_socket_path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("dotree.sock")
+}
+
+/// Serves `config` on `socket_path`. If `popup_cmd` is given, the `popup` method spawns it,
+/// which is meant to open a terminal window that runs dotree, which gets the config with the
+/// `config` method.
+pub fn serve(socket_path: &Path, config: &Config, popup_cmd: Option<&[String]>) -> Result<()> {
+    let root_node = &Node::Menu(config.menu.clone());
+    // the socket of an earlier server is replaced, but nothing else
+    match fs::symlink_metadata(socket_path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            fs::remove_file(socket_path).context("removing old socket")?
+        }
+        Ok(_) => bail!(
+            "{} exists and isn't a socket, so it isn't replaced",
+            socket_path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("checking for an old socket"),
+    }
+    let listener = UnixListener::bind(socket_path)
+        .context(format!("binding socket {}", socket_path.display()))?;
+    eprintln!("Listening on {}", socket_path.display());
+
+    thread::scope(|s| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    s.spawn(move || {
+                        if let Err(e) = handle_connection(stream, root_node, config, popup_cmd) {
+                            eprintln!("Error while handling connection:\n{e:?}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Couldn't accept connection:\n{e:?}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(
+    stream: UnixStream,
+    root_node: &Node,
+    config: &Config,
+    popup_cmd: Option<&[String]>,
+) -> Result<()> {
+    let mut writer = stream.try_clone().context("cloning stream")?;
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("reading request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        debug!("request: {line}");
+        let response = handle_request(&line, root_node, config, popup_cmd);
+        writeln!(writer, "{response}").context("writing response")?;
+    }
+    Ok(())
+}
+
+fn handle_request(
+    line: &str,
+    root_node: &Node,
+    config: &Config,
+    popup_cmd: Option<&[String]>,
+) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => return error_response(Value::Null, PARSE_ERROR, e.to_string()),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some("tree") => Ok(node_to_json(root_node)),
+        Some("execute") => execute(&params, root_node, &config.snippet_table),
+        Some("popup") => popup(popup_cmd),
+        #[cfg(feature = "serde")]
+        Some("config") => served_config(config),
+        Some(other) => Err((METHOD_NOT_FOUND, format!("Unknown method: {other}"))),
+        None => Err((INVALID_REQUEST, "Missing method".to_string())),
+    };
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err((code, msg)) => error_response(id, code, msg),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn execute(params: &Value, root_node: &Node, snippet_table: &SnippetTable) -> RpcResult {
+    let path = params
+        .get("path")
+        .and_then(Value::as_str)
+        .ok_or((INVALID_PARAMS, "Expected a string param: path".to_string()))?;
+    let mut vars = HashMap::new();
+    if let Some(var_obj) = params.get("vars") {
+        let var_obj = var_obj
+            .as_object()
+            .ok_or((INVALID_PARAMS, "vars must be an object".to_string()))?;
+        for (name, val) in var_obj {
+            let val = val
+                .as_str()
+                .ok_or((INVALID_PARAMS, format!("value of {name} must be a string")))?;
+            vars.insert(name.clone(), val.to_string());
+        }
+    }
+
+    let cmd = exec::find_command(root_node, path)
+        .ok_or((INVALID_PARAMS, format!("{path} doesn't lead to a command")))?;
+    let output = exec::run_captured(cmd, path, &vars, snippet_table)
+        .map_err(|e| (EXECUTION_ERROR, format!("{e:?}")))?;
+    Ok(json!({
+        "status": output.status.code(),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    }))
+}
+
+fn popup(popup_cmd: Option<&[String]>) -> RpcResult {
+    let (prog, args) = popup_cmd.and_then(|cmd| cmd.split_first()).ok_or((
+        EXECUTION_ERROR,
+        "No popup command configured, start the server with --terminal".to_string(),
+    ))?;
+    let mut child = process::Command::new(prog)
+        .args(args)
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| (EXECUTION_ERROR, format!("Couldn't spawn {prog}: {e}")))?;
+    let pid = child.id();
+    // so it doesn't stay behind as a zombie, once the window is closed
+    thread::spawn(move || child.wait());
+    Ok(json!({"pid": pid}))
+}
+
+/// The parsed config, and the path and local directory it was loaded with, so the popup
+/// doesn't have to parse it again
+#[cfg(feature = "serde")]
+fn served_config(config: &Config) -> RpcResult {
+    let config = serde_json::to_value(config).map_err(|e| (EXECUTION_ERROR, e.to_string()))?;
+    Ok(json!({
+        "conf_path": crate::rt_conf::conf_path(),
+        "local_conf_dir": crate::rt_conf::local_conf_dir(),
+        "config": config,
+    }))
+}
+
+/// Sends a single request to the server at `socket_path` and returns the result
+pub fn request(socket_path: &Path, method: &str, params: Value) -> Result<Value> {
+    let mut stream = UnixStream::connect(socket_path).context(format!(
+        "connecting to {}. Is `dt serve` running?",
+        socket_path.display()
+    ))?;
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    writeln!(stream, "{request}").context("sending request")?;
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("reading response")?;
+    let mut response: Value = serde_json::from_str(&line).context("parsing response")?;
+    if let Some(err) = response.get("error") {
+        bail!("Server returned an error: {}", err["message"]);
+    }
+    Ok(response["result"].take())
+}
+
+fn node_to_json(node: &Node) -> Value {
+    match node {
+        Node::Menu(m) => menu_to_json(m),
+        Node::Command(c) => command_to_json(c),
+    }
+}
+
+fn menu_to_json(menu: &Menu) -> Value {
+    let entries: Vec<_> = menu
+        .entries
+        .iter()
+        .map(|(keys, node)| json!({"keys": String::from_iter(keys), "node": node_to_json(node)}))
+        .collect();
+    json!({
+        "type": "menu",
+        "name": menu.name,
+        "display_name": menu.display_name,
+        "entries": entries,
+    })
+}
+
+fn command_to_json(cmd: &Command) -> Value {
+    let vars: Vec<_> = cmd
+        .env_vars
+        .iter()
+        .map(|v| {
+            // defaults that depend on other variables are given as written
+            let default = v
+                .value
+                .as_ref()
+                .map(|d| d.as_plain_str().map_or_else(|| d.to_string(), String::from));
+            let mut var = json!({"name": v.name, "default": default});
+            if let VarKind::MultiSelect { options, separator } = &v.kind {
+                var["options"] = json!(options);
+                var["separator"] = json!(separator);
+            }
+            if let Some(helper) = v.kind.helper() {
+                var["helper"] = json!(helper.to_string());
+                var["fuzzy"] = json!(matches!(v.kind, VarKind::Fzf { .. }));
+            }
+            var
+        })
+        .collect();
+    json!({
+        "type": "command",
+        "name": cmd.localized_name(),
+        "exec": cmd.exec_str.to_string(),
+        "vars": vars,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const CONF: &str = r#"
+        menu root {
+            a: "echo a"
+            c: cmd {
+                vars foo="bar"
+                "named" - "echo $foo"
+            }
+        }
+    "#;
+
+    #[test]
+    fn test_tree_request() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        let response = handle_request(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "tree"}"#,
+            &Node::Menu(conf.menu.clone()),
+            &conf,
+            None,
+        );
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": 1, "result": {
+                "type": "menu",
+                "name": "root",
+                "display_name": null,
+                "entries": [
+                    {"keys": "a", "node": {
+                        "type": "command", "name": null, "exec": "\"echo a\"", "vars": []
+                    }},
+                    {"keys": "c", "node": {
+                        "type": "command",
+                        "name": "named",
+                        "exec": "\"echo $foo\"",
+                        "vars": [{"name": "foo", "default": "bar"}]
+                    }},
+                ]
+            }})
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config_request() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        let _ctx = crate::rt_conf::enter(crate::rt_conf::RuntimeContext {
+            conf_path: "/conf/dotree.dt".into(),
+            ..Default::default()
+        });
+        let mut response = handle_request(
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "config"}"#,
+            &Node::Menu(conf.menu.clone()),
+            &conf,
+            None,
+        );
+        let result = &mut response["result"];
+        assert_eq!(result["conf_path"], "/conf/dotree.dt");
+        assert_eq!(result["local_conf_dir"], Value::Null);
+        let served: Config = serde_json::from_value(result["config"].take())?;
+        assert_eq!(served.menu.entries.len(), 2);
+        assert_eq!(served.snippet_table, conf.snippet_table);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_socket_replaced() -> Result<()> {
+        let path = env::temp_dir().join(format!("dotree-test-{}.sock", std::process::id()));
+        fs::write(&path, "not a socket")?;
+        let res = serve(&path, &parser::parse(CONF)?, None);
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .ends_with("isn't a socket, so it isn't replaced"));
+        assert_eq!(fs::read_to_string(&path)?, "not a socket");
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_method() -> Result<()> {
+        let conf = parser::parse(CONF)?;
+        let response = handle_request(
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "foo"}"#,
+            &Node::Menu(conf.menu.clone()),
+            &conf,
+            None,
+        );
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": 2, "error": {
+                "code": METHOD_NOT_FOUND,
+                "message": "Unknown method: foo"
+            }})
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! An animated status line, that shows that a command is still running
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use console::{measure_text_width, style, Term};
+
+use crate::rt_conf;
+
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const FRAME_DURATION: Duration = Duration::from_millis(100);
+
+/// Shows `label` and the elapsed time in the last line of the terminal, until it is dropped,
+/// which removes the line again
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    lines: Option<Sender<String>>,
+    printed: &'static AtomicUsize,
+}
+
+/// Prints lines of output above the spinner, or directly, if there is none
+#[derive(Clone)]
+pub struct LinePrinter {
+    lines: Option<Sender<String>>,
+    printed: &'static AtomicUsize,
+}
+
+impl LinePrinter {
+    pub fn println(&self, line: String) {
+        match &self.lines {
+            // lines that were sent before the spinner is dropped, are still printed, later
+            // ones are lost
+            Some(lines) => {
+                let _ = lines.send(line);
+            }
+            None => {
+                println!("{line}");
+                self.printed
+                    .fetch_add(rows(&line, Term::stdout().size().1), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The number of rows `line` takes up in a terminal that is `width` columns wide
+fn rows(line: &str, width: u16) -> usize {
+    let width = usize::from(width.max(1));
+    measure_text_width(line).saturating_sub(1) / width + 1
+}
+
+impl Spinner {
+    /// Starts the spinner, unless the output isn't a terminal, or accessible mode is on. The
+    /// rows of the lines that are printed with its `printer` are added to `printed`
+    pub fn start(term: &Term, label: String, printed: &'static AtomicUsize) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx): (_, Receiver<String>) = mpsc::channel();
+        let handle = (term.is_term() && !rt_conf::cli_options().accessible).then(|| {
+            let term = term.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                // the lines are printed by this thread, so they can't interleave with the
+                // frames
+                let print_lines = || {
+                    for line in rx.try_iter() {
+                        if term
+                            .clear_line()
+                            .and_then(|_| term.write_line(&line))
+                            .is_ok()
+                        {
+                            printed.fetch_add(rows(&line, term.size().1), Ordering::Relaxed);
+                        }
+                    }
+                };
+                let start = Instant::now();
+                for frame in FRAMES.iter().cycle() {
+                    print_lines();
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let elapsed = Duration::from_secs(start.elapsed().as_secs());
+                    let line = format!(
+                        "{frame} {label} {}",
+                        style(humantime::format_duration(elapsed)).dim()
+                    );
+                    let _ = term.clear_line().and_then(|_| term.write_str(&line));
+                    thread::sleep(FRAME_DURATION);
+                }
+                // the ones that were sent while it stopped
+                print_lines();
+                let _ = term.clear_line();
+            })
+        });
+        let lines = handle.is_some().then_some(tx);
+        Spinner {
+            stop,
+            handle,
+            lines,
+            printed,
+        }
+    }
+
+    pub fn printer(&self) -> LinePrinter {
+        LinePrinter {
+            lines: self.lines.clone(),
+            printed: self.printed,
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(rows("", 80), 1);
+        assert_eq!(rows(&"x".repeat(80), 80), 1);
+        assert_eq!(rows(&"x".repeat(81), 80), 2);
+        // escape sequences take up no space
+        assert_eq!(rows(&style("x".repeat(10)).red().to_string(), 10), 1);
+    }
+}
@@ -0,0 +1,270 @@
+//! State that dotree keeps between runs, apart from the config
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use fs2::FileExt;
+use serde_json::{json, Map, Value};
+
+use crate::crypt;
+use crate::i18n::tr;
+use crate::rt_conf;
+
+/// The directory in which dotree stores its state, like the history
+pub fn state_dir() -> Result<PathBuf> {
+    Ok(if let Some(sd) = dirs::state_dir() {
+        sd
+    } else {
+        dirs::data_local_dir().ok_or(anyhow!("couldn't get local dir"))?
+    })
+}
+
+/// The path of the state file `name`. In local mode, every project gets its own file, which
+/// is named after the project's path, and placed in the directory `<name>_projects`
+pub fn scoped_path(name: &str) -> Result<PathBuf> {
+    let dir = state_dir()?;
+    Ok(if let Some(conf_dir) = rt_conf::local_conf_dir() {
+        let project = conf_dir.to_string_lossy().replace(['/', '\\', ':'], "%");
+        dir.join(format!("{name}_projects")).join(project)
+    } else {
+        dir.join(name)
+    })
+}
+
+/// Appends a line about the command with `keys` to the audit log, e.g. that a prompt was
+/// skipped with --yes
+pub fn audit(keys: &str, note: &str) -> Result<()> {
+    let path = state_dir()?.join("dt_audit.log");
+    fs::create_dir_all(path.parent().context("Getting state file dir")?)
+        .context("creating state file dir")?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("opening audit log")?;
+    let conf_path = rt_conf::conf_path();
+    let conf_path = fs::canonicalize(&conf_path).unwrap_or(conf_path);
+    writeln!(
+        file,
+        "{} {} {keys}: {note}",
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        conf_path.display()
+    )
+    .context("writing audit log")
+}
+
+/// A command that didn't exit successfully, and the values its variables had
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedRun {
+    pub keys: String,
+    pub vars: Vec<(String, String)>,
+}
+
+fn last_failed_path() -> Result<PathBuf> {
+    scoped_path("dt_last_failed")
+}
+
+pub fn load_last_failed() -> Result<Option<FailedRun>> {
+    let Some(src) = crypt::read(&last_failed_path()?)? else {
+        return Ok(None);
+    };
+    let value: Value = serde_json::from_str(&src).context("parsing last failed run")?;
+    let keys = value["keys"]
+        .as_str()
+        .ok_or(anyhow!("last failed run is missing the keys"))?;
+    let mut vars = vec![];
+    for var in value["vars"].as_array().into_iter().flatten() {
+        match (var[0].as_str(), var[1].as_str()) {
+            (Some(name), Some(val)) => vars.push((name.to_string(), val.to_string())),
+            _ => return Err(anyhow!("invalid variable in last failed run: {var}")),
+        }
+    }
+    Ok(Some(FailedRun {
+        keys: keys.to_string(),
+        vars,
+    }))
+}
+
+/// Remembers the run if it failed. If it succeeded, and it was a retry of the last failed
+/// run, that one is forgotten. Nothing is stored if the history is disabled
+pub fn record_result(keys: &str, vars: &[(String, String)], success: bool) -> Result<()> {
+    if rt_conf::cli_options().no_history {
+        return Ok(());
+    }
+    let path = last_failed_path()?;
+    if success {
+        if load_last_failed()?.is_some_and(|run| run.keys == keys) {
+            fs::remove_file(path).context("removing last failed run")?;
+        }
+        return Ok(());
+    }
+    let value = json!({"keys": keys, "vars": vars});
+    crypt::write(&path, &value.to_string()).context("storing last failed run")
+}
+
+/// A state file with a JSON object, which maps the keys of commands to something about them
+struct KeyedFile {
+    name: &'static str,
+    /// what the file contains, for error messages
+    what: &'static str,
+    /// whether the file is written encrypted, if the `encrypt_history` setting is set
+    encrypted: bool,
+}
+
+const LAST_SUCCESSES: KeyedFile = KeyedFile {
+    name: "dt_last_success",
+    what: "last successful runs",
+    encrypted: false,
+};
+
+const LAST_RUNS: KeyedFile = KeyedFile {
+    name: "dt_last_run",
+    what: "last runs",
+    encrypted: false,
+};
+
+const LAST_VALUES: KeyedFile = KeyedFile {
+    name: "dt_last_values",
+    what: "last values",
+    encrypted: true,
+};
+
+/// The content of the file, which is empty if it doesn't exist yet
+fn load_map(file: &KeyedFile) -> Result<Map<String, Value>> {
+    // files that aren't encrypted are read as they are
+    let Some(src) = crypt::read(&scoped_path(file.name)?)? else {
+        return Ok(Default::default());
+    };
+    serde_json::from_str(&src).context(format!("parsing {}", file.what))
+}
+
+fn store_map(file: &KeyedFile, map: Map<String, Value>) -> Result<()> {
+    let path = scoped_path(file.name)?;
+    let src = Value::Object(map).to_string();
+    if file.encrypted {
+        return crypt::write(&path, &src).context(format!("storing {}", file.what));
+    }
+    fs::create_dir_all(path.parent().context("Getting state file dir")?)
+        .context("creating state file dir")?;
+    fs::write(path, src).context(format!("storing {}", file.what))
+}
+
+/// The time that is stored in the file for the command with `keys`
+fn load_time(file: &KeyedFile, keys: &str) -> Result<Option<SystemTime>> {
+    Ok(load_map(file)?
+        .get(keys)
+        .and_then(Value::as_u64)
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+}
+
+/// Stores the current time in the file, for the command with `keys`
+fn store_time(file: &KeyedFile, keys: &str) -> Result<()> {
+    let mut times = load_map(file)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    times.insert(keys.to_string(), now.into());
+    store_map(file, times)
+}
+
+/// When the command with `keys` last ran successfully, as far as it was recorded
+pub fn last_success(keys: &str) -> Result<Option<SystemTime>> {
+    load_time(&LAST_SUCCESSES, keys)
+}
+
+/// Records that the command with `keys` just ran successfully
+pub fn record_success(keys: &str) -> Result<()> {
+    store_time(&LAST_SUCCESSES, keys)
+}
+
+/// When the command with `keys` was last run, no matter how it exited
+pub fn last_run(keys: &str) -> Result<Option<SystemTime>> {
+    load_time(&LAST_RUNS, keys)
+}
+
+/// Records that the command with `keys` was just run
+pub fn record_run(keys: &str) -> Result<()> {
+    store_time(&LAST_RUNS, keys)
+}
+
+/// The value the variable `name` of the command with `keys` had when it last ran
+pub fn last_value(keys: &str, name: &str) -> Result<Option<String>> {
+    Ok(load_map(&LAST_VALUES)?
+        .get(keys)
+        .and_then(|vals| vals.get(name))
+        .and_then(Value::as_str)
+        .map(String::from))
+}
+
+/// Remembers the values of the variables of the command with `keys`, unless the history is
+/// disabled
+pub fn record_values(keys: &str, vars: &[(String, String)]) -> Result<()> {
+    if rt_conf::cli_options().no_history || vars.is_empty() {
+        return Ok(());
+    }
+    let mut all_vals = load_map(&LAST_VALUES)?;
+    let vals = all_vals
+        .entry(keys)
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or(anyhow!("invalid last values of {keys}"))?;
+    for (name, val) in vars {
+        vals.insert(name.clone(), val.clone().into());
+    }
+    store_map(&LAST_VALUES, all_vals)
+}
+
+fn pins_path() -> Result<PathBuf> {
+    scoped_path("dt_pins")
+}
+
+/// The keys of the pinned commands, in the order they were pinned
+pub fn pinned() -> Result<Vec<String>> {
+    let path = pins_path()?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let src = fs::read_to_string(path).context("reading pins")?;
+    serde_json::from_str(&src).context("parsing pins")
+}
+
+/// Pins the command with `keys`, or unpins it if it is pinned already
+pub fn toggle_pin(keys: &str) -> Result<()> {
+    let mut pins = pinned()?;
+    if let Some(i) = pins.iter().position(|p| p == keys) {
+        pins.remove(i);
+    } else {
+        pins.push(keys.to_string());
+    }
+    let path = pins_path()?;
+    fs::create_dir_all(path.parent().context("Getting state file dir")?)
+        .context("creating state file dir")?;
+    fs::write(path, json!(pins).to_string()).context("storing pins")
+}
+
+/// Takes the lock of the command with `keys`, which is held until the returned file is
+/// dropped, or the process exits. Fails if another process holds it
+pub fn lock_command(keys: &str) -> Result<File> {
+    let dir = scoped_path("dt_locks")?;
+    fs::create_dir_all(&dir).context("creating lock dir")?;
+    // keys can contain any character, so everything but alphanumerics is escaped
+    let name: String = keys
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_string()
+            } else {
+                format!("%{:x}", c as u32)
+            }
+        })
+        .collect();
+    let path = dir.join(format!("{name}.lock"));
+    let file = File::create(&path).context(format!("creating {}", path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        bail!(tr(
+            "already_running",
+            &[("keys", &keys), ("path", &path.display())]
+        ));
+    }
+    Ok(file)
+}
@@ -0,0 +1,90 @@
+//! The profile that `--timings` prints, of how long loading the config and running commands
+//! took. Phases are only measured once it was enabled
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The phases that were measured since the last report
+static PHASES: Lazy<Mutex<Vec<(&'static str, Duration)>>> = Lazy::new(Default::default);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs f, and records how long it took as the phase, if timings are enabled
+pub fn measure<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    if !enabled() {
+        return f();
+    }
+    let start = Instant::now();
+    let res = f();
+    PHASES.lock().unwrap().push((phase, start.elapsed()));
+    res
+}
+
+/// Prints the phases that were measured since the last report to stderr, and their sum, if
+/// there is more than one. Phases that occurred multiple times, are summed up
+pub fn report() {
+    let mut phases = PHASES.lock().unwrap();
+    let mut summed: Vec<(&str, Duration, usize)> = vec![];
+    for (phase, duration) in phases.drain(..) {
+        match summed.iter_mut().find(|(p, _, _)| *p == phase) {
+            Some((_, total, n)) => {
+                *total += duration;
+                *n += 1;
+            }
+            None => summed.push((phase, duration, 1)),
+        }
+    }
+    for line in format_phases(&summed) {
+        eprintln!("{line}");
+    }
+}
+
+fn format_phases(phases: &[(&str, Duration, usize)]) -> Vec<String> {
+    let width = phases.iter().map(|(p, _, _)| p.len()).max().unwrap_or(0);
+    let mut lines: Vec<_> = phases
+        .iter()
+        .map(|(phase, duration, n)| {
+            let times = if *n > 1 {
+                format!(" ({n}×)")
+            } else {
+                String::new()
+            };
+            format!("timing: {phase:width$}  {duration:>10.2?}{times}")
+        })
+        .collect();
+    if phases.len() > 1 {
+        let total: Duration = phases.iter().map(|(_, d, _)| *d).sum();
+        lines.push(format!("timing: {:width$}  {total:>10.2?}", "total"));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_phases() {
+        let phases = [
+            ("parsing", Duration::from_micros(1500), 1),
+            ("menus", Duration::from_millis(2), 3),
+        ];
+        assert_eq!(
+            format_phases(&phases),
+            [
+                "timing: parsing      1.50ms",
+                "timing: menus        2.00ms (3×)",
+                "timing: total        3.50ms",
+            ]
+        );
+    }
+}
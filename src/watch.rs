@@ -0,0 +1,123 @@
+//! Re-running commands whenever files change, like `entr`
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use console::{style, Term};
+use globset::Glob;
+use notify_debouncer_mini::new_debouncer;
+use notify_debouncer_mini::notify::RecursiveMode;
+
+use crate::core;
+use crate::i18n::tr;
+
+enum Event {
+    Changed,
+    KeyPressed,
+}
+
+/// Calls `run` whenever a file below the current directory that matches `pattern` changes,
+/// until a key is pressed. A status line with the result of the last run is shown below the
+/// current output
+pub fn watch(
+    pattern: &str,
+    term: &Term,
+    mut run: impl FnMut() -> Result<ExitStatus>,
+) -> Result<()> {
+    let matcher = Glob::new(pattern)
+        .context(format!("invalid watch pattern: {pattern}"))?
+        .compile_matcher();
+    let cwd = std::env::current_dir().context("getting cwd")?;
+    let (tx, rx) = mpsc::channel();
+
+    let change_tx = tx.clone();
+    let mut debouncer = new_debouncer(Duration::from_millis(200), move |res| {
+        let Ok(events): notify_debouncer_mini::DebounceEventResult = res else {
+            return;
+        };
+        let matches = events.iter().any(|e| {
+            let path = e.path.strip_prefix(&cwd).unwrap_or(&e.path);
+            matcher.is_match(path)
+        });
+        if matches {
+            // the receiver only disappears when watching stops
+            let _ = change_tx.send(Event::Changed);
+        }
+    })
+    .context("creating file watcher")?;
+    let base = base_dir(pattern);
+    debouncer
+        .watcher()
+        .watch(&base, RecursiveMode::Recursive)
+        .context(format!("watching {}", base.display()))?;
+
+    let key_term = term.clone();
+    thread::spawn(move || {
+        // ctrl+c also ends up here, as an interrupted error
+        let _ = key_term.read_key();
+        let _ = tx.send(Event::KeyPressed);
+    });
+
+    term.write_line(
+        &style(tr("watching", &[("pattern", &pattern)]))
+            .dim()
+            .to_string(),
+    )?;
+    loop {
+        match rx.recv()? {
+            Event::Changed => {
+                // the output of the run goes where the status line was
+                core::clear_last_lines(1)?;
+                let status = run()?;
+                let result = if status.success() {
+                    style(tr("run_ok", &[])).green()
+                } else {
+                    style(tr("run_failed", &[("status", &status)])).red()
+                };
+                term.write_line(&format!(
+                    "{} {result} {}",
+                    style(tr("watching_last_run", &[("pattern", &pattern)])).dim(),
+                    style(tr(
+                        "watching_at",
+                        &[(
+                            "time",
+                            &humantime::format_rfc3339_seconds(SystemTime::now())
+                        )]
+                    ))
+                    .dim()
+                ))?;
+            }
+            Event::KeyPressed => {
+                core::clear_last_lines(1)?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The directory that contains all files the pattern can match, which is the part before
+/// the first component with a wildcard
+fn base_dir(pattern: &str) -> PathBuf {
+    let mut res = PathBuf::new();
+    let path = Path::new(pattern);
+    let n_components = path.components().count();
+    for (i, c) in path.components().enumerate() {
+        let is_glob = c
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '[', '{']);
+        // the last component of a pattern without wildcards is the file itself
+        if is_glob || i + 1 == n_components {
+            break;
+        }
+        res.push(c);
+    }
+    if res.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        res
+    }
+}
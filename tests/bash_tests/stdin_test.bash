@@ -0,0 +1 @@
+$DT -c stdin_test.dt s
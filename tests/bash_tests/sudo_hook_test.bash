@@ -0,0 +1,3 @@
+# only the command runs as root, the hook still gets DT_EXIT_CODE and DT_COMMAND
+PATH="$PWD/fakebin:$PATH" $DT -c sudo_hook_test.dt s
+echo "exit code: $?"
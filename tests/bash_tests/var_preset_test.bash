@@ -0,0 +1,3 @@
+$DT -c arg_test.dt f --var b=beta alpha gamma
+
+printf "\n" | $DT -c arg_test.dt f --var c=gamma --var a=alpha